@@ -0,0 +1,71 @@
+use crate::{Bystander, EntityMoved, Map, Position, RunState, Viewshed};
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+
+pub struct BystanderAI {}
+
+impl<'a> System<'a> for BystanderAI {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        WriteExpect<'a, Map>,
+        ReadExpect<'a, RunState>,
+        Entities<'a>,
+        WriteStorage<'a, Viewshed>,
+        ReadStorage<'a, Bystander>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, EntityMoved>,
+        WriteExpect<'a, RandomNumberGenerator>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut map,
+            run_state,
+            entities,
+            mut viewsheds,
+            bystanders,
+            mut positions,
+            mut entity_moved,
+            mut rng,
+        ) = data;
+
+        if *run_state != RunState::MonsterTurn {
+            return;
+        }
+
+        for (entity, _bystander, pos) in (&entities, &bystanders, &mut positions).join() {
+            let (dx, dy) = match rng.roll_dice(1, 5) {
+                1 => (-1, 0),
+                2 => (1, 0),
+                3 => (0, -1),
+                4 => (0, 1),
+                _ => (0, 0),
+            };
+
+            let x = pos.x + dx;
+            let y = pos.y + dy;
+            if (dx == 0 && dy == 0) || x < 1 || x > map.width - 2 || y < 1 || y > map.height - 2 {
+                continue;
+            }
+
+            let destination_idx = map.xy_idx(x, y);
+            if map.blocked[destination_idx] {
+                continue;
+            }
+
+            let idx = map.xy_idx(pos.x, pos.y);
+            map.blocked[idx] = false;
+            pos.x = x;
+            pos.y = y;
+            map.blocked[destination_idx] = true;
+
+            entity_moved
+                .insert(entity, EntityMoved {})
+                .expect("unable to insert movement for bystander");
+
+            if let Some(viewshed) = viewsheds.get_mut(entity) {
+                viewshed.dirty = true;
+            }
+        }
+    }
+}
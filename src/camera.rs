@@ -0,0 +1,189 @@
+use crate::map::tile_glyph;
+use crate::settings;
+use crate::tileset::{self, Tileset};
+use crate::{Hidden, Map, Name, Position, Renderable};
+use rltk::{Point, Rltk, RGB};
+use specs::prelude::*;
+
+pub const VIEW_WIDTH: i32 = 80;
+pub const VIEW_HEIGHT: i32 = 43;
+
+pub struct ScreenBounds {
+    pub min_x: i32,
+    pub max_x: i32,
+    pub min_y: i32,
+    pub max_y: i32,
+}
+
+impl ScreenBounds {
+    pub fn to_world(&self, screen: Point) -> Point {
+        Point::new(screen.x + self.min_x, screen.y + self.min_y)
+    }
+
+    pub fn to_screen(&self, world: Point) -> Point {
+        Point::new(world.x - self.min_x, world.y - self.min_y)
+    }
+
+    pub fn contains_screen(&self, screen: Point) -> bool {
+        screen.x >= 0 && screen.x < VIEW_WIDTH && screen.y >= 0 && screen.y < VIEW_HEIGHT
+    }
+}
+
+pub fn get_screen_bounds(ecs: &World) -> ScreenBounds {
+    let player_pos = ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+    bounds_centered_on(&map, *player_pos)
+}
+
+fn bounds_centered_on(map: &Map, center: Point) -> ScreenBounds {
+    let min_x = clamp_axis(center.x - VIEW_WIDTH / 2, map.width, VIEW_WIDTH);
+    let min_y = clamp_axis(center.y - VIEW_HEIGHT / 2, map.height, VIEW_HEIGHT);
+
+    ScreenBounds {
+        min_x,
+        max_x: min_x + VIEW_WIDTH,
+        min_y,
+        max_y: min_y + VIEW_HEIGHT,
+    }
+}
+
+fn clamp_axis(min: i32, map_size: i32, view_size: i32) -> i32 {
+    if map_size <= view_size {
+        0
+    } else {
+        i32::max(0, i32::min(min, map_size - view_size))
+    }
+}
+
+pub fn mouse_world_position(ecs: &World, ctx: &Rltk) -> Option<Point> {
+    let bounds = get_screen_bounds(ecs);
+    let mouse_pos = ctx.mouse_point();
+    if !bounds.contains_screen(mouse_pos) {
+        return None;
+    }
+
+    let world = bounds.to_world(mouse_pos);
+    let map = ecs.fetch::<Map>();
+    if world.x < 0 || world.x >= map.width || world.y < 0 || world.y >= map.height {
+        return None;
+    }
+
+    Some(world)
+}
+
+/// Draws the map and everything visible on it, with sprites when a tileset is given.
+pub fn render_camera(ecs: &World, ctx: &mut Rltk, tileset: Option<&Tileset>) {
+    let map = ecs.fetch::<Map>();
+    let bounds = get_screen_bounds(ecs);
+
+    match tileset {
+        Some(tileset) => draw_sprite_viewport(&map, &bounds, tileset, ctx),
+        None => draw_map_viewport(&map, &bounds, ctx),
+    }
+
+    let palette = settings::palette();
+    let entities = ecs.entities();
+    let names = ecs.read_storage::<Name>();
+    let positions = ecs.read_storage::<Position>();
+    let renderables = ecs.read_storage::<Renderable>();
+    let hidden = ecs.read_storage::<Hidden>();
+
+    let mut renderable_objects = (&entities, &positions, &renderables, !&hidden)
+        .join()
+        .collect::<Vec<_>>();
+    renderable_objects
+        .sort_by_key(|&(_entity, _pos, render, _hidden)| std::cmp::Reverse(render.render_order));
+    for (entity, pos, render, _hidden) in renderable_objects.iter() {
+        let idx = map.xy_idx(pos.x, pos.y);
+        if !map.visible_tiles[idx] {
+            continue;
+        }
+
+        let screen = bounds.to_screen(Point::new(pos.x, pos.y));
+        if !bounds.contains_screen(screen) {
+            continue;
+        }
+        let name = names.get(*entity).map(|name| name.name.as_str());
+        let sprite = tileset.and_then(|tileset| tileset.entity_sprite(name, render.glyph));
+        if let Some(sprite) = sprite {
+            let white = RGB::named(rltk::WHITE);
+            tileset::draw_sprite(ctx, tileset::ENTITY_LAYER, screen.x, screen.y, white, sprite);
+        } else {
+            ctx.set(
+                screen.x,
+                screen.y,
+                palette.adjust(render.fg),
+                palette.adjust(render.bg),
+                render.glyph,
+            );
+        }
+    }
+}
+
+pub fn render_debug_map(map: &Map, ctx: &mut Rltk) {
+    let center = Point::new(map.width / 2, map.height / 2);
+    let bounds = bounds_centered_on(map, center);
+    draw_map_viewport(map, &bounds, ctx);
+}
+
+fn draw_map_viewport(map: &Map, bounds: &ScreenBounds, ctx: &mut Rltk) {
+    let palette = settings::palette();
+    for (screen_y, y) in (bounds.min_y..bounds.max_y).enumerate() {
+        for (screen_x, x) in (bounds.min_x..bounds.max_x).enumerate() {
+            if x < 0 || x >= map.width || y < 0 || y >= map.height {
+                continue;
+            }
+
+            let idx = map.xy_idx(x, y);
+            if map.revealed_tiles[idx] {
+                let (glyph, fg, bg) = tile_glyph(idx, map);
+                ctx.set(
+                    screen_x as i32,
+                    screen_y as i32,
+                    palette.adjust(fg),
+                    palette.adjust(bg),
+                    glyph,
+                );
+            }
+        }
+    }
+}
+
+/// Like `draw_map_viewport`, but with sprites on the tile layer. Tiles the tileset has no sprite
+/// for are drawn as ASCII on the text layer. Remembered tiles are dimmed rather than greyed.
+fn draw_sprite_viewport(map: &Map, bounds: &ScreenBounds, tileset: &Tileset, ctx: &mut Rltk) {
+    let palette = settings::palette();
+    for (screen_y, y) in (bounds.min_y..bounds.max_y).enumerate() {
+        for (screen_x, x) in (bounds.min_x..bounds.max_x).enumerate() {
+            if x < 0 || x >= map.width || y < 0 || y >= map.height {
+                continue;
+            }
+
+            let idx = map.xy_idx(x, y);
+            if !map.revealed_tiles[idx] {
+                continue;
+            }
+            match tileset.tile_sprite(map, idx) {
+                Some(sprite) => {
+                    let tint = if map.visible_tiles[idx] {
+                        RGB::named(rltk::WHITE)
+                    } else {
+                        RGB::from_f32(0.5, 0.5, 0.5)
+                    };
+                    let (sx, sy) = (screen_x as i32, screen_y as i32);
+                    tileset::draw_sprite(ctx, tileset::TILE_LAYER, sx, sy, tint, sprite);
+                }
+                None => {
+                    let (glyph, fg, bg) = tile_glyph(idx, map);
+                    ctx.set(
+                        screen_x as i32,
+                        screen_y as i32,
+                        palette.adjust(fg),
+                        palette.adjust(bg),
+                        glyph,
+                    );
+                }
+            }
+        }
+    }
+}
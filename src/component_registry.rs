@@ -0,0 +1,148 @@
+use crate::*;
+use specs::prelude::*;
+use specs::saveload::SimpleMarker;
+use std::any::TypeId;
+use std::collections::HashMap;
+
+/// The one list of components in the game. Every component must appear in exactly one of the two
+/// groups: `saved` components are written to save files in the order listed here, `transient`
+/// ones only ever live in the running session. Reordering or changing the saved group changes the
+/// save format, so it needs a new `SAVE_FORMAT_VERSION` and a migration.
+///
+/// Invoke as `with_components!(saved, callback, args...)` to expand to
+/// `callback!(args..., Position, Renderable, ...)`.
+#[macro_export]
+macro_rules! with_components {
+    (saved, $callback: ident, $( $arg: expr ), *) => {
+        $callback!(
+            $( $arg ), *,
+            Position,
+            Renderable,
+            Player,
+            Viewshed,
+            Monster,
+            Name,
+            BlocksTile,
+            CombatStats,
+            SufferDamage,
+            WantsToMelee,
+            Item,
+            Consumable,
+            Ranged,
+            InflictsDamage,
+            AreaOfEffect,
+            Confusion,
+            ProvidesHealing,
+            InBackpack,
+            WantsToPickupItem,
+            WantsToUseItem,
+            WantsToDropItem,
+            SerializationHelper,
+            Equippable,
+            Equipped,
+            MeleePowerBonus,
+            DefenseBonus,
+            WantsToRemoveItem,
+            MagicMapper,
+            Hidden,
+            EntryTrigger,
+            EntityMoved,
+            SingleActivation,
+            ProvidesFood,
+            Door,
+            BlocksVisibility,
+            Locked,
+            Key,
+            CannotOpenDoors,
+            WantsToOpenDoor,
+            WantsToCloseDoor,
+            Bystander,
+            Vendor,
+            RunStats,
+            OtherLevelPosition
+        )
+    };
+    (transient, $callback: ident, $( $arg: expr ), *) => {
+        $callback!(
+            $( $arg ), *,
+            ParticleLifetime
+        )
+    };
+}
+
+macro_rules! register_individual_items {
+    ($ecs: expr, $( $type: ty ), *) => {
+        $(
+            $ecs.register::<$type>();
+        )
+        *
+    };
+}
+
+macro_rules! collect_type_ids {
+    ($ids: expr, $( $type: ty ), *) => {
+        $ids.extend_from_slice(&[ $( (TypeId::of::<$type>(), stringify!($type)) ), * ]);
+    };
+}
+
+pub fn register_components(ecs: &mut World) {
+    ecs.register::<SimpleMarker<Savable>>();
+    with_components!(saved, register_individual_items, ecs);
+    with_components!(transient, register_individual_items, ecs);
+}
+
+/// Panics if a component is listed more than once, either within a group or as both saved and
+/// transient. Run once at startup, before any world is built.
+pub fn check_registry() {
+    let mut ids: Vec<(TypeId, &'static str)> = Vec::new();
+    with_components!(saved, collect_type_ids, ids);
+    with_components!(transient, collect_type_ids, ids);
+
+    let mut seen: HashMap<TypeId, &'static str> = HashMap::new();
+    for (id, name) in ids {
+        if let Some(previous) = seen.insert(id, name) {
+            panic!("Component {} is listed in the registry more than once", previous);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_has_no_duplicates() {
+        check_registry();
+    }
+
+    /// Every component declared in components.rs must be in the saved or the transient group,
+    /// otherwise it is never registered and the first system to use it panics.
+    #[test]
+    fn every_component_is_saved_or_transient() {
+        let mut ids: Vec<(TypeId, &'static str)> = Vec::new();
+        with_components!(saved, collect_type_ids, ids);
+        with_components!(transient, collect_type_ids, ids);
+        let listed: Vec<&str> = ids.iter().map(|(_id, name)| *name).collect();
+
+        let source = include_str!("components.rs");
+        let mut lines = source.lines();
+        let mut declared = 0;
+        while let Some(line) = lines.next() {
+            if !(line.starts_with("#[derive(") && line.contains("Component")) {
+                continue;
+            }
+            let name = lines
+                .next()
+                .and_then(|next| next.strip_prefix("pub struct "))
+                .and_then(|rest| rest.split(|c: char| !c.is_alphanumeric()).next())
+                .expect("a component derive should be followed by its struct");
+            assert!(
+                listed.contains(&name),
+                "Component {} is neither saved nor transient",
+                name
+            );
+            declared += 1;
+        }
+        assert_eq!(declared, listed.len(), "the registry lists a component that isn't declared");
+    }
+}
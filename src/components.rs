@@ -1,3 +1,4 @@
+use crate::encyclopedia::EncyclopediaEntry;
 use crate::Map;
 use rltk::{Point, RGB};
 use serde::{Deserialize, Serialize};
@@ -5,6 +6,7 @@ use specs::error::NoError;
 use specs::prelude::*;
 use specs::saveload::{ConvertSaveload, Marker};
 use specs_derive::*;
+use std::collections::BTreeMap;
 
 #[derive(Component, ConvertSaveload, Clone)]
 pub struct Position {
@@ -63,15 +65,27 @@ pub struct WantsToMelee {
 #[derive(Component, Debug, ConvertSaveload, Clone)]
 pub struct SufferDamage {
     pub amount: Vec<i32>,
+    pub source: String,
+    pub by_player: bool,
 }
 
 impl SufferDamage {
-    pub fn new_damage(store: &mut WriteStorage<SufferDamage>, victim: Entity, amount: i32) {
+    pub fn new_damage(
+        store: &mut WriteStorage<SufferDamage>,
+        victim: Entity,
+        amount: i32,
+        source: &str,
+        by_player: bool,
+    ) {
         if let Some(damage) = store.get_mut(victim) {
             damage.amount.push(amount);
+            damage.source = source.to_string();
+            damage.by_player |= by_player;
         } else {
             let dmg = SufferDamage {
                 amount: vec![amount],
+                source: source.to_string(),
+                by_player,
             };
             store.insert(victim, dmg).expect("Unable to insert damage!");
         }
@@ -152,9 +166,12 @@ pub struct Savable;
 #[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct SerializationHelper {
     pub map: Map,
+    /// The town, kept while the player is down in the dungeon.
+    #[serde(default)]
+    pub town: Option<Map>,
 }
 
-#[derive(PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum EquipmentSlot {
     Melee,
     Shield,
@@ -205,3 +222,59 @@ pub struct EntityMoved {}
 
 #[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct SingleActivation {}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct ProvidesFood {}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Door {
+    pub open: bool,
+}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct BlocksVisibility {}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Locked {}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Key {}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct CannotOpenDoors {}
+
+#[derive(Component, Debug, ConvertSaveload)]
+pub struct WantsToOpenDoor {
+    pub door: Entity,
+}
+
+#[derive(Component, Debug, ConvertSaveload)]
+pub struct WantsToCloseDoor {
+    pub door: Entity,
+}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Bystander {}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Vendor {}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RunStats {
+    pub kills: BTreeMap<String, i32>,
+    pub turns: i32,
+    pub deepest_depth: i32,
+    pub cause_of_death: Option<String>,
+    /// The encyclopedia: every kind of creature, item and trap seen this run, by name.
+    #[serde(default)]
+    pub encountered: BTreeMap<String, EncyclopediaEntry>,
+}
+
+/// Where an entity waits while the player is on another level. Entities keep this instead of a
+/// `Position` so that systems working on the current level never see them.
+#[derive(Component, ConvertSaveload, Clone)]
+pub struct OtherLevelPosition {
+    pub x: i32,
+    pub y: i32,
+    pub depth: i32,
+}
@@ -1,28 +1,46 @@
-use super::{CombatStats, GameLog, Name, Player, SufferDamage};
-use crate::{Map, Position, RunState};
+use super::{CombatStats, GameLog, LogCategory, Name, Player, SufferDamage};
+use crate::{spawner, Equipped, InBackpack, Map, Position, RunState, RunStats};
 use specs::prelude::*;
 
 pub struct DamageSystem {}
 
 impl<'a> System<'a> for DamageSystem {
+    #[allow(clippy::type_complexity)]
     type SystemData = (
         WriteStorage<'a, CombatStats>,
         WriteStorage<'a, SufferDamage>,
         ReadStorage<'a, Position>,
         WriteExpect<'a, Map>,
         Entities<'a>,
+        ReadExpect<'a, Entity>,
+        ReadStorage<'a, Name>,
+        WriteStorage<'a, RunStats>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut stats, mut damage, positions, mut map, entities) = data;
+        let (mut stats, mut damage, positions, mut map, entities, player_entity, names, mut run_stats) =
+            data;
 
-        for (entity, mut stats, damage) in (&entities, &mut stats, &damage).join() {
+        for (entity, stats, damage) in (&entities, &mut stats, &damage).join() {
+            let was_alive = stats.hp > 0;
             stats.hp -= damage.amount.iter().sum::<i32>();
             let pos = positions.get(entity);
             if let Some(pos) = pos {
                 let index = map.xy_idx(pos.x, pos.y);
                 map.blood_stains.insert(index);
             }
+
+            if was_alive && stats.hp < 1 {
+                if let Some(player_stats) = run_stats.get_mut(*player_entity) {
+                    if entity == *player_entity {
+                        player_stats.cause_of_death = Some(damage.source.clone());
+                    } else if damage.by_player {
+                        if let Some(name) = names.get(entity) {
+                            *player_stats.kills.entry(name.name.clone()).or_insert(0) += 1;
+                        }
+                    }
+                }
+            }
         }
 
         damage.clear();
@@ -46,7 +64,7 @@ pub fn delete_dead(ecs: &mut World) {
                     None => {
                         let victim_name = names.get(entity);
                         if let Some(victim_name) = victim_name {
-                            log.entries.push(format!("{} is dead", &victim_name.name));
+                            log.entry(LogCategory::Combat).name(&victim_name.name).text(" is dead").log();
                         }
                         dead.push(entity)
                     }
@@ -59,8 +77,60 @@ pub fn delete_dead(ecs: &mut World) {
         }
     }
 
+    for victim in dead.iter() {
+        drop_carried_items(ecs, *victim);
+        leave_corpse(ecs, *victim);
+    }
+
     for victim in dead {
         ecs.delete_entity(victim)
             .expect("Unable to remove the dead");
     }
 }
+
+fn drop_carried_items(ecs: &mut World, victim: Entity) {
+    let victim_position = match ecs.read_storage::<Position>().get(victim) {
+        None => return,
+        Some(pos) => pos.clone(),
+    };
+
+    let entities = ecs.entities();
+    let mut backpack = ecs.write_storage::<InBackpack>();
+    let mut equipped = ecs.write_storage::<Equipped>();
+    let mut positions = ecs.write_storage::<Position>();
+
+    let mut carried: Vec<Entity> = Vec::new();
+    for (item, pack) in (&entities, &backpack).join() {
+        if pack.owner == victim {
+            carried.push(item);
+        }
+    }
+    for (item, equip) in (&entities, &equipped).join() {
+        if equip.owner == victim {
+            carried.push(item);
+        }
+    }
+
+    for item in carried.iter() {
+        backpack.remove(*item);
+        equipped.remove(*item);
+        positions
+            .insert(*item, victim_position.clone())
+            .expect("Unable to drop loot from the dead");
+    }
+}
+
+fn leave_corpse(ecs: &mut World, victim: Entity) {
+    let corpse = {
+        let positions = ecs.read_storage::<Position>();
+        let names = ecs.read_storage::<Name>();
+        match (positions.get(victim), names.get(victim)) {
+            (Some(pos), Some(name)) => Some((pos.x, pos.y, name.name.clone())),
+            _ => None,
+        }
+    };
+
+    if let Some((x, y, name)) = corpse {
+        spawner::corpse(ecs, x, y, name);
+    }
+}
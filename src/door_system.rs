@@ -0,0 +1,128 @@
+use crate::{
+    BlocksTile, BlocksVisibility, Door, GameLog, InBackpack, LogCategory, Key, Locked, Name, Renderable,
+    Viewshed, WantsToCloseDoor, WantsToOpenDoor,
+};
+use specs::prelude::*;
+
+pub struct DoorSystem {}
+
+impl<'a> System<'a> for DoorSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        WriteStorage<'a, WantsToOpenDoor>,
+        WriteStorage<'a, WantsToCloseDoor>,
+        WriteStorage<'a, Door>,
+        WriteStorage<'a, BlocksTile>,
+        WriteStorage<'a, BlocksVisibility>,
+        WriteStorage<'a, Renderable>,
+        WriteStorage<'a, Locked>,
+        ReadStorage<'a, Key>,
+        ReadStorage<'a, InBackpack>,
+        WriteStorage<'a, Name>,
+        WriteStorage<'a, Viewshed>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            player_entity,
+            mut log,
+            mut wants_to_open,
+            mut wants_to_close,
+            mut doors,
+            mut blocks_tile,
+            mut blocks_visibility,
+            mut renderables,
+            mut locked,
+            keys,
+            backpack,
+            mut names,
+            mut viewsheds,
+        ) = data;
+
+        let mut doors_changed = false;
+
+        for (entity, intent) in (&entities, &wants_to_open).join() {
+            let door = match doors.get_mut(intent.door) {
+                Some(door) if !door.open => door,
+                _ => continue,
+            };
+
+            if locked.get(intent.door).is_some() {
+                let key = (&entities, &keys, &backpack)
+                    .join()
+                    .find(|(_key, _k, pack)| pack.owner == entity)
+                    .map(|(key, _k, _pack)| key);
+
+                match key {
+                    None => {
+                        if entity == *player_entity {
+                            log.add(LogCategory::System, "The door is locked.");
+                        }
+                        continue;
+                    }
+                    Some(key) => {
+                        if entity == *player_entity {
+                            if let Some(key_name) = names.get(key) {
+                                log.entry(LogCategory::Item)
+                                    .text("You unlock the door with the ")
+                                    .item(&key_name.name)
+                                    .log();
+                            }
+                        }
+                        locked.remove(intent.door);
+                        entities.delete(key).expect("Unable to use up key");
+                        if let Some(name) = names.get_mut(intent.door) {
+                            name.name = "Door".to_string();
+                        }
+                    }
+                }
+            }
+
+            door.open = true;
+            blocks_tile.remove(intent.door);
+            blocks_visibility.remove(intent.door);
+            if let Some(render) = renderables.get_mut(intent.door) {
+                render.glyph = rltk::to_cp437('/');
+            }
+            if entity == *player_entity {
+                log.add(LogCategory::System, "You open the door.");
+            }
+            doors_changed = true;
+        }
+
+        for (entity, intent) in (&entities, &wants_to_close).join() {
+            let door = match doors.get_mut(intent.door) {
+                Some(door) if door.open => door,
+                _ => continue,
+            };
+
+            door.open = false;
+            blocks_tile
+                .insert(intent.door, BlocksTile {})
+                .expect("Unable to block closed door");
+            blocks_visibility
+                .insert(intent.door, BlocksVisibility {})
+                .expect("Unable to block sight through closed door");
+            if let Some(render) = renderables.get_mut(intent.door) {
+                render.glyph = rltk::to_cp437('+');
+            }
+            if entity == *player_entity {
+                log.add(LogCategory::System, "You close the door.");
+            }
+            doors_changed = true;
+        }
+
+        if doors_changed {
+            for viewshed in (&mut viewsheds).join() {
+                viewshed.dirty = true;
+            }
+        }
+
+        wants_to_open.clear();
+        wants_to_close.clear();
+    }
+}
@@ -0,0 +1,164 @@
+use crate::gui::item_properties;
+use crate::{
+    Bystander, CombatStats, Confusion, EntryTrigger, Equipped, Hidden, InBackpack, Item, Map,
+    Monster, Name, Player, Position, Renderable, RunStats, Vendor,
+};
+use rltk::RGB;
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug)]
+pub enum EntryKind {
+    Creature,
+    Item,
+    Trap,
+}
+
+impl EntryKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            EntryKind::Creature => "Creature",
+            EntryKind::Item => "Item",
+            EntryKind::Trap => "Trap",
+        }
+    }
+}
+
+/// Something the player has seen, as it looked the first time.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EncyclopediaEntry {
+    pub kind: EntryKind,
+    pub glyph: rltk::FontCharType,
+    pub fg: RGB,
+    /// Where it was first seen; 0 is the town.
+    pub first_seen_depth: i32,
+    /// Stats and effects, e.g. "Attack 4, block 1" or "Heals 8 HP".
+    pub facts: Vec<String>,
+}
+
+/// Flavour text for the encyclopedia, by entity name.
+pub fn description(name: &str) -> String {
+    if let Some(creature) = name.strip_suffix(" corpse") {
+        return format!("{} remains. Still edible, if you aren't fussy.", creature);
+    }
+
+    match name {
+        "Goblin" => "A small, spiteful creature that attacks anything weaker than a goblin. It rarely travels without something worth stealing.",
+        "Orc" => "A brutish warrior, more common the deeper you go. Orcs often carry weapons and armour taken from earlier adventurers.",
+        "Townsperson" => "One of the locals, going about their business. They have no quarrel with you.",
+        "Guard" => "Keeps the peace in town. Leave the townsfolk alone and they will leave you alone.",
+        "Barkeep" => "Runs the tavern and hears every rumour about the dungeon below.",
+        "Merchant" => "Buys and sells whatever adventurers drag back up from the depths.",
+        "Health Potion" => "A small vial of red liquid that knits wounds closed.",
+        "Fireball Scroll" => "Unleashes a ball of flame that burns everything caught in the blast, friend or foe.",
+        "Confusion Scroll" => "Befuddles a creature so that it staggers about aimlessly for a while.",
+        "Magic Missile Scroll" => "Fires a bolt of force at a single target.",
+        "Magic Mapping Scroll" => "Reveals the layout of the whole level when read.",
+        "Dagger" => "A short blade. Better than fighting with your fists.",
+        "Longsword" => "A well-balanced blade for those who have made it this far.",
+        "Shield" => "A round wooden shield that turns aside some blows.",
+        "Tower Shield" => "A heavy shield that covers most of the body.",
+        "Iron Key" => "Opens the locked door somewhere on this level.",
+        "Bear Trap" => "Steel jaws hidden on the floor. Careful searching reveals them before they snap shut.",
+        _ => "Little is known about this.",
+    }
+    .to_string()
+}
+
+/// Adds everything the player can currently see, or is carrying, to their encyclopedia.
+pub fn record_encounters(ecs: &World) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let map = ecs.fetch::<Map>();
+    let entities = ecs.entities();
+    let names = ecs.read_storage::<Name>();
+    let positions = ecs.read_storage::<Position>();
+    let renderables = ecs.read_storage::<Renderable>();
+    let hidden = ecs.read_storage::<Hidden>();
+    let players = ecs.read_storage::<Player>();
+    let mut run_stats = ecs.write_storage::<RunStats>();
+    let stats = match run_stats.get_mut(player_entity) {
+        Some(stats) => stats,
+        None => return,
+    };
+
+    for (entity, name, render, _hidden, _player) in
+        (&entities, &names, &renderables, !&hidden, !&players).join()
+    {
+        if stats.encountered.contains_key(&name.name) {
+            continue;
+        }
+        // Items the player carries have no position but have clearly been seen; ones a monster
+        // carries haven't.
+        let seen = match positions.get(entity) {
+            Some(pos) => map.visible_tiles[map.xy_idx(pos.x, pos.y)],
+            None => carried_by(ecs, entity, player_entity),
+        };
+        if !seen {
+            continue;
+        }
+        let kind = match entry_kind(ecs, entity) {
+            Some(kind) => kind,
+            None => continue,
+        };
+
+        stats.encountered.insert(
+            name.name.clone(),
+            EncyclopediaEntry {
+                kind,
+                glyph: render.glyph,
+                fg: render.fg,
+                first_seen_depth: map.depth,
+                facts: facts(ecs, entity),
+            },
+        );
+    }
+}
+
+fn entry_kind(ecs: &World, entity: Entity) -> Option<EntryKind> {
+    if ecs.read_storage::<Monster>().get(entity).is_some()
+        || ecs.read_storage::<Bystander>().get(entity).is_some()
+        || ecs.read_storage::<Vendor>().get(entity).is_some()
+    {
+        Some(EntryKind::Creature)
+    } else if ecs.read_storage::<Item>().get(entity).is_some() {
+        Some(EntryKind::Item)
+    } else if ecs.read_storage::<EntryTrigger>().get(entity).is_some() {
+        Some(EntryKind::Trap)
+    } else {
+        None
+    }
+}
+
+fn carried_by(ecs: &World, item: Entity, owner: Entity) -> bool {
+    ecs.read_storage::<InBackpack>()
+        .get(item)
+        .is_some_and(|pack| pack.owner == owner)
+        || ecs
+            .read_storage::<Equipped>()
+            .get(item)
+            .is_some_and(|equipped| equipped.owner == owner)
+}
+
+fn facts(ecs: &World, entity: Entity) -> Vec<String> {
+    let mut facts = Vec::new();
+    if let Some(stats) = ecs.read_storage::<CombatStats>().get(entity) {
+        facts.push(format!("{} HP", stats.max_hp));
+        facts.push(format!(
+            "Attack {}, block {}",
+            stats.attack_power, stats.block
+        ));
+    }
+    if ecs.read_storage::<Bystander>().get(entity).is_some() {
+        facts.push("Peaceful".to_string());
+    }
+    if ecs.read_storage::<Vendor>().get(entity).is_some() {
+        facts.push("Trader".to_string());
+    }
+    if ecs.read_storage::<Item>().get(entity).is_some() {
+        if let Some(confusion) = ecs.read_storage::<Confusion>().get(entity) {
+            facts.push(format!("Confuses for {} turns", confusion.turns));
+        }
+    }
+    facts.extend(item_properties(ecs, entity));
+    facts
+}
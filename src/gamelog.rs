@@ -1,3 +1,151 @@
+use rltk::RGB;
+use std::collections::VecDeque;
+
+/// How many entries the log keeps before dropping the oldest.
+pub const MAX_LOG_ENTRIES: usize = 500;
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum LogCategory {
+    Combat,
+    Item,
+    System,
+}
+
+impl LogCategory {
+    pub const ALL: [LogCategory; 3] = [LogCategory::Combat, LogCategory::Item, LogCategory::System];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LogCategory::Combat => "combat",
+            LogCategory::Item => "items",
+            LogCategory::System => "system",
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct LogSpan {
+    pub text: String,
+    pub color: RGB,
+}
+
+#[derive(Clone, Debug)]
+pub struct LogEntry {
+    pub turn: i32,
+    pub category: LogCategory,
+    pub spans: Vec<LogSpan>,
+    /// How many times in a row this message was logged.
+    pub count: u32,
+}
+
+impl LogEntry {
+    /// The message without colours, e.g. "Goblin hits Player for 3 damage (x3)".
+    pub fn text(&self) -> String {
+        let mut text: String = self.spans.iter().map(|span| span.text.as_str()).collect();
+        if self.count > 1 {
+            text.push_str(&format!(" (x{})", self.count));
+        }
+        text
+    }
+}
+
 pub struct GameLog {
-    pub entries : Vec<String>,
-}
\ No newline at end of file
+    entries: VecDeque<LogEntry>,
+    /// Stamped onto new entries; kept up to date by the main loop.
+    pub turn: i32,
+}
+
+impl GameLog {
+    pub fn new() -> GameLog {
+        GameLog {
+            entries: VecDeque::new(),
+            turn: 0,
+        }
+    }
+
+    /// Logs a message in a single colour.
+    pub fn add<S: ToString>(&mut self, category: LogCategory, text: S) {
+        self.entry(category).text(text).log();
+    }
+
+    /// Starts a message made of coloured spans, finished with `log()`.
+    pub fn entry(&mut self, category: LogCategory) -> LogBuilder<'_> {
+        LogBuilder {
+            log: self,
+            category,
+            spans: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, category: LogCategory, spans: Vec<LogSpan>) {
+        if let Some(last) = self.entries.back_mut() {
+            if last.category == category && last.spans == spans {
+                last.count += 1;
+                last.turn = self.turn;
+                return;
+            }
+        }
+
+        self.entries.push_back(LogEntry {
+            turn: self.turn,
+            category,
+            spans,
+            count: 1,
+        });
+        while self.entries.len() > MAX_LOG_ENTRIES {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Oldest first.
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &LogEntry> + ExactSizeIterator {
+        self.entries.iter()
+    }
+}
+
+impl Default for GameLog {
+    fn default() -> GameLog {
+        GameLog::new()
+    }
+}
+
+pub struct LogBuilder<'a> {
+    log: &'a mut GameLog,
+    category: LogCategory,
+    spans: Vec<LogSpan>,
+}
+
+impl<'a> LogBuilder<'a> {
+    pub fn color<S: ToString>(mut self, color: RGB, text: S) -> LogBuilder<'a> {
+        self.spans.push(LogSpan {
+            text: text.to_string(),
+            color,
+        });
+        self
+    }
+
+    pub fn text<S: ToString>(self, text: S) -> LogBuilder<'a> {
+        self.color(RGB::named(rltk::WHITE), text)
+    }
+
+    /// A creature's name.
+    pub fn name<S: ToString>(self, text: S) -> LogBuilder<'a> {
+        self.color(RGB::named(rltk::YELLOW), text)
+    }
+
+    pub fn item<S: ToString>(self, text: S) -> LogBuilder<'a> {
+        self.color(RGB::named(rltk::CYAN), text)
+    }
+
+    pub fn damage(self, amount: i32) -> LogBuilder<'a> {
+        self.color(RGB::named(rltk::RED), amount)
+    }
+
+    pub fn healing(self, amount: i32) -> LogBuilder<'a> {
+        self.color(RGB::named(rltk::GREEN), amount)
+    }
+
+    pub fn log(self) {
+        self.log.push(self.category, self.spans);
+    }
+}
@@ -1,9 +1,20 @@
-use super::{CombatStats, GameLog, Map, Name, Player, Position};
-use crate::{save_exists, Equipped, Hidden, InBackpack, ItemHasOwner, RunState, State, Viewshed};
-use rltk::{console, Point, Rltk, VirtualKeyCode, RGB};
+use super::{CombatStats, GameLog, LogCategory, LogEntry, Map, Monster, Name, Player, Position};
+use crate::{
+    replay_exists, save_exists, AreaOfEffect, Bystander, Confusion, Consumable, DefenseBonus, Door,
+    EntryTrigger, EquipmentSlot, Equipped, Hidden, InBackpack, InflictsDamage, Item, ItemHasOwner, Key,
+    Locked, MagicMapper, MeleePowerBonus, ProvidesFood, ProvidesHealing, Ranged, RunState,
+    RunStats, SaveMetadata, State, Vendor, Viewshed,
+};
+use rltk::{Point, Rltk, VirtualKeyCode, RGB};
 use specs::prelude::*;
 use specs::world::EntitiesRes;
+use crate::camera;
+use crate::encyclopedia::{self, EncyclopediaEntry};
+use crate::hall_of_fame::{ScoreSort, ScoreTable};
+use crate::keybindings::{Action, KeyBindings};
+use crate::morgue::{format_timestamp, RunReport};
 use crate::rex_assets::RexAssets;
+use crate::settings::{self, LogLevel, OptionField, Settings};
 
 #[derive(PartialEq, Copy, Clone)]
 pub enum ItemMenuResult {
@@ -15,7 +26,11 @@ pub enum ItemMenuResult {
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub enum MainMenuSelection {
     NewGame,
+    NewHardcoreGame,
     LoadGame,
+    WatchReplay,
+    HallOfFame,
+    Options,
     Quit,
 }
 
@@ -25,13 +40,55 @@ pub enum MainMenuResult {
     Selected { selected: MainMenuSelection },
 }
 
+#[derive(PartialEq, Copy, Clone)]
+pub enum LoadGameResult {
+    NoSelection { selection: usize },
+    Load { selection: usize },
+    Delete { selection: usize },
+    Cancel,
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum HallOfFameResult {
+    NoSelection { sort: ScoreSort },
+    Close,
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum HelpResult {
+    NoSelection,
+    Close,
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum OptionsResult {
+    NoSelection { selection: usize },
+    Changed { selection: usize },
+    Close,
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum MessageLogResult {
+    NoSelection {
+        offset: usize,
+        filter: Option<LogCategory>,
+    },
+    Close,
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum EncyclopediaResult {
+    NoSelection { selection: usize },
+    Close,
+}
+
 #[derive(PartialEq, Copy, Clone)]
 pub enum GameOverResult {
     NoSelection,
     QuitToMenu,
 }
 
-pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
+pub fn draw_ui(ecs: &World, ctx: &mut Rltk, keybindings: &KeyBindings) {
     ctx.draw_box(
         0,
         43,
@@ -41,6 +98,17 @@ pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
         RGB::named(rltk::BLACK),
     );
 
+    if let Some(key) = keybindings.primary_key(Action::Help) {
+        let hint = format!(" {}: help ", key);
+        ctx.print_color(
+            78 - hint.len() as i32,
+            49,
+            RGB::named(rltk::GRAY),
+            RGB::named(rltk::BLACK),
+            &hint,
+        );
+    }
+
     let combat_stats = ecs.read_storage::<CombatStats>();
     let players = ecs.read_storage::<Player>();
     for (_player, stats) in (&players, &combat_stats).join() {
@@ -54,7 +122,11 @@ pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
         );
 
         let map = ecs.fetch::<Map>();
-        let depth = format!(" Depth: {}", map.depth);
+        let depth = if map.depth == 0 {
+            " Town".to_string()
+        } else {
+            format!(" Depth: {}", map.depth)
+        };
         ctx.print_color(
             2,
             43,
@@ -69,23 +141,21 @@ pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
             51,
             stats.hp,
             stats.max_hp,
-            RGB::named(rltk::RED),
+            settings::palette().adjust(RGB::named(rltk::RED)),
             RGB::named(rltk::BLACK),
         );
     }
 
     let log = ecs.fetch::<GameLog>();
-    let mut y = 44;
-    for s in log.entries.iter().rev() {
-        if y < 49 {
-            ctx.print(2, y, s);
-        }
-        y += 1;
+    for (row, entry) in log.entries().rev().take(5).enumerate() {
+        draw_log_entry(ctx, 2, 44 + row as i32, 78, entry);
     }
 
-    let mouse_pos = ctx.mouse_pos();
-    ctx.set_bg(mouse_pos.0, mouse_pos.1, RGB::named(rltk::MAGENTA));
-    draw_tooltips(ecs, ctx);
+    if !matches!(*ecs.fetch::<RunState>(), RunState::LookMode { .. }) {
+        let mouse_pos = ctx.mouse_point();
+        ctx.set_bg(mouse_pos.x, mouse_pos.y, RGB::named(rltk::MAGENTA));
+        draw_tooltips(ecs, ctx, mouse_pos);
+    }
 }
 
 pub fn show_inventory(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
@@ -171,48 +241,46 @@ pub fn show_main_menu(gs: &mut State, ctx: &mut Rltk) -> MainMenuResult {
         let box_text_title = RGB::named(rltk::YELLOW);
         let box_text = RGB::named(rltk::CYAN);
 
-        ctx.draw_box_double(24, 18, 31, 10, box_foreground, box_background);
+        ctx.draw_box_double(24, 18, 31, 13, box_foreground, box_background);
         ctx.print_color_centered(20, box_text_title, box_background, "Deathlike");
         ctx.print_color_centered(22, box_text, box_background, "It's like death");
 
 
-        ctx.print_color_centered(
-            24,
-            if selection == MainMenuSelection::NewGame {
-                selected_color
-            } else {
-                idle_color
-            },
-            background,
-            "Begin New Game",
-        );
-
-        let mut y = 25;
-
+        let mut entries = vec![
+            (MainMenuSelection::NewGame, "Begin New Game"),
+            (MainMenuSelection::NewHardcoreGame, "Begin Hardcore Game"),
+        ];
         if show_load_game {
+            entries.push((MainMenuSelection::LoadGame, "Load Game"));
+        }
+        if replay_exists() {
+            entries.push((MainMenuSelection::WatchReplay, "Watch Last Replay"));
+        }
+        entries.push((MainMenuSelection::HallOfFame, "Hall of Fame"));
+        entries.push((MainMenuSelection::Options, "Options"));
+        entries.push((MainMenuSelection::Quit, "Quit Game"));
+
+        for (y, (entry, label)) in entries.iter().enumerate() {
             ctx.print_color_centered(
-                y,
-                if selection == MainMenuSelection::LoadGame {
+                24 + y as i32,
+                if selection == *entry {
                     selected_color
                 } else {
                     idle_color
                 },
                 background,
-                "Load Game",
+                label,
             );
-            y += 1;
         }
 
-        ctx.print_color_centered(
-            y,
-            if selection == MainMenuSelection::Quit {
-                selected_color
-            } else {
-                idle_color
-            },
-            background,
-            "Quit Game",
-        );
+        if let Some(message) = &gs.menu_message {
+            ctx.print_color_centered(33, RGB::named(rltk::RED), background, message);
+        }
+
+        let current = entries
+            .iter()
+            .position(|(entry, _)| *entry == selection)
+            .unwrap_or(0);
 
         return match ctx.key {
             None => MainMenuResult::NoSelection {
@@ -222,40 +290,14 @@ pub fn show_main_menu(gs: &mut State, ctx: &mut Rltk) -> MainMenuResult {
                 VirtualKeyCode::Escape => MainMenuResult::NoSelection {
                     selected: selection,
                 },
-                VirtualKeyCode::Up => {
-                    let mut new_selection;
-                    match selection {
-                        MainMenuSelection::NewGame => new_selection = MainMenuSelection::Quit,
-                        MainMenuSelection::LoadGame => new_selection = MainMenuSelection::NewGame,
-                        MainMenuSelection::Quit => new_selection = MainMenuSelection::LoadGame,
-                    }
-
-                    if new_selection == MainMenuSelection::LoadGame && !show_load_game {
-                        new_selection = MainMenuSelection::NewGame;
-                    }
-
-                    MainMenuResult::NoSelection {
-                        selected: new_selection,
-                    }
-                }
-                VirtualKeyCode::Down => {
-                    let mut new_selection;
-                    match selection {
-                        MainMenuSelection::NewGame => new_selection = MainMenuSelection::LoadGame,
-                        MainMenuSelection::LoadGame => new_selection = MainMenuSelection::Quit,
-                        MainMenuSelection::Quit => new_selection = MainMenuSelection::NewGame,
-                    }
-
-                    if new_selection == MainMenuSelection::LoadGame && !show_load_game {
-                        new_selection = MainMenuSelection::Quit;
-                    }
-
-                    MainMenuResult::NoSelection {
-                        selected: new_selection,
-                    }
-                }
+                VirtualKeyCode::Up => MainMenuResult::NoSelection {
+                    selected: entries[(current + entries.len() - 1) % entries.len()].0,
+                },
+                VirtualKeyCode::Down => MainMenuResult::NoSelection {
+                    selected: entries[(current + 1) % entries.len()].0,
+                },
                 VirtualKeyCode::Return => MainMenuResult::Selected {
-                    selected: selection,
+                    selected: entries[current].0,
                 },
                 _ => MainMenuResult::NoSelection {
                     selected: selection,
@@ -269,28 +311,48 @@ pub fn show_main_menu(gs: &mut State, ctx: &mut Rltk) -> MainMenuResult {
     }
 }
 
-pub fn game_over(ctx: &mut Rltk) -> GameOverResult {
+pub fn game_over(ctx: &mut Rltk, report: &RunReport) -> GameOverResult {
     let bg = RGB::named(rltk::BLACK);
+    let summary = &report.summary;
+
+    ctx.draw_box_double(14, 12, 51, 14, RGB::named(rltk::WHEAT), bg);
     ctx.print_color_centered(
-        15,
+        14,
         RGB::named(rltk::YELLOW),
         bg,
         "Your journey has ended".to_string(),
     );
+    ctx.print_color_centered(
+        16,
+        RGB::named(rltk::WHITE),
+        bg,
+        format!("Cause of death: {}", summary.cause_of_death),
+    );
     ctx.print_color_centered(
         17,
         RGB::named(rltk::WHITE),
         bg,
-        "One day, we might tell you how that happened".to_string(),
+        format!(
+            "Depth {} (deepest {}), {} turns",
+            summary.depth, summary.deepest_depth, summary.turns
+        ),
     );
     ctx.print_color_centered(
         18,
         RGB::named(rltk::WHITE),
         bg,
-        "Sadly I am teh lazy".to_string(),
+        format!("Monsters slain: {}", summary.total_kills),
     );
+    if let Some(path) = &report.morgue_path {
+        ctx.print_color_centered(
+            20,
+            RGB::named(rltk::GRAY),
+            bg,
+            format!("Morgue written to {}", path),
+        );
+    }
     ctx.print_color_centered(
-        20,
+        23,
         RGB::named(rltk::MAGENTA),
         bg,
         "Press any key to return to the main menu",
@@ -302,6 +364,409 @@ pub fn game_over(ctx: &mut Rltk) -> GameOverResult {
     }
 }
 
+/// Prints a log entry span by span, cutting it off at `max_x`.
+fn draw_log_entry(ctx: &mut Rltk, x: i32, y: i32, max_x: i32, entry: &LogEntry) {
+    let bg = RGB::named(rltk::BLACK);
+    let repeats = format!(" (x{})", entry.count);
+    let mut spans: Vec<(&str, RGB)> = entry
+        .spans
+        .iter()
+        .map(|span| (span.text.as_str(), span.color))
+        .collect();
+    if entry.count > 1 {
+        spans.push((&repeats, RGB::named(rltk::GRAY)));
+    }
+
+    let mut x = x;
+    for (text, color) in spans {
+        let room = (max_x - x).max(0) as usize;
+        let text: String = text.chars().take(room).collect();
+        ctx.print_color(x, y, color, bg, &text);
+        x += text.chars().count() as i32;
+    }
+}
+
+fn next_log_filter(filter: Option<LogCategory>) -> Option<LogCategory> {
+    match filter {
+        None => Some(LogCategory::ALL[0]),
+        Some(category) => LogCategory::ALL
+            .iter()
+            .position(|c| *c == category)
+            .and_then(|i| LogCategory::ALL.get(i + 1).copied()),
+    }
+}
+
+/// The whole stored log, newest at the bottom. `offset` counts lines scrolled back from the
+/// newest entry.
+pub fn show_message_log(
+    ctx: &mut Rltk,
+    log: &GameLog,
+    offset: usize,
+    filter: Option<LogCategory>,
+    keybindings: &KeyBindings,
+) -> MessageLogResult {
+    const ROWS: usize = 43;
+    let bg = RGB::named(rltk::BLACK);
+
+    let entries: Vec<&LogEntry> = log
+        .entries()
+        .filter(|entry| filter.is_none_or(|category| entry.category == category))
+        .collect();
+    let max_offset = entries.len().saturating_sub(ROWS);
+    let offset = usize::min(offset, max_offset);
+
+    ctx.draw_box_double(0, 0, 79, 49, RGB::named(rltk::WHEAT), bg);
+    ctx.print_color_centered(0, RGB::named(rltk::YELLOW), bg, "Message Log");
+    ctx.print_color(
+        2,
+        1,
+        RGB::named(rltk::CYAN),
+        bg,
+        format!("Showing: {}", filter.map_or("everything", |c| c.label())),
+    );
+
+    let end = entries.len() - offset;
+    let start = end.saturating_sub(ROWS);
+    for (row, entry) in entries[start..end].iter().enumerate() {
+        let y = 3 + row as i32;
+        ctx.print_color(2, y, RGB::named(rltk::GRAY), bg, format!("{:>6}", entry.turn));
+        draw_log_entry(ctx, 9, y, 78, entry);
+    }
+
+    ctx.print_color_centered(
+        49,
+        RGB::named(rltk::YELLOW),
+        bg,
+        " Up/Down/PgUp/PgDn: scroll, Tab: filter, Esc: close ",
+    );
+
+    let scrolled = |offset: usize| MessageLogResult::NoSelection {
+        offset: usize::min(offset, max_offset),
+        filter,
+    };
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => MessageLogResult::Close,
+        Some(key) if keybindings.action_for(key) == Some(Action::MessageLog) => {
+            MessageLogResult::Close
+        }
+        Some(VirtualKeyCode::Up) => scrolled(offset + 1),
+        Some(VirtualKeyCode::Down) => scrolled(offset.saturating_sub(1)),
+        Some(VirtualKeyCode::PageUp) => scrolled(offset + ROWS),
+        Some(VirtualKeyCode::PageDown) => scrolled(offset.saturating_sub(ROWS)),
+        Some(VirtualKeyCode::Home) => scrolled(max_offset),
+        Some(VirtualKeyCode::End) => scrolled(0),
+        Some(VirtualKeyCode::Tab) => MessageLogResult::NoSelection {
+            offset: 0,
+            filter: next_log_filter(filter),
+        },
+        _ => scrolled(offset),
+    }
+}
+
+/// Splits `text` into lines of at most `width` characters, breaking between words.
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Everything the player has met this run: creatures first, then items and traps, each with
+/// what was learned about it.
+pub fn show_encyclopedia(
+    ecs: &World,
+    ctx: &mut Rltk,
+    selection: usize,
+    keybindings: &KeyBindings,
+) -> EncyclopediaResult {
+    const ROWS: usize = 45;
+    let bg = RGB::named(rltk::BLACK);
+    let player_entity = *ecs.fetch::<Entity>();
+    let run_stats = ecs.read_storage::<RunStats>();
+    let stats = run_stats.get(player_entity).cloned().unwrap_or_default();
+
+    let mut entries: Vec<(&String, &EncyclopediaEntry)> = stats.encountered.iter().collect();
+    entries.sort_by_key(|(name, entry)| (entry.kind, name.as_str()));
+
+    ctx.draw_box_double(0, 0, 79, 49, RGB::named(rltk::WHEAT), bg);
+    ctx.print_color_centered(0, RGB::named(rltk::YELLOW), bg, "Encyclopedia");
+    ctx.print_color_centered(
+        49,
+        RGB::named(rltk::YELLOW),
+        bg,
+        " Up/Down: choose, Esc: close ",
+    );
+
+    let close = match ctx.key {
+        Some(VirtualKeyCode::Escape) => true,
+        Some(key) => keybindings.action_for(key) == Some(Action::Encyclopedia),
+        None => false,
+    };
+    if close {
+        return EncyclopediaResult::Close;
+    }
+
+    if entries.is_empty() {
+        ctx.print_color(2, 2, RGB::named(rltk::GRAY), bg, "You haven't met anything yet.");
+        return EncyclopediaResult::NoSelection { selection: 0 };
+    }
+
+    let selection = usize::min(selection, entries.len() - 1);
+    let first = (selection + 1).saturating_sub(ROWS);
+    for (row, (name, entry)) in entries.iter().enumerate().skip(first).take(ROWS) {
+        let y = 2 + (row - first) as i32;
+        let color = if row == selection {
+            RGB::named(rltk::MAGENTA)
+        } else {
+            RGB::named(rltk::WHITE)
+        };
+        ctx.set(2, y, entry.fg, bg, entry.glyph);
+        ctx.print_color(4, y, color, bg, name.as_str());
+    }
+
+    let (name, entry) = entries[selection];
+    let grey = RGB::named(rltk::LIGHT_GRAY);
+    ctx.set(28, 2, entry.fg, bg, entry.glyph);
+    ctx.print_color(30, 2, RGB::named(rltk::YELLOW), bg, name.as_str());
+    ctx.print_color(30, 3, RGB::named(rltk::CYAN), bg, entry.kind.label());
+
+    let mut y = 5;
+    let first_seen = match entry.first_seen_depth {
+        0 => "First seen in the town".to_string(),
+        depth => format!("First seen at depth {}", depth),
+    };
+    ctx.print_color(28, y, grey, bg, first_seen);
+    y += 1;
+    if let Some(kills) = stats.kills.get(name) {
+        ctx.print_color(28, y, grey, bg, format!("Killed {}", kills));
+        y += 1;
+    }
+
+    y += 1;
+    for line in wrap_text(&encyclopedia::description(name), 49) {
+        ctx.print_color(28, y, RGB::named(rltk::WHITE), bg, line);
+        y += 1;
+    }
+
+    y += 1;
+    for fact in entry.facts.iter() {
+        ctx.print_color(28, y, grey, bg, fact);
+        y += 1;
+    }
+
+    match ctx.key {
+        Some(VirtualKeyCode::Up) => EncyclopediaResult::NoSelection {
+            selection: selection.saturating_sub(1),
+        },
+        Some(VirtualKeyCode::Down) => EncyclopediaResult::NoSelection {
+            selection: usize::min(selection + 1, entries.len() - 1),
+        },
+        _ => EncyclopediaResult::NoSelection { selection },
+    }
+}
+
+/// Lists every action with the keys currently bound to it.
+pub fn show_help(ctx: &mut Rltk, keybindings: &KeyBindings) -> HelpResult {
+    let bg = RGB::named(rltk::BLACK);
+    let height = Action::ALL.len() as i32 + 5;
+    let y = (43 - height) / 2;
+
+    ctx.draw_box(10, y, 59, height, RGB::named(rltk::WHITE), bg);
+    ctx.print_color(13, y, RGB::named(rltk::YELLOW), bg, "Controls");
+    for (row, action) in Action::ALL.iter().enumerate() {
+        let line = y + 2 + row as i32;
+        ctx.print_color(13, line, RGB::named(rltk::WHITE), bg, action.description());
+        ctx.print_color(40, line, RGB::named(rltk::CYAN), bg, keybindings.describe(*action));
+    }
+    let line = y + 2 + Action::ALL.len() as i32;
+    ctx.print_color(13, line, RGB::named(rltk::WHITE), bg, "Run in a direction");
+    ctx.print_color(40, line, RGB::named(rltk::CYAN), bg, "Shift + move");
+    ctx.print_color(13, y + height, RGB::named(rltk::YELLOW), bg, "Escape to close");
+
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => HelpResult::Close,
+        Some(key) if keybindings.action_for(key) == Some(Action::Help) => HelpResult::Close,
+        _ => HelpResult::NoSelection,
+    }
+}
+
+/// Up and down pick a setting, left, right and Enter change it. Every change is saved straight away.
+pub fn show_options(ctx: &mut Rltk, settings: &mut Settings, selection: usize) -> OptionsResult {
+    let bg = RGB::named(rltk::BLACK);
+    let fields = OptionField::ALL;
+    let height = fields.len() as i32 + 5;
+    let y = (50 - height) / 2;
+
+    ctx.draw_box_double(12, y, 55, height, RGB::named(rltk::WHEAT), bg);
+    ctx.print_color_centered(y, RGB::named(rltk::YELLOW), bg, "Options");
+    for (row, field) in fields.iter().enumerate() {
+        let line = y + 2 + row as i32;
+        let color = if row == selection {
+            RGB::named(rltk::MAGENTA)
+        } else {
+            RGB::named(rltk::WHITE)
+        };
+        ctx.print_color(15, line, color, bg, field.label());
+        ctx.print_color(44, line, RGB::named(rltk::CYAN), bg, field.value(settings));
+    }
+    ctx.print_color(15, y + height - 2, RGB::named(rltk::GRAY), bg, "* applies after a restart");
+    ctx.print_color_centered(y + height, RGB::named(rltk::YELLOW), bg, "Left/Right: change, Esc: back");
+
+    let count = fields.len();
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => OptionsResult::Close,
+        Some(VirtualKeyCode::Up) => OptionsResult::NoSelection {
+            selection: (selection + count - 1) % count,
+        },
+        Some(VirtualKeyCode::Down) => OptionsResult::NoSelection {
+            selection: (selection + 1) % count,
+        },
+        Some(VirtualKeyCode::Right) | Some(VirtualKeyCode::Return) => {
+            fields[selection].change(settings, true);
+            OptionsResult::Changed { selection }
+        }
+        Some(VirtualKeyCode::Left) => {
+            fields[selection].change(settings, false);
+            OptionsResult::Changed { selection }
+        }
+        _ => OptionsResult::NoSelection { selection },
+    }
+}
+
+pub fn show_load_game(ctx: &mut Rltk, slots: &[SaveMetadata], selection: usize) -> LoadGameResult {
+    let bg = RGB::named(rltk::BLACK);
+    let header = RGB::named(rltk::YELLOW);
+
+    ctx.draw_box_double(0, 0, 79, 49, RGB::named(rltk::WHEAT), bg);
+    ctx.print_color_centered(1, header, bg, "Load Game");
+    ctx.print_color(
+        2,
+        3,
+        header,
+        bg,
+        format!(
+            "{:<16} {:>5} {:>6}  {:<20} {:>20}",
+            "Character", "Depth", "Turn", "Saved", "Seed"
+        ),
+    );
+
+    if slots.is_empty() {
+        ctx.print_color_centered(5, RGB::named(rltk::GRAY), bg, "No saved games");
+    }
+    for (row, slot) in slots.iter().take(42).enumerate() {
+        let mut name = slot.character_name.clone();
+        name.truncate(16);
+        ctx.print_color(
+            2,
+            5 + row as i32,
+            if row == selection {
+                RGB::named(rltk::MAGENTA)
+            } else {
+                RGB::named(rltk::WHITE)
+            },
+            bg,
+            format!(
+                "{:<16} {:>5} {:>6}  {:<20} {:>20}",
+                name,
+                slot.depth,
+                slot.turn,
+                format_timestamp(slot.timestamp),
+                slot.seed
+            ),
+        );
+    }
+
+    ctx.print_color_centered(
+        48,
+        RGB::named(rltk::MAGENTA),
+        bg,
+        "Enter: load, Delete: remove slot, Esc: back",
+    );
+
+    let count = usize::min(slots.len(), 42);
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => LoadGameResult::Cancel,
+        _ if count == 0 => LoadGameResult::NoSelection { selection: 0 },
+        Some(VirtualKeyCode::Up) => LoadGameResult::NoSelection {
+            selection: (selection + count - 1) % count,
+        },
+        Some(VirtualKeyCode::Down) => LoadGameResult::NoSelection {
+            selection: (selection + 1) % count,
+        },
+        Some(VirtualKeyCode::Return) => LoadGameResult::Load { selection },
+        Some(VirtualKeyCode::Delete) => LoadGameResult::Delete { selection },
+        _ => LoadGameResult::NoSelection { selection },
+    }
+}
+
+pub fn show_hall_of_fame(ctx: &mut Rltk, table: &ScoreTable, sort: ScoreSort) -> HallOfFameResult {
+    let bg = RGB::named(rltk::BLACK);
+    let header = RGB::named(rltk::YELLOW);
+    let text = RGB::named(rltk::WHITE);
+
+    ctx.draw_box_double(0, 0, 79, 49, RGB::named(rltk::WHEAT), bg);
+    ctx.print_color_centered(1, header, bg, "Hall of Fame");
+    ctx.print_color(
+        2,
+        3,
+        header,
+        bg,
+        format!(
+            "{:<20} {:>20} {:>6} {:>5} {:>5} {:<2} Cause of death",
+            "Date", "Seed", "Score", "Depth", "Kills", ""
+        ),
+    );
+
+    let entries = table.sorted(sort);
+    if entries.is_empty() {
+        ctx.print_color_centered(5, RGB::named(rltk::GRAY), bg, "No runs recorded yet");
+    }
+    for (row, entry) in entries.iter().take(42).enumerate() {
+        let mut cause = entry.cause_of_death.clone();
+        cause.truncate(16);
+        ctx.print_color(
+            2,
+            5 + row as i32,
+            text,
+            bg,
+            format!(
+                "{:<20} {:>20} {:>6} {:>5} {:>5} {:<2} {}",
+                format_timestamp(entry.timestamp),
+                entry.seed,
+                entry.score,
+                entry.deepest_depth,
+                entry.kills,
+                entry.flags(),
+                cause
+            ),
+        );
+    }
+
+    ctx.print_color_centered(
+        48,
+        RGB::named(rltk::MAGENTA),
+        bg,
+        format!("Sorted by {} - H: hardcore, !: restored save - Tab: sort, Esc: back", sort.label()),
+    );
+
+    match ctx.key {
+        Some(VirtualKeyCode::Tab) => HallOfFameResult::NoSelection { sort: sort.next() },
+        Some(VirtualKeyCode::Escape) | Some(VirtualKeyCode::Return) => HallOfFameResult::Close,
+        _ => HallOfFameResult::NoSelection { sort },
+    }
+}
+
 fn process_item_selection(
     ctx: &mut Rltk,
     count: usize,
@@ -393,153 +858,405 @@ fn draw_title_box(title_text: String, ctx: &mut Rltk, count: usize, y: i32) {
     );
 }
 
-fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
-    let map = ecs.fetch::<Map>();
+enum TooltipLine {
+    Text(String, RGB),
+    Health { hp: i32, max_hp: i32 },
+}
+
+impl TooltipLine {
+    fn width(&self) -> usize {
+        match self {
+            TooltipLine::Text(text, _) => text.chars().count(),
+            TooltipLine::Health { .. } => 20,
+        }
+    }
+}
+
+/// What an item (or trap) does, one short phrase per effect.
+pub fn item_properties(ecs: &World, entity: Entity) -> Vec<String> {
+    let mut properties = Vec::new();
+    if let Some(heal) = ecs.read_storage::<ProvidesHealing>().get(entity) {
+        properties.push(format!("Heals {} HP", heal.heal_amount));
+    }
+    if let Some(damage) = ecs.read_storage::<InflictsDamage>().get(entity) {
+        properties.push(format!("Deals {} damage", damage.damage));
+    }
+    if let Some(ranged) = ecs.read_storage::<Ranged>().get(entity) {
+        properties.push(format!("Range {}", ranged.range));
+    }
+    if let Some(aoe) = ecs.read_storage::<AreaOfEffect>().get(entity) {
+        properties.push(format!("Blast radius {}", aoe.radius));
+    }
+    if let Some(bonus) = ecs.read_storage::<MeleePowerBonus>().get(entity) {
+        properties.push(format!("+{} attack", bonus.power));
+    }
+    if let Some(bonus) = ecs.read_storage::<DefenseBonus>().get(entity) {
+        properties.push(format!("+{} block", bonus.defense));
+    }
+    if ecs.read_storage::<ProvidesFood>().get(entity).is_some() {
+        properties.push(format!(
+            "Edible, restores {} HP",
+            crate::inventory_system::FOOD_HEAL_AMOUNT
+        ));
+    }
+    if ecs.read_storage::<MagicMapper>().get(entity).is_some() {
+        properties.push("Reveals the level".to_string());
+    }
+    if ecs.read_storage::<Key>().get(entity).is_some() {
+        properties.push("Opens a locked door".to_string());
+    }
+    if ecs.read_storage::<Consumable>().get(entity).is_some() {
+        properties.push("Used up on use".to_string());
+    }
+    properties
+}
+
+/// Everything the player can tell about an entity by looking at it.
+fn describe_entity(ecs: &World, entity: Entity, lines: &mut Vec<TooltipLine>) {
     let names = ecs.read_storage::<Name>();
+    let stats = ecs.read_storage::<CombatStats>();
+    let items = ecs.read_storage::<Item>();
+    let equipped = ecs.read_storage::<Equipped>();
+    let entities = ecs.entities();
+
+    let grey = RGB::named(rltk::LIGHT_GRAY);
+    let name = match names.get(entity) {
+        Some(name) => name.name.clone(),
+        None => return,
+    };
+    let name_color = if stats.get(entity).is_some() {
+        RGB::named(rltk::YELLOW)
+    } else if items.get(entity).is_some() {
+        RGB::named(rltk::CYAN)
+    } else {
+        RGB::named(rltk::WHITE)
+    };
+    lines.push(TooltipLine::Text(name, name_color));
+
+    if let Some(stats) = stats.get(entity) {
+        lines.push(TooltipLine::Health {
+            hp: stats.hp,
+            max_hp: stats.max_hp,
+        });
+        lines.push(TooltipLine::Text(
+            format!("Attack {}, block {}", stats.attack_power, stats.block),
+            grey,
+        ));
+    }
+    if let Some(confusion) = ecs.read_storage::<Confusion>().get(entity) {
+        if stats.get(entity).is_some() {
+            lines.push(TooltipLine::Text(
+                format!("Confused ({} turns)", confusion.turns),
+                RGB::named(rltk::MAGENTA),
+            ));
+        } else {
+            lines.push(TooltipLine::Text(format!("Confuses for {} turns", confusion.turns), grey));
+        }
+    }
+    if ecs.read_storage::<Bystander>().get(entity).is_some() {
+        lines.push(TooltipLine::Text("Peaceful".to_string(), grey));
+    }
+    if ecs.read_storage::<Vendor>().get(entity).is_some() {
+        lines.push(TooltipLine::Text("Trader".to_string(), grey));
+    }
+    for (item, held) in (&entities, &equipped).join() {
+        if held.owner != entity {
+            continue;
+        }
+        if let Some(item_name) = names.get(item) {
+            let verb = match held.slot {
+                EquipmentSlot::Melee => "Wielding",
+                EquipmentSlot::Shield => "Holding",
+            };
+            lines.push(TooltipLine::Text(format!("{} {}", verb, item_name.name), grey));
+        }
+    }
+
+    for property in item_properties(ecs, entity) {
+        lines.push(TooltipLine::Text(property, grey));
+    }
+    if let Some(door) = ecs.read_storage::<Door>().get(entity) {
+        let state = if ecs.read_storage::<Locked>().get(entity).is_some() {
+            "Locked"
+        } else if door.open {
+            "Open"
+        } else {
+            "Closed"
+        };
+        lines.push(TooltipLine::Text(state.to_string(), grey));
+    }
+    if ecs.read_storage::<EntryTrigger>().get(entity).is_some() {
+        lines.push(TooltipLine::Text("Trap".to_string(), RGB::named(rltk::RED)));
+    }
+}
+
+/// Describes every visible entity on the map tile under `screen`, in a box beside it.
+fn draw_tooltips(ecs: &World, ctx: &mut Rltk, screen: Point) {
+    let map = ecs.fetch::<Map>();
     let positions = ecs.read_storage::<Position>();
     let hidden = ecs.read_storage::<Hidden>();
+    let entities = ecs.entities();
 
-    let mouse_pos = ctx.mouse_pos();
-    if mouse_pos.0 >= map.width || mouse_pos.1 >= map.height {
+    let bounds = camera::get_screen_bounds(ecs);
+    if !bounds.contains_screen(screen) {
         return;
     }
-
-    let mut tooltip: Vec<String> = Vec::new();
-    for (name, position, _h) in (&names, &positions, &hidden).join() {
-        let idx = map.xy_idx(position.x, position.y);
-        if position.x == mouse_pos.0 && position.y == mouse_pos.1 && map.visible_tiles[idx] {
-            tooltip.push(name.name.to_string());
-        }
+    let world = bounds.to_world(screen);
+    if world.x < 0 || world.x >= map.width || world.y < 0 || world.y >= map.height {
+        return;
+    }
+    if !map.visible_tiles[map.xy_idx(world.x, world.y)] {
+        return;
     }
 
-    if !tooltip.is_empty() {
-        let mut width: i32 = 0;
-        for s in tooltip.iter() {
-            if width < s.len() as i32 {
-                width = s.len() as i32;
+    let mut lines: Vec<TooltipLine> = Vec::new();
+    for (entity, position, _hidden) in (&entities, &positions, !&hidden).join() {
+        if position.x == world.x && position.y == world.y {
+            if !lines.is_empty() {
+                lines.push(TooltipLine::Text(String::new(), RGB::named(rltk::WHITE)));
             }
+            describe_entity(ecs, entity, &mut lines);
         }
+    }
+    if lines.is_empty() {
+        return;
+    }
 
-        width += 3;
+    let fg = RGB::named(rltk::WHITE);
+    let bg = RGB::named(rltk::DIM_GRAY);
+    let width = lines.iter().map(TooltipLine::width).max().unwrap_or(0) as i32 + 2;
+    let height = lines.len() as i32;
+    let (left_x, arrow_x, arrow) = if screen.x > camera::VIEW_WIDTH / 2 {
+        (screen.x - 1 - width, screen.x - 1, '→')
+    } else {
+        (screen.x + 2, screen.x + 1, '←')
+    };
+    ctx.set(arrow_x, screen.y, fg, bg, rltk::to_cp437(arrow));
+    let top_y = i32::max(0, i32::min(screen.y, camera::VIEW_HEIGHT - height));
 
-        if mouse_pos.0 > 40 {
-            let arrow_pos = Point::new(mouse_pos.0 - 2, mouse_pos.1);
-            let left_x = mouse_pos.0 - width;
-            let mut y = mouse_pos.1;
-            for s in tooltip.iter() {
-                ctx.print_color(
-                    left_x,
+    for (row, line) in lines.iter().enumerate() {
+        let y = top_y + row as i32;
+        ctx.print_color(left_x, y, fg, bg, " ".repeat(width as usize));
+        match line {
+            TooltipLine::Text(text, color) => ctx.print_color(left_x + 1, y, *color, bg, text),
+            TooltipLine::Health { hp, max_hp } => {
+                let label = format!("HP {}/{}", hp, max_hp);
+                ctx.print_color(left_x + 1, y, fg, bg, &label);
+                let bar_x = left_x + 2 + label.len() as i32;
+                ctx.draw_bar_horizontal(
+                    bar_x,
                     y,
-                    RGB::named(rltk::WHITE),
-                    RGB::named(rltk::GRAY),
-                    s,
+                    left_x + width - 1 - bar_x,
+                    *hp,
+                    *max_hp,
+                    settings::palette().adjust(RGB::named(rltk::RED)),
+                    RGB::named(rltk::BLACK),
                 );
-                let padding = (width - s.len() as i32) - 1;
-                for i in 0..padding {
-                    ctx.print_color(
-                        arrow_pos.x - i,
-                        y,
-                        RGB::named(rltk::WHITE),
-                        RGB::named(rltk::GRAY),
-                        &" ".to_string(),
-                    );
-                }
-
-                y += 1;
             }
+        }
+    }
+}
 
-            ctx.print_color(
-                arrow_pos.x,
-                arrow_pos.y,
-                RGB::named(rltk::WHITE),
-                RGB::named(rltk::GRAY),
-                &"->".to_string(),
-            )
-        } else {
-            let arrow_pos = Point::new(mouse_pos.0 + 1, mouse_pos.1);
-            let left_x = mouse_pos.0 + 3;
-            let mut y = mouse_pos.1;
-            for s in tooltip.iter() {
-                ctx.print_color(
-                    left_x + 1,
-                    y,
-                    RGB::named(rltk::WHITE),
-                    RGB::named(rltk::GRAY),
-                    s,
-                );
-                let padding = (width - s.len() as i32) - 1;
-                for i in 0..padding {
-                    ctx.print_color(
-                        arrow_pos.x + 1 + i,
-                        y,
-                        RGB::named(rltk::WHITE),
-                        RGB::named(rltk::GRAY),
-                        &" ".to_string(),
-                    );
-                }
+#[derive(PartialEq, Copy, Clone)]
+pub enum LookResult {
+    NoSelection { x: i32, y: i32 },
+    Close,
+}
+
+/// Moves a cursor over the map with the movement keys and describes whatever is under it.
+pub fn look_mode(gs: &mut State, ctx: &mut Rltk, x: i32, y: i32) -> LookResult {
+    let bounds = camera::get_screen_bounds(&gs.ecs);
+    let screen = bounds.to_screen(Point::new(x, y));
+
+    ctx.print_color(
+        5,
+        0,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Look around - movement keys move the cursor, Escape to stop",
+    );
+    ctx.set_bg(screen.x, screen.y, RGB::named(rltk::MAGENTA));
+    draw_tooltips(&gs.ecs, ctx, screen);
 
-                y += 1;
+    let action = match ctx.key {
+        None => return LookResult::NoSelection { x, y },
+        Some(VirtualKeyCode::Escape) => return LookResult::Close,
+        Some(key) => gs.keybindings.action_for(key),
+    };
+
+    match action.and_then(Action::direction) {
+        Some((dx, dy)) => {
+            let map = gs.ecs.fetch::<Map>();
+            let next = Point::new(x + dx, y + dy);
+            let on_screen = bounds.contains_screen(bounds.to_screen(next));
+            if on_screen && next.x >= 0 && next.x < map.width && next.y >= 0 && next.y < map.height {
+                LookResult::NoSelection { x: next.x, y: next.y }
+            } else {
+                LookResult::NoSelection { x, y }
             }
-            ctx.print_color(
-                arrow_pos.x,
-                arrow_pos.y,
-                RGB::named(rltk::WHITE),
-                RGB::named(rltk::GRAY),
-                &"<-".to_string(),
-            );
         }
+        None if action == Some(Action::Look) => LookResult::Close,
+        None => LookResult::NoSelection { x, y },
     }
 }
 
+/// Picks a target for a ranged item. Tab cycles through visible hostiles in range, nearest
+/// first, the movement keys nudge the reticle, Enter or a left click fires and Escape cancels.
+/// The blast of an area-of-effect item is shown around the reticle.
 pub fn ranged_target(
     gs: &mut State,
     ctx: &mut Rltk,
     range: i32,
+    item: Entity,
 ) -> (ItemMenuResult, Option<Point>) {
-    let player_entity = gs.ecs.fetch::<Entity>();
-    let player_pos = gs.ecs.fetch::<Point>();
-    let viewsheds = gs.ecs.read_storage::<Viewshed>();
+    let bounds = camera::get_screen_bounds(&gs.ecs);
+    let player_pos = *gs.ecs.fetch::<Point>();
 
     ctx.print_color(
         5,
         0,
         RGB::named(rltk::YELLOW),
         RGB::named(rltk::BLACK),
-        "Select Target",
+        "Select Target - Tab: next hostile, Enter: fire, Escape: cancel",
     );
 
-    let mut available_cells = Vec::new();
-    let visible = viewsheds.get(*player_entity);
-    if let Some(visible) = visible {
-        for i in visible.visible_tiles.iter() {
-            let distance = rltk::DistanceAlg::Pythagoras.distance2d(*player_pos, *i);
-            if distance <= range as f32 {
-                ctx.set_bg(i.x, i.y, RGB::named(rltk::BLUE));
-                available_cells.push(i);
+    let visible_cells: Option<Vec<Point>> = {
+        let player_entity = gs.ecs.fetch::<Entity>();
+        let viewsheds = gs.ecs.read_storage::<Viewshed>();
+        viewsheds.get(*player_entity).map(|visible| {
+            visible
+                .visible_tiles
+                .iter()
+                .filter(|tile| {
+                    rltk::DistanceAlg::Pythagoras.distance2d(player_pos, **tile) <= range as f32
+                })
+                .copied()
+                .collect()
+        })
+    };
+    let available_cells = match visible_cells {
+        Some(cells) => cells,
+        None => return finish_targeting(gs, ItemMenuResult::Cancel, None),
+    };
+    for cell in available_cells.iter() {
+        let screen = bounds.to_screen(*cell);
+        if bounds.contains_screen(screen) {
+            ctx.set_bg(screen.x, screen.y, RGB::named(rltk::BLUE));
+        }
+    }
+
+    let hostiles = hostiles_in_range(&gs.ecs, &available_cells, player_pos);
+    let cursor = match gs.target_cursor {
+        Some(cursor) => cursor,
+        None => {
+            let last = gs
+                .last_target
+                .and_then(|last| hostiles.iter().find(|(entity, _)| *entity == last));
+            match last.or_else(|| hostiles.first()) {
+                Some((_, pos)) => *pos,
+                None => player_pos,
             }
         }
+    };
+    gs.target_cursor = Some(cursor);
+
+    let valid_target = |point: Point| available_cells.contains(&point);
+
+    if let Some(aoe) = gs.ecs.read_storage::<AreaOfEffect>().get(item) {
+        let map = gs.ecs.fetch::<Map>();
+        for tile in rltk::field_of_view(cursor, aoe.radius, &*map).iter() {
+            let screen = bounds.to_screen(*tile);
+            if bounds.contains_screen(screen) && map.visible_tiles[map.xy_idx(tile.x, tile.y)] {
+                ctx.set_bg(screen.x, screen.y, RGB::named(rltk::DARK_ORANGE));
+            }
+        }
+    }
+
+    let reticle = bounds.to_screen(cursor);
+    if valid_target(cursor) {
+        ctx.set_bg(reticle.x, reticle.y, RGB::named(rltk::CYAN));
     } else {
-        return (ItemMenuResult::Cancel, None);
+        ctx.set_bg(reticle.x, reticle.y, RGB::named(rltk::RED));
     }
 
-    let (mouse_x, mouse_y) = ctx.mouse_pos();
-    let valid_target = available_cells
-        .iter()
-        .any(|idx| idx.x == mouse_x && idx.y == mouse_y);
+    if ctx.left_click {
+        let mouse_world = bounds.to_world(ctx.mouse_point());
+        return if valid_target(mouse_world) {
+            settings::log(LogLevel::Verbose, "Target acquired!");
+            finish_targeting(gs, ItemMenuResult::Selected, Some(mouse_world))
+        } else {
+            settings::log(LogLevel::Verbose, "No valid target");
+            finish_targeting(gs, ItemMenuResult::Cancel, None)
+        };
+    }
 
-    if valid_target {
-        ctx.set_bg(mouse_x, mouse_y, RGB::named(rltk::CYAN));
-        if ctx.left_click {
-            console::log("Target acquired!".to_string());
-            return (ItemMenuResult::Selected, Some(Point::new(mouse_x, mouse_y)));
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => finish_targeting(gs, ItemMenuResult::Cancel, None),
+        Some(VirtualKeyCode::Return) | Some(VirtualKeyCode::NumpadEnter) => {
+            if valid_target(cursor) {
+                finish_targeting(gs, ItemMenuResult::Selected, Some(cursor))
+            } else {
+                (ItemMenuResult::NoResponse, None)
+            }
         }
-    } else {
-        ctx.set_bg(mouse_x, mouse_y, RGB::named(rltk::RED));
-        if ctx.left_click {
-            console::log("No valid target".to_string());
-            return (ItemMenuResult::Cancel, None);
+        Some(VirtualKeyCode::Tab) => {
+            if !hostiles.is_empty() {
+                let next = match hostiles.iter().position(|(_, pos)| *pos == cursor) {
+                    Some(i) => (i + 1) % hostiles.len(),
+                    None => 0,
+                };
+                gs.target_cursor = Some(hostiles[next].1);
+            }
+            (ItemMenuResult::NoResponse, None)
+        }
+        Some(key) => {
+            let direction = gs.keybindings.action_for(key).and_then(Action::direction);
+            if let Some((dx, dy)) = direction {
+                let next = Point::new(cursor.x + dx, cursor.y + dy);
+                if bounds.contains_screen(bounds.to_screen(next)) {
+                    gs.target_cursor = Some(next);
+                }
+            }
+            (ItemMenuResult::NoResponse, None)
         }
+        None => (ItemMenuResult::NoResponse, None),
     }
+}
 
-    (ItemMenuResult::NoResponse, None)
+/// Visible monsters standing on `cells`, nearest to `from` first.
+fn hostiles_in_range(ecs: &World, cells: &[Point], from: Point) -> Vec<(Entity, Point)> {
+    let entities = ecs.entities();
+    let monsters = ecs.read_storage::<Monster>();
+    let positions = ecs.read_storage::<Position>();
+    let hidden = ecs.read_storage::<Hidden>();
+
+    let mut hostiles: Vec<(Entity, Point)> = (&entities, &monsters, &positions, !&hidden)
+        .join()
+        .map(|(entity, _monster, pos, _hidden)| (entity, Point::new(pos.x, pos.y)))
+        .filter(|(_, pos)| cells.contains(pos))
+        .collect();
+    hostiles.sort_by(|(_, a), (_, b)| {
+        let distance = |p: &Point| rltk::DistanceAlg::Pythagoras.distance2d(from, *p);
+        distance(a).total_cmp(&distance(b))
+    });
+    hostiles
+}
+
+/// Leaves targeting, remembering the monster that was fired at so the next ranged item starts
+/// on it.
+fn finish_targeting(
+    gs: &mut State,
+    result: ItemMenuResult,
+    target: Option<Point>,
+) -> (ItemMenuResult, Option<Point>) {
+    gs.target_cursor = None;
+    if let Some(target) = target {
+        let map = gs.ecs.fetch::<Map>();
+        let monsters = gs.ecs.read_storage::<Monster>();
+        let idx = map.xy_idx(target.x, target.y);
+        if let Some(entity) = map.tile_content[idx].iter().find(|e| monsters.get(**e).is_some()) {
+            gs.last_target = Some(*entity);
+        }
+    }
+    (result, target)
 }
@@ -0,0 +1,154 @@
+use crate::hardcore::RunInfo;
+use crate::morgue::RunSummary;
+use crate::save_load_system::{save_dir, write_atomically, SaveError};
+use crate::settings::load_versioned_json;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+const SCORES_FILE: &str = "scores.json";
+const SCORES_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ScoreEntry {
+    pub timestamp: u64,
+    pub seed: u64,
+    pub score: i32,
+    pub deepest_depth: i32,
+    pub kills: i32,
+    pub turns: i32,
+    pub cause_of_death: String,
+    #[serde(default)]
+    pub hardcore: bool,
+    /// The run was continued from a hardcore save that wasn't its latest one.
+    #[serde(default)]
+    pub restored_out_of_order: bool,
+}
+
+impl ScoreEntry {
+    /// "H" for hardcore runs, "H!" when a hardcore save was restored out of order.
+    pub fn flags(&self) -> &'static str {
+        match (self.hardcore, self.restored_out_of_order) {
+            (true, true) => "H!",
+            (true, false) => "H",
+            _ => "",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ScoreTable {
+    pub version: u32,
+    pub entries: Vec<ScoreEntry>,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum ScoreSort {
+    Score,
+    Date,
+    Depth,
+}
+
+impl ScoreSort {
+    pub fn next(self) -> ScoreSort {
+        match self {
+            ScoreSort::Score => ScoreSort::Date,
+            ScoreSort::Date => ScoreSort::Depth,
+            ScoreSort::Depth => ScoreSort::Score,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ScoreSort::Score => "score",
+            ScoreSort::Date => "date",
+            ScoreSort::Depth => "depth",
+        }
+    }
+}
+
+/// The game has no gold or experience yet, so the score rewards how deep the run went and how
+/// many monsters it killed, with a small bonus for surviving longer.
+pub fn score_for(summary: &RunSummary) -> i32 {
+    summary.deepest_depth * 100 + summary.total_kills * 10 + summary.turns / 10
+}
+
+fn scores_path() -> PathBuf {
+    save_dir().join(SCORES_FILE)
+}
+
+impl ScoreTable {
+    fn empty() -> ScoreTable {
+        ScoreTable {
+            version: SCORES_VERSION,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Reads the table, or `None` if there is a scores file that can't be used.
+    fn read() -> Option<ScoreTable> {
+        match load_versioned_json(&scores_path(), SCORES_VERSION) {
+            Ok(Some(table)) => Some(table),
+            Ok(None) => Some(ScoreTable::empty()),
+            Err(e) => {
+                rltk::console::log(format!("Unable to read scores file: {}", e));
+                None
+            }
+        }
+    }
+
+    pub fn load() -> ScoreTable {
+        ScoreTable::read().unwrap_or_else(ScoreTable::empty)
+    }
+
+    fn write(&self) -> Result<(), SaveError> {
+        fs::create_dir_all(save_dir())?;
+        write_atomically(&scores_path(), serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    pub fn save(&self) {
+        if let Err(e) = self.write() {
+            rltk::console::log(format!("Unable to write scores file: {}", e));
+        }
+    }
+
+    pub fn sorted(&self, sort: ScoreSort) -> Vec<ScoreEntry> {
+        let mut entries = self.entries.clone();
+        match sort {
+            ScoreSort::Score => entries.sort_by_key(|e| std::cmp::Reverse(e.score)),
+            ScoreSort::Date => entries.sort_by_key(|e| std::cmp::Reverse(e.timestamp)),
+            ScoreSort::Depth => {
+                entries.sort_by_key(|e| std::cmp::Reverse((e.deepest_depth, e.score)))
+            }
+        }
+        entries
+    }
+}
+
+pub fn record_run(summary: &RunSummary, run: &RunInfo, seed: u64, timestamp: u64) {
+    // A scores file that can't be read is moved aside rather than overwritten, so the runs in it
+    // aren't lost.
+    let mut table = match ScoreTable::read() {
+        Some(table) => table,
+        None => {
+            let backup = save_dir().join(format!("{}.bak", SCORES_FILE));
+            if let Err(e) = fs::rename(scores_path(), &backup) {
+                rltk::console::log(format!("Not recording the run, unable to back up scores: {}", e));
+                return;
+            }
+            ScoreTable::empty()
+        }
+    };
+    table.entries.push(ScoreEntry {
+        timestamp,
+        seed,
+        score: score_for(summary),
+        deepest_depth: summary.deepest_depth,
+        kills: summary.total_kills,
+        turns: summary.turns,
+        cause_of_death: summary.cause_of_death.clone(),
+        hardcore: run.hardcore,
+        restored_out_of_order: run.restored_out_of_order,
+    });
+    table.save();
+}
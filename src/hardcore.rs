@@ -0,0 +1,133 @@
+use crate::fresh_seed;
+use crate::save_load_system::{save_dir, write_atomically, SaveError};
+use crate::settings::load_versioned_json;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+
+const LEDGER_FILE: &str = "ledger.json";
+const LEDGER_VERSION: u32 = 1;
+
+/// Identifies the current run across saves. Stored in the save header rather than as a component.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RunInfo {
+    pub run_id: u64,
+    pub hardcore: bool,
+    pub save_sequence: u32,
+    /// Set when a hardcore save was loaded that wasn't the latest one written for its run.
+    pub restored_out_of_order: bool,
+}
+
+impl RunInfo {
+    pub fn new(hardcore: bool) -> RunInfo {
+        RunInfo {
+            run_id: fresh_seed(),
+            hardcore,
+            save_sequence: 0,
+            restored_out_of_order: false,
+        }
+    }
+}
+
+/// What the ledger remembers about one hardcore run: the last save written for it and whether the
+/// run has ended.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LedgerEntry {
+    pub sequence: u32,
+    pub checksum: String,
+    pub dead: bool,
+}
+
+/// Tracks hardcore runs outside of their save files, so copying a save elsewhere doesn't bring a
+/// dead character back.
+#[derive(Serialize, Deserialize)]
+pub struct Ledger {
+    pub version: u32,
+    pub runs: BTreeMap<String, LedgerEntry>,
+}
+
+pub enum LedgerCheck {
+    Current,
+    OutOfOrder,
+    RunEnded,
+}
+
+/// 64-bit FNV-1a, stable across builds unlike the standard library's hasher.
+pub fn checksum(data: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in data {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+fn run_key(run: &RunInfo) -> String {
+    format!("{:016x}", run.run_id)
+}
+
+impl Ledger {
+    /// A missing ledger is an empty one. One that can't be read is an error rather than empty,
+    /// so that saving never writes over the runs it records.
+    pub fn load() -> Result<Ledger, SaveError> {
+        match load_versioned_json(&save_dir().join(LEDGER_FILE), LEDGER_VERSION) {
+            Ok(Some(ledger)) => Ok(ledger),
+            Ok(None) => Ok(Ledger {
+                version: LEDGER_VERSION,
+                runs: BTreeMap::new(),
+            }),
+            Err(e) => Err(SaveError::Invalid(format!("unable to read the hardcore ledger: {}", e))),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), SaveError> {
+        fs::create_dir_all(save_dir())?;
+        write_atomically(
+            &save_dir().join(LEDGER_FILE),
+            serde_json::to_string_pretty(self)?.as_bytes(),
+        )
+    }
+
+    /// Compares a save that is about to be loaded against the last save written for its run.
+    pub fn check(&self, run: &RunInfo, checksum: &str) -> LedgerCheck {
+        match self.runs.get(&run_key(run)) {
+            Some(entry) if entry.dead => LedgerCheck::RunEnded,
+            Some(entry) if entry.sequence == run.save_sequence && entry.checksum == checksum => {
+                LedgerCheck::Current
+            }
+            Some(_) | None => LedgerCheck::OutOfOrder,
+        }
+    }
+}
+
+pub fn record_save(mut ledger: Ledger, run: &RunInfo, checksum: String) -> Result<(), SaveError> {
+    ledger.runs.insert(
+        run_key(run),
+        LedgerEntry {
+            sequence: run.save_sequence,
+            checksum,
+            dead: false,
+        },
+    );
+    ledger.save()
+}
+
+pub fn record_death(run: &RunInfo) {
+    let mut ledger = match Ledger::load() {
+        Ok(ledger) => ledger,
+        Err(e) => {
+            rltk::console::log(format!("Unable to record death in ledger: {}", e));
+            return;
+        }
+    };
+    let entry = ledger.runs.entry(run_key(run)).or_insert(LedgerEntry {
+        sequence: run.save_sequence,
+        checksum: String::new(),
+        dead: true,
+    });
+    entry.dead = true;
+
+    if let Err(e) = ledger.save() {
+        rltk::console::log(format!("Unable to record death in ledger: {}", e));
+    }
+}
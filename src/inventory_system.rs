@@ -1,12 +1,15 @@
 use crate::{
     AreaOfEffect, CombatStats, Confusion, Consumable, Entity, EquipmentSlot, Equippable, Equipped,
-    GameLog, InBackpack, InflictsDamage, MagicMapper, Map, Name, ParticleBuilder, Position,
-    ProvidesHealing, RunState, SufferDamage, WantsToRemoveItem, WantsToUseItem,
+    GameLog, InBackpack, InflictsDamage, LogCategory, MagicMapper, Map, Name, ParticleBuilder, Position,
+    ProvidesFood, ProvidesHealing, RunState, SufferDamage, WantsToRemoveItem, WantsToUseItem,
 };
 use rltk::RGB;
 use specs::prelude::*;
 use specs::world::EntitiesRes;
 
+/// How much health eating something restores.
+pub const FOOD_HEAL_AMOUNT: i32 = 2;
+
 pub struct UseItemSystem {}
 
 impl UseItemSystem {
@@ -19,7 +22,7 @@ impl UseItemSystem {
     ) -> bool {
         let is_mapper = magic_mappers.get(item_to_use.item);
         if is_mapper.is_some() {
-            log.entries.push("All is revealed to you!".to_string());
+            log.add(LogCategory::Item, "All is revealed to you!");
             **run_state = RunState::MagicMapReveal { row: 0 };
 
             return true;
@@ -52,6 +55,7 @@ impl<'a> System<'a> for UseItemSystem {
         ReadStorage<'a, Position>,
         ReadStorage<'a, MagicMapper>,
         WriteExpect<'a, RunState>,
+        ReadStorage<'a, ProvidesFood>,
     );
 
     #[allow(clippy::cognitive_complexity)]
@@ -77,6 +81,7 @@ impl<'a> System<'a> for UseItemSystem {
             positions,
             magic_mappers,
             mut run_state,
+            food,
         ) = data;
 
         for (entity, item_to_use) in (&entities, &wants_to_use_item).join() {
@@ -123,6 +128,17 @@ impl<'a> System<'a> for UseItemSystem {
                 &mut particle_builder,
             );
 
+            used_item |= self.process_food_actions(
+                &player_entity,
+                &mut log,
+                &names,
+                &mut combat_stats,
+                &food,
+                &entity,
+                item_to_use,
+                &targets,
+            );
+
             used_item |= self.process_damage_actions(
                 &player_entity,
                 &mut log,
@@ -149,7 +165,7 @@ impl<'a> System<'a> for UseItemSystem {
             );
 
             if used_item {
-                self.process_consumables(&entities, &consumables, item_to_use)
+                self.process_consumables(&entities, &consumables, &food, item_to_use)
             }
         }
 
@@ -189,6 +205,7 @@ impl UseItemSystem {
                         blast_tiles.retain(|p| {
                             p.x > 0 && p.x < map.width - 1 && p.y > 0 && p.y < map.height - 1
                         });
+                        blast_tiles.sort_by_key(|p| (p.y, p.x));
 
                         for tile_index in blast_tiles.iter() {
                             let index = map.xy_idx(tile_index.x, tile_index.y);
@@ -233,15 +250,26 @@ impl UseItemSystem {
         match damaging_item {
             None => {}
             Some(damage) => {
+                let item_name = names.get(item_to_use.item).unwrap();
                 for mob in targets.iter() {
-                    SufferDamage::new_damage(&mut suffer_damage, *mob, damage.damage);
+                    SufferDamage::new_damage(
+                        &mut suffer_damage,
+                        *mob,
+                        damage.damage,
+                        &item_name.name,
+                        entity == player_entity,
+                    );
                     if entity == player_entity {
                         let mob_name = names.get(*mob).unwrap();
-                        let item_name = names.get(item_to_use.item).unwrap();
-                        log.entries.push(format!(
-                            "You use {} on {} and inflict {} damage",
-                            item_name.name, mob_name.name, damage.damage
-                        ));
+                        log.entry(LogCategory::Combat)
+                            .text("You use ")
+                            .item(&item_name.name)
+                            .text(" on ")
+                            .name(&mob_name.name)
+                            .text(" and inflict ")
+                            .damage(damage.damage)
+                            .text(" damage")
+                            .log();
                     }
 
                     let pos = positions.get(*mob);
@@ -267,10 +295,11 @@ impl UseItemSystem {
         &self,
         entities: &EntitiesRes,
         consumables: &ReadStorage<Consumable>,
+        food: &ReadStorage<ProvidesFood>,
         item_to_use: &WantsToUseItem,
     ) {
         let consumable = consumables.get(item_to_use.item);
-        if consumable.is_some() {
+        if consumable.is_some() || food.get(item_to_use.item).is_some() {
             entities
                 .delete(item_to_use.item)
                 .expect("Unable to delete used item");
@@ -303,11 +332,13 @@ impl UseItemSystem {
                         Some(s) => {
                             s.hp = i32::min(s.max_hp, s.hp + heal.heal_amount);
                             if entity == player_entity {
-                                log.entries.push(format!(
-                                    "You drink the {}, healing {} HP",
-                                    names.get(item_to_use.item).unwrap().name,
-                                    heal.heal_amount
-                                ));
+                                log.entry(LogCategory::Item)
+                                    .text("You drink the ")
+                                    .item(&names.get(item_to_use.item).unwrap().name)
+                                    .text(", healing ")
+                                    .healing(heal.heal_amount)
+                                    .text(" HP")
+                                    .log();
                             }
                             used_item = true;
 
@@ -329,6 +360,41 @@ impl UseItemSystem {
         }
         used_item
     }
+
+    #[allow(clippy::too_many_arguments)]
+    fn process_food_actions(
+        &self,
+        player_entity: &Entity,
+        log: &mut GameLog,
+        names: &ReadStorage<Name>,
+        combat_stats: &mut WriteStorage<CombatStats>,
+        food: &ReadStorage<ProvidesFood>,
+        entity: &Entity,
+        item_to_use: &WantsToUseItem,
+        targets: &[Entity],
+    ) -> bool {
+        if food.get(item_to_use.item).is_none() {
+            return false;
+        }
+
+        let mut used_item = false;
+        for target in targets.iter() {
+            if let Some(stats) = combat_stats.get_mut(*target) {
+                stats.hp = i32::min(stats.max_hp, stats.hp + FOOD_HEAL_AMOUNT);
+                if entity == player_entity {
+                    log.entry(LogCategory::Item)
+                        .text("You eat the ")
+                        .item(&names.get(item_to_use.item).unwrap().name)
+                        .text(", recovering ")
+                        .healing(FOOD_HEAL_AMOUNT)
+                        .text(" HP")
+                        .log();
+                }
+                used_item = true;
+            }
+        }
+        used_item
+    }
 }
 
 impl UseItemSystem {
@@ -356,10 +422,13 @@ impl UseItemSystem {
                     if entity == player_entity {
                         let mob_name = names.get(*mob).unwrap();
                         let item_name = names.get(item_to_use.item).unwrap();
-                        log.entries.push(format!(
-                            "You confused {} by using {} on them",
-                            mob_name.name, item_name.name
-                        ));
+                        log.entry(LogCategory::Combat)
+                            .text("You confused ")
+                            .name(&mob_name.name)
+                            .text(" by using ")
+                            .item(&item_name.name)
+                            .text(" on them")
+                            .log();
                     }
                     used_item = true;
 
@@ -429,7 +498,7 @@ impl UseItemSystem {
             if already_equipped.owner == target && already_equipped.slot == target_slot {
                 unequip.push(item_entity);
                 if target == *player_entity {
-                    log.entries.push(format!("You unequip {}", name.name));
+                    log.entry(LogCategory::Item).text("You unequip ").item(&name.name).log();
                 }
             }
         }
@@ -487,10 +556,10 @@ impl UseItemSystem {
                 backpack.remove(item_to_use.item);
 
                 if target == *player_entity {
-                    log.entries.push(format!(
-                        "You equip {}",
-                        names.get(item_to_use.item).unwrap().name
-                    ))
+                    log.entry(LogCategory::Item)
+                        .text("You equip ")
+                        .item(&names.get(item_to_use.item).unwrap().name)
+                        .log();
                 }
             }
         }
@@ -1,5 +1,5 @@
 use specs::prelude::*;
-use super::{WantsToPickupItem, Name, InBackpack, Position, GameLog};
+use super::{WantsToPickupItem, Name, InBackpack, Position, GameLog, LogCategory};
 
 pub struct ItemCollectionSystem {}
 
@@ -22,7 +22,7 @@ impl<'a> System<'a> for ItemCollectionSystem {
             backpack.insert(pickup.item, InBackpack { owner: pickup.collected_by }).expect("Unable to add item to backpack!");
 
             if pickup.collected_by == *player_entity {
-                game_log.entries.push(format!("You pick up the {}", names.get(pickup.item).unwrap().name));
+                game_log.entry(LogCategory::Item).text("You pick up the ").item(&names.get(pickup.item).unwrap().name).log();
             }
         }
 
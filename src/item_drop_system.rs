@@ -1,4 +1,4 @@
-use super::{Entity, GameLog, InBackpack, Name, Position, WantsToDropItem};
+use super::{Entity, GameLog, InBackpack, LogCategory, Name, Position, WantsToDropItem};
 use specs::prelude::*;
 
 pub struct ItemDropSystem {}
@@ -46,10 +46,11 @@ impl<'a> System<'a> for ItemDropSystem {
                 .expect("Unable to remove item from backpack");
 
             if entity == *player_entity {
-                log.entries.push(format!(
-                    "You drop the {}.",
-                    names.get(to_drop.item).unwrap().name
-                ));
+                log.entry(LogCategory::Item)
+                    .text("You drop the ")
+                    .item(&names.get(to_drop.item).unwrap().name)
+                    .text(".")
+                    .log();
             }
         }
 
@@ -0,0 +1,309 @@
+use crate::settings::load_versioned_json;
+use rltk::VirtualKeyCode;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const CONFIG_DIR_ENV: &str = "DEATHLIKE_CONFIG_DIR";
+const KEYBINDINGS_FILE: &str = "keybindings.json";
+const KEYBINDINGS_VERSION: u32 = 1;
+
+/// Everything the player can ask for from the map view. Menus keep their own fixed keys.
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug, Hash)]
+pub enum Action {
+    MoveN,
+    MoveS,
+    MoveW,
+    MoveE,
+    MoveNW,
+    MoveNE,
+    MoveSW,
+    MoveSE,
+    Wait,
+    Rest,
+    PickUp,
+    Inventory,
+    Drop,
+    RemoveItem,
+    CloseDoor,
+    Descend,
+    Ascend,
+    AutoExplore,
+    TravelToStairs,
+    Help,
+    Look,
+    MessageLog,
+    Overview,
+    Encyclopedia,
+    ToggleGraphics,
+    SaveAndQuit,
+    AbandonRun,
+}
+
+impl Action {
+    pub const ALL: [Action; 27] = [
+        Action::MoveN,
+        Action::MoveS,
+        Action::MoveW,
+        Action::MoveE,
+        Action::MoveNW,
+        Action::MoveNE,
+        Action::MoveSW,
+        Action::MoveSE,
+        Action::Wait,
+        Action::Rest,
+        Action::PickUp,
+        Action::Inventory,
+        Action::Drop,
+        Action::RemoveItem,
+        Action::CloseDoor,
+        Action::Descend,
+        Action::Ascend,
+        Action::AutoExplore,
+        Action::TravelToStairs,
+        Action::Help,
+        Action::Look,
+        Action::MessageLog,
+        Action::Overview,
+        Action::Encyclopedia,
+        Action::ToggleGraphics,
+        Action::SaveAndQuit,
+        Action::AbandonRun,
+    ];
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::MoveN => "Move north",
+            Action::MoveS => "Move south",
+            Action::MoveW => "Move west",
+            Action::MoveE => "Move east",
+            Action::MoveNW => "Move north-west",
+            Action::MoveNE => "Move north-east",
+            Action::MoveSW => "Move south-west",
+            Action::MoveSE => "Move south-east",
+            Action::Wait => "Wait a turn",
+            Action::Rest => "Rest until healed or disturbed",
+            Action::PickUp => "Pick up an item",
+            Action::Inventory => "Use an item",
+            Action::Drop => "Drop an item",
+            Action::RemoveItem => "Unequip an item",
+            Action::CloseDoor => "Close an adjacent door",
+            Action::Descend => "Go down stairs",
+            Action::Ascend => "Go up stairs",
+            Action::AutoExplore => "Explore automatically",
+            Action::TravelToStairs => "Travel to the stairs down",
+            Action::Help => "Show this help",
+            Action::Look => "Look around the map",
+            Action::MessageLog => "Show the message log",
+            Action::Overview => "Show the whole level",
+            Action::Encyclopedia => "Show the encyclopedia",
+            Action::ToggleGraphics => "Switch between ASCII and tiles",
+            Action::SaveAndQuit => "Save and quit to the menu",
+            Action::AbandonRun => "Abandon the run",
+        }
+    }
+
+    /// The step a movement action takes, for anything that moves a cursor or the player.
+    pub fn direction(self) -> Option<(i32, i32)> {
+        match self {
+            Action::MoveN => Some((0, -1)),
+            Action::MoveS => Some((0, 1)),
+            Action::MoveW => Some((-1, 0)),
+            Action::MoveE => Some((1, 0)),
+            Action::MoveNW => Some((-1, -1)),
+            Action::MoveNE => Some((1, -1)),
+            Action::MoveSW => Some((-1, 1)),
+            Action::MoveSE => Some((1, 1)),
+            _ => None,
+        }
+    }
+
+    fn default_keys(self) -> Vec<VirtualKeyCode> {
+        use VirtualKeyCode::*;
+        match self {
+            Action::MoveN => vec![Up, K, Numpad8],
+            Action::MoveS => vec![Down, J, Numpad2],
+            Action::MoveW => vec![Left, H, Numpad4],
+            Action::MoveE => vec![Right, L, Numpad6],
+            Action::MoveNW => vec![Y, Numpad7],
+            Action::MoveNE => vec![U, Numpad9],
+            Action::MoveSW => vec![B, Numpad1],
+            Action::MoveSE => vec![N, Numpad3],
+            Action::Wait => vec![Numpad5, Space],
+            Action::Rest => vec![Z],
+            Action::PickUp => vec![G],
+            Action::Inventory => vec![I],
+            Action::Drop => vec![D],
+            Action::RemoveItem => vec![R],
+            Action::CloseDoor => vec![C],
+            Action::Descend => vec![Period],
+            Action::Ascend => vec![Comma],
+            Action::AutoExplore => vec![O],
+            Action::TravelToStairs => vec![T],
+            Action::Help => vec![F1, Slash],
+            Action::Look => vec![X],
+            Action::MessageLog => vec![M],
+            Action::Overview => vec![Tab],
+            Action::Encyclopedia => vec![E],
+            Action::ToggleGraphics => vec![F2],
+            Action::SaveAndQuit => vec![Escape],
+            Action::AbandonRun => vec![F12],
+        }
+    }
+}
+
+/// Keys that can be named in the bindings file, by their `VirtualKeyCode` variant name.
+const NAMED_KEYS: [VirtualKeyCode; 85] = {
+    use VirtualKeyCode::*;
+    [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, Key0, Key1,
+        Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Numpad0, Numpad1, Numpad2, Numpad3,
+        Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9, F1, F2, F3, F4, F5, F6, F7, F8, F9,
+        F10, F11, F12, Up, Down, Left, Right, Home, End, PageUp, PageDown, Insert, Delete, Escape,
+        Return, Space, Tab, Back, Period, Comma, Slash, Backslash, Semicolon, Apostrophe, Minus,
+        Equals, LBracket, RBracket, Grave, NumpadEnter,
+    ]
+};
+
+pub fn key_name(key: VirtualKeyCode) -> String {
+    format!("{:?}", key)
+}
+
+fn parse_key(name: &str) -> Option<VirtualKeyCode> {
+    NAMED_KEYS.iter().copied().find(|key| key_name(*key) == name)
+}
+
+/// Bindings live in the platform config directory unless `DEATHLIKE_CONFIG_DIR` points
+/// somewhere else.
+pub fn config_dir() -> PathBuf {
+    if let Some(dir) = env::var_os(CONFIG_DIR_ENV) {
+        return PathBuf::from(dir);
+    }
+
+    match dirs::config_dir() {
+        Some(dir) => dir.join("deathlike"),
+        None => PathBuf::from("."),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyBindingsFile {
+    version: u32,
+    bindings: BTreeMap<Action, Vec<String>>,
+}
+
+pub struct KeyBindings {
+    bindings: BTreeMap<Action, Vec<VirtualKeyCode>>,
+    lookup: HashMap<VirtualKeyCode, Action>,
+}
+
+impl KeyBindings {
+    pub fn defaults() -> KeyBindings {
+        KeyBindings::from_bindings(
+            Action::ALL
+                .iter()
+                .map(|action| (*action, action.default_keys()))
+                .collect(),
+        )
+    }
+
+    fn from_bindings(bindings: BTreeMap<Action, Vec<VirtualKeyCode>>) -> KeyBindings {
+        let mut lookup = HashMap::new();
+        for action in Action::ALL.iter() {
+            for key in bindings[action].iter() {
+                if let Some(existing) = lookup.insert(*key, *action) {
+                    rltk::console::log(format!(
+                        "{} is bound to both {:?} and {:?}, using {:?}",
+                        key_name(*key),
+                        existing,
+                        action,
+                        existing
+                    ));
+                    lookup.insert(*key, existing);
+                }
+            }
+        }
+
+        KeyBindings { bindings, lookup }
+    }
+
+    /// Reads the bindings file, writing one with the defaults if there isn't one yet. Actions the
+    /// file doesn't mention keep their default keys.
+    pub fn load() -> KeyBindings {
+        let path = config_dir().join(KEYBINDINGS_FILE);
+        let file: KeyBindingsFile = match load_versioned_json(&path, KEYBINDINGS_VERSION) {
+            Ok(Some(file)) => file,
+            Ok(None) => {
+                let defaults = KeyBindings::defaults();
+                defaults.save();
+                return defaults;
+            }
+            Err(e) => {
+                rltk::console::log(format!("Unable to read key bindings: {}", e));
+                return KeyBindings::defaults();
+            }
+        };
+
+        let mut bindings = KeyBindings::defaults().bindings;
+        for (action, names) in file.bindings {
+            let keys = names
+                .iter()
+                .filter_map(|name| {
+                    let key = parse_key(name);
+                    if key.is_none() {
+                        rltk::console::log(format!("Unknown key {} bound to {:?}", name, action));
+                    }
+                    key
+                })
+                .collect();
+            bindings.insert(action, keys);
+        }
+
+        KeyBindings::from_bindings(bindings)
+    }
+
+    pub fn save(&self) {
+        let file = KeyBindingsFile {
+            version: KEYBINDINGS_VERSION,
+            bindings: self
+                .bindings
+                .iter()
+                .map(|(action, keys)| (*action, keys.iter().map(|key| key_name(*key)).collect()))
+                .collect(),
+        };
+
+        let data = match serde_json::to_string_pretty(&file) {
+            Ok(data) => data,
+            Err(e) => {
+                rltk::console::log(format!("Unable to serialize key bindings: {}", e));
+                return;
+            }
+        };
+
+        let result = fs::create_dir_all(config_dir())
+            .and_then(|_| fs::write(config_dir().join(KEYBINDINGS_FILE), data));
+        if let Err(e) = result {
+            rltk::console::log(format!("Unable to write key bindings: {}", e));
+        }
+    }
+
+    pub fn action_for(&self, key: VirtualKeyCode) -> Option<Action> {
+        self.lookup.get(&key).copied()
+    }
+
+    /// The keys bound to an action as shown to the player, e.g. "Up, K, Numpad8".
+    pub fn describe(&self, action: Action) -> String {
+        let keys = &self.bindings[&action];
+        if keys.is_empty() {
+            return "unbound".to_string();
+        }
+        keys.iter().map(|key| key_name(*key)).collect::<Vec<_>>().join(", ")
+    }
+
+    /// The first key bound to an action, for short hints.
+    pub fn primary_key(&self, action: Action) -> Option<String> {
+        self.bindings[&action].first().map(|key| key_name(*key))
+    }
+}
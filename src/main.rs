@@ -1,22 +1,37 @@
+mod bystander_ai_system;
+mod camera;
+mod component_registry;
 mod components;
 mod damage_system;
+mod door_system;
+mod encyclopedia;
 mod gamelog;
 mod gui;
+mod hall_of_fame;
+mod hardcore;
 mod inventory_system;
+mod keybindings;
 mod item_collection_system;
 mod item_drop_system;
 mod map;
 mod map_builders;
 mod map_indexing_system;
 mod melee_combat_system;
+mod minimap;
 mod monster_ai_system;
+mod morgue;
 mod particle_system;
 mod player;
 mod random_table;
 mod rect;
+mod replay;
 mod rollable;
 mod save_load_system;
+mod settings;
 mod spawner;
+mod theme;
+mod tileset;
+mod travel;
 mod trigger_system;
 mod visibility_system;
 mod rex_assets;
@@ -29,15 +44,17 @@ pub use particle_system::*;
 pub use player::*;
 pub use random_table::*;
 pub use rect::*;
+pub use replay::*;
 pub use rollable::*;
 pub use save_load_system::*;
 pub use spawner::*;
+pub use theme::*;
 pub use trigger_system::*;
 
 use crate::map_builders::MapBuilder;
 use rltk::{GameState, Point, RandomNumberGenerator, Rltk};
 use specs::prelude::*;
-use specs::saveload::{SimpleMarker, SimpleMarkerAllocator};
+use specs::saveload::SimpleMarkerAllocator;
 
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub enum RunState {
@@ -56,12 +73,35 @@ pub enum RunState {
     },
     SaveGame,
     NextLevel,
+    PreviousLevel,
     ShowRemoveItem,
     GameOver,
     MagicMapReveal {
         row: i32,
     },
     MapGeneration,
+    HallOfFame {
+        sort: hall_of_fame::ScoreSort,
+    },
+    ShowLoadGame {
+        selection: usize,
+    },
+    ShowHelp,
+    ShowOptions {
+        selection: usize,
+    },
+    ShowLog {
+        offset: usize,
+        filter: Option<LogCategory>,
+    },
+    LookMode {
+        x: i32,
+        y: i32,
+    },
+    ShowOverview,
+    ShowEncyclopedia {
+        selection: usize,
+    },
 }
 
 pub struct MapGenState {
@@ -69,7 +109,6 @@ pub struct MapGenState {
     history: Vec<Map>,
     index: usize,
     timer: f32,
-    total_time: f32,
 }
 
 pub struct State {
@@ -77,28 +116,40 @@ pub struct State {
     pub mapgen: MapGenState,
     last_get_state: RunState,
     last_set_state: RunState,
+    replay: Option<ReplayPlayback>,
+    run_report: Option<morgue::RunReport>,
+    scores: Option<hall_of_fame::ScoreTable>,
+    save_slots: Option<Vec<SaveMetadata>>,
+    menu_message: Option<String>,
+    autosave_pending: bool,
+    keybindings: keybindings::KeyBindings,
+    settings: settings::Settings,
+    target_cursor: Option<Point>,
+    last_target: Option<Entity>,
+    travel: Option<travel::Travel>,
+    tileset: Option<tileset::Tileset>,
 }
 
-const SHOW_MAPGEN_VISUALIZER: bool = true;
-#[allow(dead_code)]
-const SHOW_RUNSTATE_DEBUG: bool = true;
-
-#[allow(dead_code)]
-const GENERATE_RANDOM_MAPS: bool = true;
-
 impl State {
     fn run_systems(&mut self) {
+        let mut map_index = map_indexing_system::MapIndexingSystem {};
+        map_index.run_now(&self.ecs);
+
         let mut vis = visibility_system::VisibilitySystem {};
         vis.run_now(&self.ecs);
+        encyclopedia::record_encounters(&self.ecs);
 
         let mut mob = monster_ai_system::MonsterAI {};
         mob.run_now(&self.ecs);
 
+        let mut bystanders = bystander_ai_system::BystanderAI {};
+        bystanders.run_now(&self.ecs);
+
         let mut triggers = trigger_system::TriggerSystem {};
         triggers.run_now(&self.ecs);
 
-        let mut map_index = map_indexing_system::MapIndexingSystem {};
-        map_index.run_now(&self.ecs);
+        let mut door_system = door_system::DoorSystem {};
+        door_system.run_now(&self.ecs);
 
         let mut melee_combat_system = melee_combat_system::MeleeCombatSystem {};
         melee_combat_system.run_now(&self.ecs);
@@ -128,7 +179,7 @@ impl State {
         let run_state = self.ecs.fetch::<RunState>();
         if (*run_state) == self.last_get_state {
             self.last_get_state = *run_state.clone();
-            rltk::console::log(format!("Starting with state {:?}", *run_state));
+            settings::log(settings::LogLevel::Verbose, format!("Starting with state {:?}", *run_state));
         }
         *run_state
     }
@@ -136,30 +187,22 @@ impl State {
     fn store_run_state(&mut self, state: &RunState) {
         if *state != self.last_set_state {
             self.last_set_state = *state;
-            rltk::console::log(format!("Storing run state {:?}", state));
+            settings::log(settings::LogLevel::Verbose, format!("Storing run state {:?}", state));
         }
         let mut run_writer = self.ecs.write_resource::<RunState>();
         *run_writer = *state;
     }
 
     fn draw_interface(&mut self, ctx: &mut Rltk) {
-        let positions = self.ecs.read_storage::<Position>();
-        let renderables = self.ecs.read_storage::<Renderable>();
-        let hidden = self.ecs.read_storage::<Hidden>();
-        let map = self.ecs.fetch::<Map>();
-
-        let mut renderable_objects = (&positions, &renderables, !&hidden)
-            .join()
-            .collect::<Vec<_>>();
-        renderable_objects.sort_by(|&a, &b| b.1.render_order.cmp(&a.1.render_order));
-        for (pos, render, _hidden) in renderable_objects.iter() {
-            let idx = map.xy_idx(pos.x, pos.y);
-            if map.visible_tiles[idx] {
-                ctx.set(pos.x, pos.y, render.fg, render.bg, render.glyph);
-            }
+        let tileset = self.tileset.as_ref().filter(|_| self.settings.use_tileset);
+        camera::render_camera(&self.ecs, ctx, tileset);
+        gui::draw_ui(&self.ecs, ctx, &self.keybindings);
+        if self.settings.show_minimap {
+            minimap::draw_corner_minimap(&self.ecs, ctx);
+        }
+        if let Some(replay) = &self.replay {
+            replay.draw_status(ctx);
         }
-
-        gui::draw_ui(&self.ecs, ctx);
     }
 
     fn fetch_entities_to_remove_on_level_change(&mut self) -> Vec<Entity> {
@@ -169,6 +212,9 @@ impl State {
         let player_entity = self.ecs.fetch::<Entity>();
         let equipped = self.ecs.read_storage::<Equipped>();
 
+        let parked = self.ecs.read_storage::<OtherLevelPosition>();
+        let keep_items_of = |owner: Entity| owner == *player_entity || parked.get(owner).is_some();
+
         let mut to_delete: Vec<Entity> = Vec::new();
         for entity in entities.join() {
             let p = player.get(entity);
@@ -176,16 +222,20 @@ impl State {
                 continue;
             }
 
+            if parked.get(entity).is_some() {
+                continue;
+            }
+
             let bp = backpack.get(entity);
             if let Some(bp) = bp {
-                if bp.owner == *player_entity {
+                if keep_items_of(bp.owner) {
                     continue;
                 }
             }
 
             let eq = equipped.get(entity);
             if let Some(eq) = eq {
-                if eq.owner == *player_entity {
+                if keep_items_of(eq.owner) {
                     continue;
                 }
             }
@@ -221,38 +271,156 @@ impl State {
         self.mapgen.timer = 0.;
         self.mapgen.history.clear();
 
-        let mut builder = if GENERATE_RANDOM_MAPS { map_builders::random_builder(new_depth) } else { map_builders::static_builder(new_depth) };
-        builder.build_map();
+        let theme = {
+            let library = self.ecs.fetch::<ThemeLibrary>();
+            let mut rng = self.ecs.write_resource::<RandomNumberGenerator>();
+            library.pick_for_depth(new_depth, &mut rng)
+        };
+
+        let (width, height) = map_builders::level_dimensions(new_depth);
+        let mut builder = {
+            let mut rng = self.ecs.write_resource::<RandomNumberGenerator>();
+            let mut builder = if new_depth == 0 {
+                map_builders::town_builder(width, height)
+            } else if self.settings.random_maps {
+                map_builders::random_builder(new_depth, width, height, &theme, &mut rng)
+            } else {
+                map_builders::static_builder(new_depth, width, height)
+            };
+            builder.build_map(&mut rng);
+            builder
+        };
 
         self.mapgen.history = builder.get_snapshot_history();
+        for snapshot in self.mapgen.history.iter_mut() {
+            snapshot.theme = theme.clone();
+        }
 
         let start_position = self.determine_start_position(&mut builder);
+        {
+            let mut map = self.ecs.write_resource::<Map>();
+            map.theme = theme;
+            if new_depth == 1 {
+                let start_idx = map.xy_idx(start_position.x, start_position.y);
+                map.tiles[start_idx] = TileType::StairsUp;
+            }
+        }
 
         builder.spawn_entities(&mut self.ecs);
 
         self.set_player_position(&start_position);
+        self.update_run_stats(|stats| stats.deepest_depth = i32::max(stats.deepest_depth, new_depth));
 
         self.reset_player_viewshed();
     }
 
-    fn go_to_next_level(&mut self) {
+    fn update_run_stats<F: FnOnce(&mut RunStats)>(&mut self, update: F) {
+        let player_entity = self.ecs.fetch::<Entity>();
+        if let Some(stats) = self.ecs.write_storage::<RunStats>().get_mut(*player_entity) {
+            update(stats);
+        }
+    }
+
+    /// Saves the current run without leaving it. Replays are never saved, as their run already
+    /// exists elsewhere.
+    fn autosave(&mut self) {
+        if self.replay.is_some() {
+            return;
+        }
+
+        if let Err(e) = save_load_system::save_game(&mut self.ecs, SaveOptions::from_env()) {
+            let mut log = self.ecs.write_resource::<GameLog>();
+            log.add(LogCategory::System, format!("Autosave failed: {}", e));
+        }
+    }
+
+    fn remove_entities_for_level_change(&mut self) {
         let to_delete = self.fetch_entities_to_remove_on_level_change();
         for target in to_delete {
             self.ecs
                 .delete_entity(target)
                 .expect("unable to delete entity during floor change");
         }
+    }
+
+    /// Puts the town and everyone in it aside, so that it can be restored as it was when the
+    /// player comes back up.
+    fn store_town(&mut self) {
+        let map = (*self.ecs.fetch::<Map>()).clone();
+        let depth = map.depth;
+        self.ecs.write_resource::<StoredTown>().map = Some(map);
+
+        let entities = self.ecs.entities();
+        let player_entity = self.ecs.fetch::<Entity>();
+        let particles = self.ecs.read_storage::<ParticleLifetime>();
+        let mut positions = self.ecs.write_storage::<Position>();
+        let mut parked = self.ecs.write_storage::<OtherLevelPosition>();
+
+        let to_park: Vec<(Entity, Position)> = (&entities, &positions)
+            .join()
+            .filter(|(entity, _pos)| *entity != *player_entity && particles.get(*entity).is_none())
+            .map(|(entity, pos)| (entity, pos.clone()))
+            .collect();
+        for (entity, pos) in to_park {
+            positions.remove(entity);
+            parked
+                .insert(entity, OtherLevelPosition { x: pos.x, y: pos.y, depth })
+                .expect("Unable to park entity");
+        }
+    }
 
+    /// Brings back the town stored by `store_town`. Returns false if there is none, e.g. in
+    /// saves from before the town was kept.
+    fn restore_town(&mut self) -> bool {
+        let map = match self.ecs.write_resource::<StoredTown>().map.take() {
+            Some(map) => map,
+            None => return false,
+        };
+        let depth = map.depth;
+        {
+            let mut map_resource = self.ecs.write_resource::<Map>();
+            *map_resource = map;
+            map_resource.tile_content = vec![Vec::new(); (map_resource.width * map_resource.height) as usize];
+        }
+
+        let entities = self.ecs.entities();
+        let mut positions = self.ecs.write_storage::<Position>();
+        let mut parked = self.ecs.write_storage::<OtherLevelPosition>();
+        let mut viewsheds = self.ecs.write_storage::<Viewshed>();
+
+        let to_restore: Vec<(Entity, Position)> = (&entities, &parked)
+            .join()
+            .filter(|(_entity, other)| other.depth == depth)
+            .map(|(entity, other)| (entity, Position { x: other.x, y: other.y }))
+            .collect();
+        for (entity, pos) in to_restore {
+            parked.remove(entity);
+            positions.insert(entity, pos).expect("Unable to restore entity");
+            if let Some(viewshed) = viewsheds.get_mut(entity) {
+                viewshed.dirty = true;
+            }
+        }
+        true
+    }
+
+    fn go_to_next_level(&mut self) {
         let current_depth;
         {
             let map_resource = self.ecs.fetch::<Map>();
             current_depth = map_resource.depth;
         }
+        if current_depth == 0 {
+            self.store_town();
+        }
+        self.remove_entities_for_level_change();
+
         self.generate_world_map(current_depth + 1);
 
         let mut log = self.ecs.fetch_mut::<GameLog>();
-        log.entries
-            .push("You descend to the next level - suddenly your strength returns".to_string());
+        log.add(
+            LogCategory::System,
+            "You descend to the next level - suddenly your strength returns",
+        );
 
         let player_entity = self.ecs.fetch::<Entity>();
         let mut combat_stats = self.ecs.write_storage::<CombatStats>();
@@ -262,6 +430,37 @@ impl State {
         }
     }
 
+    fn go_to_previous_level(&mut self) {
+        self.remove_entities_for_level_change();
+
+        let current_depth = self.ecs.fetch::<Map>().depth;
+        if current_depth - 1 != 0 || !self.restore_town() {
+            self.generate_world_map(current_depth - 1);
+        }
+
+        let stairs_down = {
+            let map = self.ecs.fetch::<Map>();
+            map.tiles
+                .iter()
+                .position(|t| *t == TileType::StairsDown)
+                .map(|idx| Position {
+                    x: idx as i32 % map.width,
+                    y: idx as i32 / map.width,
+                })
+        };
+        if let Some(stairs_down) = stairs_down {
+            self.set_player_position(&stairs_down);
+        }
+        self.reset_player_viewshed();
+
+        let mut log = self.ecs.fetch_mut::<GameLog>();
+        if current_depth - 1 == 0 {
+            log.add(LogCategory::System, "You climb back up into the daylight of the town");
+        } else {
+            log.add(LogCategory::System, "You climb up to the previous level");
+        }
+    }
+
     fn reset_player_viewshed(&mut self) {
         let player_entity = self.ecs.fetch::<Entity>();
         let mut viewshed_components = self.ecs.write_storage::<Viewshed>();
@@ -272,6 +471,11 @@ impl State {
     }
 
     pub fn game_over_cleanup(&mut self) {
+        self.ecs.fetch::<ReplayRecorder>().save();
+        self.replay = None;
+        self.run_report = None;
+        self.travel = None;
+
         let mut to_delete = Vec::new();
         for e in self.ecs.entities().join() {
             to_delete.push(e);
@@ -288,7 +492,58 @@ impl State {
             *writer = player_entity;
         }
 
-        self.generate_world_map(1);
+        self.ecs.write_resource::<StoredTown>().map = None;
+        self.generate_world_map(0);
+    }
+
+    /// Throws away the whole world and builds a fresh one from `seed`. Starting every run from
+    /// an empty world keeps entity ids, and therefore replays, reproducible.
+    fn reset_world(&mut self, seed: u64) {
+        self.run_report = None;
+        self.autosave_pending = false;
+        self.travel = None;
+        self.ecs = World::new();
+        component_registry::register_components(&mut self.ecs);
+
+        self.ecs.insert(SimpleMarkerAllocator::<Savable>::new());
+        self.ecs.insert(RunState::PreRun);
+        self.ecs.insert(Map::new(1, camera::VIEW_WIDTH, camera::VIEW_HEIGHT));
+        self.ecs.insert(StoredTown::default());
+        self.ecs.insert(Point::new(0, 0));
+        self.ecs.insert(RandomNumberGenerator::seeded(seed));
+        self.ecs.insert(ReplayRecorder::new(seed));
+        self.ecs.insert(hardcore::RunInfo::new(false));
+
+        let player_entity = spawner::player(&mut self.ecs, 0, 0);
+        self.ecs.insert(player_entity);
+        let mut log = GameLog::new();
+        log.add(
+            LogCategory::System,
+            "Welcome to deathlike! The dungeon entrance lies east of town.",
+        );
+        self.ecs.insert(log);
+        self.ecs.insert(ParticleBuilder::new());
+        self.ecs.insert(rex_assets::RexAssets::new());
+        self.ecs.insert(ThemeLibrary::load());
+        self.generate_world_map(0);
+    }
+
+    fn replay_input(&mut self, ctx: &mut Rltk) -> RunState {
+        let input = match self.replay.as_mut() {
+            Some(replay) => replay.poll(ctx),
+            None => return RunState::AwaitingInput,
+        };
+
+        match input {
+            PlaybackInput::Idle => RunState::AwaitingInput,
+            PlaybackInput::Command(command) => perform_command(&mut self.ecs, command),
+            PlaybackInput::Stop => {
+                self.replay = None;
+                RunState::MainMenu {
+                    menu_selection: MainMenuSelection::NewGame,
+                }
+            }
+        }
     }
 }
 
@@ -296,14 +551,21 @@ impl GameState for State {
     fn tick(&mut self, ctx: &mut Rltk) {
         let mut new_run_state = self.determine_run_state();
 
+        if self.tileset.is_some() {
+            tileset::clear_sprites(ctx);
+        }
         ctx.cls();
-        remove_dead_particles(&mut self.ecs, ctx);
+        fade_particles(&mut self.ecs, ctx);
 
         match new_run_state {
-            RunState::MainMenu { .. } => {}
+            RunState::MainMenu { .. }
+            | RunState::HallOfFame { .. }
+            | RunState::ShowLoadGame { .. }
+            | RunState::ShowOptions { .. }
+            | RunState::ShowLog { .. }
+            | RunState::ShowOverview
+            | RunState::ShowEncyclopedia { .. } => {}
             _ => {
-                map::draw_map(&self.ecs.fetch::<Map>(), ctx);
-
                 self.draw_interface(ctx);
             }
         }
@@ -315,9 +577,31 @@ impl GameState for State {
                 new_run_state = RunState::AwaitingInput;
             }
             RunState::AwaitingInput => {
-                new_run_state = player_input(self, ctx);
+                if self.autosave_pending {
+                    self.autosave_pending = false;
+                    self.autosave();
+                }
+
+                new_run_state = if self.replay.is_some() {
+                    self.replay_input(ctx)
+                } else if self.travel.is_some() {
+                    continue_travel(self, ctx)
+                } else {
+                    player_input(self, ctx)
+                };
             }
             RunState::PlayerTurn => {
+                clear_particles(&mut self.ecs);
+                let mut turns = 0;
+                self.update_run_stats(|stats| {
+                    stats.turns += 1;
+                    turns = stats.turns;
+                });
+                let interval = self.settings.autosave_interval;
+                self.ecs.write_resource::<GameLog>().turn = turns;
+                if interval > 0 && turns % interval == 0 {
+                    self.autosave_pending = true;
+                }
                 self.run_systems();
                 self.ecs.maintain();
 
@@ -340,27 +624,25 @@ impl GameState for State {
                     ItemMenuResult::NoResponse => {}
                     ItemMenuResult::Selected => {
                         let item_entity = selection.unwrap();
-                        let mut intent = self.ecs.write_storage::<WantsToUseItem>();
-                        let ranged_items = self.ecs.read_storage::<Ranged>();
-                        let ranged_item = ranged_items.get(item_entity);
-                        match ranged_item {
+                        let ranged_range = self
+                            .ecs
+                            .read_storage::<Ranged>()
+                            .get(item_entity)
+                            .map(|ranged| ranged.range);
+                        match ranged_range {
                             None => {
-                                intent
-                                    .insert(
-                                        *self.ecs.fetch::<Entity>(),
-                                        WantsToUseItem {
-                                            item: item_entity,
-                                            target: None,
-                                        },
-                                    )
-                                    .expect("Unable to insert use item intent");
-
-                                new_run_state = RunState::PlayerTurn;
+                                new_run_state = perform_command(
+                                    &mut self.ecs,
+                                    PlayerCommand::UseItem {
+                                        item: item_entity.id(),
+                                        target: None,
+                                    },
+                                );
                             }
-                            Some(ranged) => {
+                            Some(range) => {
                                 new_run_state = RunState::ShowTargeting {
                                     item: item_entity,
-                                    range: ranged.range,
+                                    range,
                                 }
                             }
                         }
@@ -374,35 +656,28 @@ impl GameState for State {
                     ItemMenuResult::NoResponse => {}
                     ItemMenuResult::Selected => {
                         let item_entity = selection.unwrap();
-                        let mut intent = self.ecs.write_storage::<WantsToDropItem>();
-                        intent
-                            .insert(
-                                *self.ecs.fetch::<Entity>(),
-                                WantsToDropItem { item: item_entity },
-                            )
-                            .expect("Unable to insert drop item intent");
-
-                        new_run_state = RunState::PlayerTurn;
+                        new_run_state = perform_command(
+                            &mut self.ecs,
+                            PlayerCommand::DropItem {
+                                item: item_entity.id(),
+                            },
+                        );
                     }
                 }
             }
             RunState::ShowTargeting { range, item } => {
-                let (result, target_point) = gui::ranged_target(self, ctx, range);
+                let (result, target_point) = gui::ranged_target(self, ctx, range, item);
                 match result {
                     ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
                     ItemMenuResult::NoResponse => {}
                     ItemMenuResult::Selected => {
-                        let mut intent = self.ecs.write_storage::<WantsToUseItem>();
-                        intent
-                            .insert(
-                                *self.ecs.fetch::<Entity>(),
-                                WantsToUseItem {
-                                    item,
-                                    target: target_point,
-                                },
-                            )
-                            .expect("Unable to insert targeting intent");
-                        new_run_state = RunState::PlayerTurn;
+                        new_run_state = perform_command(
+                            &mut self.ecs,
+                            PlayerCommand::UseItem {
+                                item: item.id(),
+                                target: target_point,
+                            },
+                        );
                     }
                 }
             }
@@ -414,27 +689,76 @@ impl GameState for State {
                             menu_selection: selected,
                         }
                     }
-                    MainMenuResult::Selected { selected } => match selected {
-                        MainMenuSelection::NewGame => new_run_state = RunState::PreRun,
-                        MainMenuSelection::LoadGame => {
-                            save_load_system::load_game(&mut self.ecs);
-                            new_run_state = RunState::AwaitingInput;
-                            delete_saved_game();
-                        }
-                        MainMenuSelection::Quit => {
-                            ::std::process::exit(0);
+                    MainMenuResult::Selected { selected } => {
+                        self.menu_message = None;
+                        match selected {
+                            MainMenuSelection::NewGame => {
+                                self.replay = None;
+                                self.reset_world(replay::fresh_seed());
+                                new_run_state = RunState::PreRun;
+                            }
+                            MainMenuSelection::NewHardcoreGame => {
+                                self.replay = None;
+                                self.reset_world(replay::fresh_seed());
+                                self.ecs.insert(hardcore::RunInfo::new(true));
+                                new_run_state = RunState::PreRun;
+                            }
+                            MainMenuSelection::LoadGame => {
+                                self.save_slots = Some(list_save_slots());
+                                new_run_state = RunState::ShowLoadGame { selection: 0 };
+                            }
+                            MainMenuSelection::WatchReplay => match replay::load_replay() {
+                                Some(recorded) => {
+                                    self.reset_world(recorded.seed);
+                                    self.ecs.write_resource::<ReplayRecorder>().enabled = false;
+                                    self.replay = Some(ReplayPlayback::new(recorded));
+                                    new_run_state = RunState::PreRun;
+                                }
+                                None => {
+                                    new_run_state = RunState::MainMenu {
+                                        menu_selection: MainMenuSelection::NewGame,
+                                    }
+                                }
+                            },
+                            MainMenuSelection::HallOfFame => {
+                                self.scores = Some(hall_of_fame::ScoreTable::load());
+                                new_run_state = RunState::HallOfFame {
+                                    sort: hall_of_fame::ScoreSort::Score,
+                                };
+                            }
+                            MainMenuSelection::Options => {
+                                new_run_state = RunState::ShowOptions { selection: 0 };
+                            }
+                            MainMenuSelection::Quit => {
+                                ::std::process::exit(0);
+                            }
                         }
-                    },
+                    }
                 }
             }
             RunState::SaveGame => {
-                save_load_system::save_game(&mut self.ecs);
-                new_run_state = RunState::MainMenu {
-                    menu_selection: MainMenuSelection::LoadGame,
-                };
+                self.ecs.fetch::<ReplayRecorder>().save();
+                match save_load_system::save_game(&mut self.ecs, SaveOptions::from_env()) {
+                    Ok(()) => {
+                        new_run_state = RunState::MainMenu {
+                            menu_selection: MainMenuSelection::LoadGame,
+                        };
+                    }
+                    Err(e) => {
+                        let mut log = self.ecs.write_resource::<GameLog>();
+                        log.add(LogCategory::System, format!("Unable to save the game: {}", e));
+                        new_run_state = RunState::AwaitingInput;
+                    }
+                }
             }
             RunState::NextLevel => {
                 self.go_to_next_level();
+                self.autosave_pending = true;
+                new_run_state = RunState::PreRun;
+            }
+            RunState::PreviousLevel => {
+                self.go_to_previous_level();
+                self.autosave_pending = true;
                 new_run_state = RunState::PreRun;
             }
             RunState::ShowRemoveItem => {
@@ -444,20 +768,28 @@ impl GameState for State {
                     ItemMenuResult::NoResponse => {}
                     ItemMenuResult::Selected => {
                         let item_entity = selection.unwrap();
-                        let mut intent = self.ecs.write_storage::<WantsToRemoveItem>();
-                        intent
-                            .insert(
-                                *self.ecs.fetch::<Entity>(),
-                                WantsToRemoveItem { item: item_entity },
-                            )
-                            .expect("Unable to insert remove item intent");
-
-                        new_run_state = RunState::PlayerTurn;
+                        new_run_state = perform_command(
+                            &mut self.ecs,
+                            PlayerCommand::RemoveItem {
+                                item: item_entity.id(),
+                            },
+                        );
                     }
                 }
             }
             RunState::GameOver => {
-                let result = gui::game_over(ctx);
+                if self.run_report.is_none() {
+                    self.run_report = Some(morgue::finish_run(&self.ecs, self.replay.is_none()));
+                    if self.replay.is_none() {
+                        save_load_system::delete_current_slot(&self.ecs);
+                        let run = self.ecs.fetch::<hardcore::RunInfo>();
+                        if run.hardcore {
+                            hardcore::record_death(&run);
+                        }
+                    }
+                }
+
+                let result = gui::game_over(ctx, self.run_report.as_ref().unwrap());
                 match result {
                     GameOverResult::NoSelection => {}
                     GameOverResult::QuitToMenu => {
@@ -468,33 +800,147 @@ impl GameState for State {
                     }
                 }
             }
+            RunState::ShowLog { offset, filter } => {
+                let log = self.ecs.fetch::<GameLog>();
+                match gui::show_message_log(ctx, &log, offset, filter, &self.keybindings) {
+                    MessageLogResult::NoSelection { offset, filter } => {
+                        new_run_state = RunState::ShowLog { offset, filter };
+                    }
+                    MessageLogResult::Close => new_run_state = RunState::AwaitingInput,
+                }
+            }
+            RunState::LookMode { x, y } => match gui::look_mode(self, ctx, x, y) {
+                LookResult::NoSelection { x, y } => new_run_state = RunState::LookMode { x, y },
+                LookResult::Close => new_run_state = RunState::AwaitingInput,
+            },
+            RunState::ShowOverview => {
+                let result = minimap::show_overview(&self.ecs, ctx, &self.keybindings);
+                if result == minimap::OverviewResult::Close {
+                    new_run_state = RunState::AwaitingInput;
+                }
+            }
+            RunState::ShowEncyclopedia { selection } => {
+                match gui::show_encyclopedia(&self.ecs, ctx, selection, &self.keybindings) {
+                    EncyclopediaResult::NoSelection { selection } => {
+                        new_run_state = RunState::ShowEncyclopedia { selection };
+                    }
+                    EncyclopediaResult::Close => new_run_state = RunState::AwaitingInput,
+                }
+            }
+            RunState::ShowHelp => {
+                if gui::show_help(ctx, &self.keybindings) == HelpResult::Close {
+                    new_run_state = RunState::AwaitingInput;
+                }
+            }
+            RunState::ShowOptions { selection } => {
+                match gui::show_options(ctx, &mut self.settings, selection) {
+                    OptionsResult::NoSelection { selection } => {
+                        new_run_state = RunState::ShowOptions { selection };
+                    }
+                    OptionsResult::Changed { selection } => {
+                        self.settings.apply();
+                        self.settings.save();
+                        new_run_state = RunState::ShowOptions { selection };
+                    }
+                    OptionsResult::Close => {
+                        new_run_state = RunState::MainMenu {
+                            menu_selection: MainMenuSelection::Options,
+                        };
+                    }
+                }
+            }
+            RunState::ShowLoadGame { selection } => {
+                let slots = self.save_slots.get_or_insert_with(list_save_slots);
+                match gui::show_load_game(ctx, slots, selection) {
+                    LoadGameResult::NoSelection { selection } => {
+                        new_run_state = RunState::ShowLoadGame { selection };
+                    }
+                    LoadGameResult::Load { selection } => {
+                        let slot = slots[selection].clone();
+                        self.save_slots = None;
+                        self.replay = None;
+                        match save_load_system::load_game(&mut self.ecs, &slot) {
+                            Ok(()) => {
+                                let mut turns = 0;
+                                self.update_run_stats(|stats| turns = stats.turns);
+                                self.ecs.write_resource::<GameLog>().turn = turns;
+
+                                // Hardcore runs can't go back to a save once it has been played on.
+                                if self.ecs.fetch::<hardcore::RunInfo>().hardcore {
+                                    delete_save_slot(&slot.slot);
+                                }
+
+                                let mut recorder = self.ecs.write_resource::<ReplayRecorder>();
+                                recorder.seed = slot.seed;
+                                recorder.enabled = false;
+                                new_run_state = RunState::AwaitingInput;
+                            }
+                            Err(e) => {
+                                rltk::console::log(format!("Failed to load {}: {:?}", slot.slot, e));
+                                self.reset_world(replay::fresh_seed());
+                                self.menu_message = Some(format!("Could not load save: {}", e));
+                                new_run_state = RunState::MainMenu {
+                                    menu_selection: MainMenuSelection::LoadGame,
+                                };
+                            }
+                        }
+                    }
+                    LoadGameResult::Delete { selection } => {
+                        delete_save_slot(&slots[selection].slot);
+                        self.save_slots = Some(list_save_slots());
+                        new_run_state = RunState::ShowLoadGame {
+                            selection: selection.saturating_sub(1),
+                        };
+                    }
+                    LoadGameResult::Cancel => {
+                        self.save_slots = None;
+                        new_run_state = RunState::MainMenu {
+                            menu_selection: MainMenuSelection::LoadGame,
+                        };
+                    }
+                }
+            }
+            RunState::HallOfFame { sort } => {
+                let table = self.scores.get_or_insert_with(hall_of_fame::ScoreTable::load);
+                match gui::show_hall_of_fame(ctx, table, sort) {
+                    HallOfFameResult::NoSelection { sort } => {
+                        new_run_state = RunState::HallOfFame { sort };
+                    }
+                    HallOfFameResult::Close => {
+                        self.scores = None;
+                        new_run_state = RunState::MainMenu {
+                            menu_selection: MainMenuSelection::HallOfFame,
+                        };
+                    }
+                }
+            }
             RunState::MagicMapReveal { row } => {
                 let mut map = self.ecs.fetch_mut::<Map>();
-                for x in 0..MAP_WIDTH {
-                    let i = map.xy_idx(x as i32, row);
+                for x in 0..map.width {
+                    let i = map.xy_idx(x, row);
                     map.revealed_tiles[i] = true;
                 }
 
-                if row as usize == MAP_HEIGHT - 1 {
+                if row == map.height - 1 {
                     new_run_state = RunState::MonsterTurn;
                 } else {
                     new_run_state = RunState::MagicMapReveal { row: row + 1 };
                 }
             }
             RunState::MapGeneration => {
-                if !SHOW_MAPGEN_VISUALIZER {
+                if !self.settings.show_mapgen_visualizer || self.mapgen.history.is_empty() {
                     new_run_state = self.mapgen.next_state.unwrap();
-                }
-
-                ctx.cls();
-                draw_map(&self.mapgen.history[self.mapgen.index], ctx);
-
-                self.mapgen.timer += ctx.frame_time_ms;
-                if self.mapgen.timer > self.mapgen.total_time {
-                    self.mapgen.timer = 0.;
-                    self.mapgen.index += 1;
-                    if self.mapgen.index >= self.mapgen.history.len() {
-                        new_run_state = self.mapgen.next_state.unwrap();
+                } else {
+                    ctx.cls();
+                    camera::render_debug_map(&self.mapgen.history[self.mapgen.index], ctx);
+
+                    self.mapgen.timer += ctx.frame_time_ms;
+                    if self.mapgen.timer > self.settings.mapgen_step_ms {
+                        self.mapgen.timer = 0.;
+                        self.mapgen.index += 1;
+                        if self.mapgen.index >= self.mapgen.history.len() {
+                            new_run_state = self.mapgen.next_state.unwrap();
+                        }
                     }
                 }
             }
@@ -506,9 +952,49 @@ impl GameState for State {
     }
 }
 
+/// `deathlike --convert-save <input> <output> <json|json.gz|msgpack|msgpack.gz>` rewrites a save
+/// in another encoding without starting the game.
+fn convert_save_from_args(args: &[String]) {
+    let usage = "usage: deathlike --convert-save <input> <output> <json|json.gz|msgpack|msgpack.gz>";
+    if args.len() != 5 {
+        eprintln!("{}", usage);
+        std::process::exit(2);
+    }
+
+    let options = match SaveOptions::parse(&args[4]) {
+        Some(options) => options,
+        None => {
+            eprintln!("{}", usage);
+            std::process::exit(2);
+        }
+    };
+
+    let (input, output) = (std::path::Path::new(&args[2]), std::path::Path::new(&args[3]));
+    match save_load_system::convert_save(input, output, options) {
+        Ok(()) => println!("Converted {} to {}", input.display(), output.display()),
+        Err(e) => {
+            eprintln!("Unable to convert {}: {}", input.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
 fn main() -> rltk::BError {
-    use rltk::RltkBuilder;
-    let context = RltkBuilder::simple80x50().with_title("Deathlike").build()?;
+    component_registry::check_registry();
+
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("--convert-save") {
+        convert_save_from_args(&args);
+        return Ok(());
+    }
+
+    let settings = settings::Settings::load();
+    settings.apply();
+    let tileset = tileset::Tileset::load();
+    let context = settings
+        .window_builder(tileset.as_ref())
+        .with_title("Deathlike")
+        .build()?;
 
     let mut gs = State {
         ecs: World::new(),
@@ -519,65 +1005,25 @@ fn main() -> rltk::BError {
             history: Vec::new(),
             timer: 0.,
             index: 0,
-            total_time: 300.0,
         },
         last_get_state: RunState::GameOver,
         last_set_state: RunState::GameOver,
+        replay: None,
+        run_report: None,
+        scores: None,
+        save_slots: None,
+        menu_message: None,
+        autosave_pending: false,
+        keybindings: keybindings::KeyBindings::load(),
+        settings,
+        target_cursor: None,
+        last_target: None,
+        travel: None,
+        tileset,
     };
 
-    register_components(&mut gs);
-
-    gs.ecs.insert(SimpleMarkerAllocator::<Savable>::new());
+    gs.reset_world(replay::fresh_seed());
     gs.ecs.insert(RunState::MapGeneration {});
-    gs.ecs.insert(Map::new(1));
-    gs.ecs.insert(Point::new(0, 0));
-    gs.ecs.insert(RandomNumberGenerator::new());
-
-    let player_entity = spawner::player(&mut gs.ecs, 0, 0);
-    gs.ecs.insert(player_entity);
-    gs.ecs.insert(GameLog {
-        entries: vec!["Welcome to deathlike!".to_string()],
-    });
-    gs.ecs.insert(ParticleBuilder::new());
-    gs.ecs.insert(rex_assets::RexAssets::new());
-    gs.generate_world_map(1);
 
     rltk::main_loop(context, gs)
 }
-
-fn register_components(gs: &mut State) {
-    gs.ecs.register::<Position>();
-    gs.ecs.register::<Renderable>();
-    gs.ecs.register::<Player>();
-    gs.ecs.register::<Viewshed>();
-    gs.ecs.register::<Monster>();
-    gs.ecs.register::<Name>();
-    gs.ecs.register::<BlocksTile>();
-    gs.ecs.register::<CombatStats>();
-    gs.ecs.register::<WantsToMelee>();
-    gs.ecs.register::<SufferDamage>();
-    gs.ecs.register::<Item>();
-    gs.ecs.register::<InBackpack>();
-    gs.ecs.register::<WantsToPickupItem>();
-    gs.ecs.register::<WantsToUseItem>();
-    gs.ecs.register::<WantsToDropItem>();
-    gs.ecs.register::<Consumable>();
-    gs.ecs.register::<ProvidesHealing>();
-    gs.ecs.register::<Ranged>();
-    gs.ecs.register::<InflictsDamage>();
-    gs.ecs.register::<AreaOfEffect>();
-    gs.ecs.register::<Confusion>();
-    gs.ecs.register::<SimpleMarker<Savable>>();
-    gs.ecs.register::<SerializationHelper>();
-    gs.ecs.register::<Equippable>();
-    gs.ecs.register::<Equipped>();
-    gs.ecs.register::<MeleePowerBonus>();
-    gs.ecs.register::<DefenseBonus>();
-    gs.ecs.register::<WantsToRemoveItem>();
-    gs.ecs.register::<ParticleLifetime>();
-    gs.ecs.register::<MagicMapper>();
-    gs.ecs.register::<Hidden>();
-    gs.ecs.register::<EntryTrigger>();
-    gs.ecs.register::<EntityMoved>();
-    gs.ecs.register::<SingleActivation>();
-}
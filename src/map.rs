@@ -1,4 +1,5 @@
-use rltk::{Algorithm2D, BaseMap, Point, Rltk, RGB};
+use crate::Theme;
+use rltk::{Algorithm2D, BaseMap, Point, RGB};
 use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 use std::collections::HashSet;
@@ -8,6 +9,52 @@ pub enum TileType {
     Wall,
     Floor,
     StairsDown,
+    StairsUp,
+    ShallowWater,
+    DeepWater,
+    Lava,
+    Chasm,
+    Rubble,
+    Bridge,
+    Grass,
+    WoodFloor,
+    StoneFloor,
+    Gravel,
+}
+
+impl TileType {
+    pub fn is_walkable(self) -> bool {
+        !matches!(self, TileType::Wall | TileType::DeepWater | TileType::Chasm)
+    }
+
+    pub fn is_opaque(self) -> bool {
+        self == TileType::Wall
+    }
+
+    pub fn movement_cost(self) -> f32 {
+        match self {
+            TileType::ShallowWater => 2.0,
+            TileType::Rubble => 2.0,
+            TileType::Lava => 4.0,
+            TileType::Gravel => 1.2,
+            TileType::Grass => 1.1,
+            _ => 1.0,
+        }
+    }
+
+    pub fn entry_damage(self) -> i32 {
+        match self {
+            TileType::Lava => 6,
+            _ => 0,
+        }
+    }
+
+    /// Tiles that monsters and items can be placed on: anything safe to stand on except the stairs.
+    pub fn allows_spawns(self) -> bool {
+        self.is_walkable()
+            && self.entry_damage() == 0
+            && !matches!(self, TileType::StairsDown | TileType::StairsUp)
+    }
 }
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone)]
@@ -20,28 +67,39 @@ pub struct Map {
     pub blocked: Vec<bool>,
     pub depth: i32,
     pub blood_stains: HashSet<usize>,
+    pub theme: Theme,
+
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub view_blocked: HashSet<usize>,
 
     #[serde(skip_serializing)]
     #[serde(skip_deserializing)]
     pub tile_content: Vec<Vec<Entity>>,
 }
 
-pub const MAP_WIDTH: usize = 80;
-pub const MAP_HEIGHT: usize = 43;
-pub const MAP_COUNT: usize = MAP_HEIGHT * MAP_WIDTH;
+/// The town is only built once per run. While the player is in the dungeon its map waits here, so
+/// climbing back up returns to the same town.
+#[derive(Default, Clone)]
+pub struct StoredTown {
+    pub map: Option<Map>,
+}
 
 impl Map {
-    pub fn new(new_depth: i32) -> Map {
+    pub fn new(new_depth: i32, width: i32, height: i32) -> Map {
+        let map_tile_count = (width * height) as usize;
         Map {
-            tiles: vec![TileType::Wall; MAP_COUNT],
-            width: MAP_WIDTH as i32,
-            height: MAP_HEIGHT as i32,
-            revealed_tiles: vec![false; MAP_COUNT],
-            visible_tiles: vec![false; MAP_COUNT],
-            blocked: vec![false; MAP_COUNT],
-            tile_content: vec![Vec::new(); MAP_COUNT],
+            tiles: vec![TileType::Wall; map_tile_count],
+            width,
+            height,
+            revealed_tiles: vec![false; map_tile_count],
+            visible_tiles: vec![false; map_tile_count],
+            blocked: vec![false; map_tile_count],
+            tile_content: vec![Vec::new(); map_tile_count],
             depth: new_depth,
             blood_stains: HashSet::new(),
+            theme: Theme::default(),
+            view_blocked: HashSet::new(),
         }
     }
 
@@ -79,7 +137,7 @@ impl Map {
 
     pub fn populate_blocked(&mut self) {
         for (i, tile) in self.tiles.iter_mut().enumerate() {
-            self.blocked[i] = *tile == TileType::Wall;
+            self.blocked[i] = !tile.is_walkable();
         }
     }
 
@@ -90,6 +148,10 @@ impl Map {
         let idx = self.xy_idx(x, y);
         !self.blocked[idx]
     }
+
+    fn exit_cost(&self, idx: usize, base_cost: f32) -> f32 {
+        base_cost * self.tiles[idx].movement_cost()
+    }
 }
 
 impl Algorithm2D for Map {
@@ -100,7 +162,7 @@ impl Algorithm2D for Map {
 
 impl BaseMap for Map {
     fn is_opaque(&self, idx: usize) -> bool {
-        self.tiles[idx] == TileType::Wall
+        self.tiles[idx].is_opaque() || self.view_blocked.contains(&idx)
     }
 
     fn get_available_exits(&self, idx: usize) -> rltk::SmallVec<[(usize, f32); 10]> {
@@ -111,29 +173,29 @@ impl BaseMap for Map {
         let w = self.width as usize;
 
         if self.is_exit_valid(x - 1, y) {
-            exits.push((idx - 1, 1.0))
+            exits.push((idx - 1, self.exit_cost(idx - 1, 1.0)))
         };
         if self.is_exit_valid(x + 1, y) {
-            exits.push((idx + 1, 1.0))
+            exits.push((idx + 1, self.exit_cost(idx + 1, 1.0)))
         };
         if self.is_exit_valid(x, y - 1) {
-            exits.push((idx - w, 1.0))
+            exits.push((idx - w, self.exit_cost(idx - w, 1.0)))
         };
         if self.is_exit_valid(x, y + 1) {
-            exits.push((idx + w, 1.0))
+            exits.push((idx + w, self.exit_cost(idx + w, 1.0)))
         };
 
         if self.is_exit_valid(x - 1, y - 1) {
-            exits.push(((idx - w) - 1, 1.45));
+            exits.push(((idx - w) - 1, self.exit_cost((idx - w) - 1, 1.45)));
         }
         if self.is_exit_valid(x + 1, y - 1) {
-            exits.push(((idx - w) + 1, 1.45));
+            exits.push(((idx - w) + 1, self.exit_cost((idx - w) + 1, 1.45)));
         }
         if self.is_exit_valid(x - 1, y + 1) {
-            exits.push(((idx + w) - 1, 1.45));
+            exits.push(((idx + w) - 1, self.exit_cost((idx + w) - 1, 1.45)));
         }
         if self.is_exit_valid(x + 1, y + 1) {
-            exits.push(((idx + w) + 1, 1.45));
+            exits.push(((idx + w) + 1, self.exit_cost((idx + w) + 1, 1.45)));
         }
 
         exits
@@ -147,46 +209,84 @@ impl BaseMap for Map {
     }
 }
 
-pub fn draw_map(map: &Map, ctx: &mut Rltk) {
-    let mut x = 0;
-    let mut y = 0;
-    for (idx, tile) in map.tiles.iter().enumerate() {
-        let glyph;
-        let mut fg;
-        let mut bg = RGB::from_f32(0., 0., 0.);
-
-        if map.revealed_tiles[idx] {
-            match tile {
-                TileType::Floor => {
-                    glyph = rltk::to_cp437('.');
-                    fg = RGB::from_f32(0.5, 0.5, 0.5);
-                }
-                TileType::Wall => {
-                    glyph = wall_glyph(&*map, x, y);
-                    fg = RGB::from_f32(0.0, 1.0, 0.0);
-                }
-                TileType::StairsDown => {
-                    glyph = rltk::to_cp437('>');
-                    fg = RGB::from_f32(0., 1.0, 1.0);
-                }
-            }
-
-            if map.blood_stains.contains(&idx) && map.visible_tiles[idx] {
-                bg = RGB::from_f32(0.75, 0., 0.);
-            }
-
-            if !map.visible_tiles[idx] {
-                fg = fg.to_greyscale()
-            }
-            ctx.set(x, y, fg, bg, glyph);
-        }
-
-        x += 1;
-        if x > 79 {
-            x = 0;
-            y += 1;
+pub fn tile_glyph(idx: usize, map: &Map) -> (rltk::FontCharType, RGB, RGB) {
+    let x = idx as i32 % map.width;
+    let y = idx as i32 / map.width;
+    let glyph;
+    let mut fg;
+    let mut bg = RGB::from_f32(0., 0., 0.);
+
+    match map.tiles[idx] {
+        TileType::Floor => {
+            glyph = rltk::to_cp437(map.theme.floor.glyph.unwrap_or('.'));
+            fg = map.theme.floor.color();
+        }
+        TileType::Wall => {
+            glyph = match map.theme.wall.glyph {
+                Some(g) => rltk::to_cp437(g),
+                None => wall_glyph(map, x, y),
+            };
+            fg = map.theme.wall.color();
+        }
+        TileType::StairsDown => {
+            glyph = rltk::to_cp437('>');
+            fg = RGB::from_f32(0., 1.0, 1.0);
+        }
+        TileType::StairsUp => {
+            glyph = rltk::to_cp437('<');
+            fg = RGB::from_f32(0., 1.0, 1.0);
+        }
+        TileType::ShallowWater => {
+            glyph = rltk::to_cp437('~');
+            fg = RGB::from_f32(0.4, 0.7, 1.0);
+        }
+        TileType::DeepWater => {
+            glyph = rltk::to_cp437('≈');
+            fg = RGB::from_f32(0.1, 0.2, 0.9);
+        }
+        TileType::Lava => {
+            glyph = rltk::to_cp437('≈');
+            fg = RGB::from_f32(1.0, 0.4, 0.0);
+        }
+        TileType::Chasm => {
+            glyph = rltk::to_cp437(':');
+            fg = RGB::from_f32(0.3, 0.3, 0.5);
         }
+        TileType::Rubble => {
+            glyph = rltk::to_cp437(';');
+            fg = RGB::from_f32(0.6, 0.5, 0.4);
+        }
+        TileType::Bridge => {
+            glyph = rltk::to_cp437('=');
+            fg = RGB::from_f32(0.7, 0.5, 0.2);
+        }
+        TileType::Grass => {
+            glyph = rltk::to_cp437('"');
+            fg = RGB::from_f32(0.2, 0.8, 0.2);
+        }
+        TileType::WoodFloor => {
+            glyph = rltk::to_cp437('.');
+            fg = RGB::from_f32(0.6, 0.4, 0.2);
+        }
+        TileType::StoneFloor => {
+            glyph = rltk::to_cp437('.');
+            fg = RGB::from_f32(0.75, 0.75, 0.8);
+        }
+        TileType::Gravel => {
+            glyph = rltk::to_cp437(',');
+            fg = RGB::from_f32(0.6, 0.6, 0.55);
+        }
+    }
+
+    if map.blood_stains.contains(&idx) && map.visible_tiles[idx] {
+        bg = RGB::from_f32(0.75, 0., 0.);
+    }
+
+    if !map.visible_tiles[idx] {
+        fg = fg.to_greyscale()
     }
+
+    (glyph, fg, bg)
 }
 
 fn is_revealed_and_wall(map: &Map, x: i32, y: i32) -> bool {
@@ -195,23 +295,10 @@ fn is_revealed_and_wall(map: &Map, x: i32, y: i32) -> bool {
 }
 
 fn wall_glyph(map: &Map, x: i32, y: i32) -> rltk::FontCharType {
-    if x < 1 || x > map.width - 2 || y < 1 || y > map.height - 2 {
-        return 35;
-    }
-
-    let mut mask: u8 = 0;
-    if is_revealed_and_wall(map, x, y - 1) {
-        mask += 1
-    }
-    if is_revealed_and_wall(map, x, y + 1) {
-        mask += 2
-    }
-    if is_revealed_and_wall(map, x - 1, y) {
-        mask += 4
-    }
-    if is_revealed_and_wall(map, x + 1, y) {
-        mask += 8
-    }
+    let mask = match wall_mask(map, x, y) {
+        Some(mask) => mask,
+        None => return 35,
+    };
 
     match mask {
         0 => 9,    // Pillar because we can't see neighbors
@@ -233,3 +320,26 @@ fn wall_glyph(map: &Map, x: i32, y: i32) -> rltk::FontCharType {
         _ => 35,   // We missed one?
     }
 }
+
+/// Which neighbours of a wall are known walls too: north 1, south 2, west 4, east 8. Walls on the
+/// edge of the map have no mask.
+pub fn wall_mask(map: &Map, x: i32, y: i32) -> Option<u8> {
+    if x < 1 || x > map.width - 2 || y < 1 || y > map.height - 2 {
+        return None;
+    }
+
+    let mut mask: u8 = 0;
+    if is_revealed_and_wall(map, x, y - 1) {
+        mask += 1
+    }
+    if is_revealed_and_wall(map, x, y + 1) {
+        mask += 2
+    }
+    if is_revealed_and_wall(map, x - 1, y) {
+        mask += 4
+    }
+    if is_revealed_and_wall(map, x + 1, y) {
+        mask += 8
+    }
+    Some(mask)
+}
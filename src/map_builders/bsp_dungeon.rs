@@ -1,6 +1,6 @@
 use crate::map_builders::room_and_corridor_creation::apply_room_to_map;
 use crate::map_builders::{build_snapshot, MapBuilder};
-use crate::{spawner, Map, Position, Rect, TileType, SHOW_MAPGEN_VISUALIZER, impl_map_builder_with_rooms};
+use crate::{spawner, Map, Position, Rect, TileType, impl_map_builder_with_rooms};
 use rltk::RandomNumberGenerator;
 use specs::World;
 
@@ -14,9 +14,9 @@ pub struct BspDungeonBuilder {
 }
 
 impl BspDungeonBuilder {
-    pub fn new(new_depth: i32) -> BspDungeonBuilder {
+    pub fn new(new_depth: i32, width: i32, height: i32) -> BspDungeonBuilder {
         BspDungeonBuilder {
-            map: Map::new(new_depth),
+            map: Map::new(new_depth, width, height),
             starting_position: Position::origin(),
             depth: new_depth,
             rooms: Vec::new(),
@@ -25,10 +25,9 @@ impl BspDungeonBuilder {
         }
     }
 
-    fn build(&mut self) {
-        let mut rng = RandomNumberGenerator::new();
+    fn build(&mut self, rng: &mut RandomNumberGenerator) {
 
-        self.add_rooms(&mut rng);
+        self.add_rooms(rng);
 
         let (x, y) = self.rooms[0].center();
         self.starting_position = Position { x, y };
@@ -37,7 +36,7 @@ impl BspDungeonBuilder {
 
         self.add_stairs();
 
-        self.add_corridors(&mut rng);
+        self.add_corridors(rng);
     }
 
     fn add_stairs(&mut self) {
@@ -46,7 +45,7 @@ impl BspDungeonBuilder {
         self.map.tiles[stairs_position] = TileType::StairsDown;
     }
 
-    fn add_rooms(&mut self, mut rng: &mut RandomNumberGenerator) {
+    fn add_rooms(&mut self, rng: &mut RandomNumberGenerator) {
         self.rects.clear();
         self.rects
             .push(Rect::new(2, 2, self.map.width - 5, self.map.height - 5));
@@ -55,8 +54,8 @@ impl BspDungeonBuilder {
 
         let mut room_count = 0;
         while room_count < 240 {
-            let rect = self.get_random_rect(&mut rng);
-            let candidate = self.get_random_sub_rect(rect, &mut rng);
+            let rect = self.get_random_rect(rng);
+            let candidate = self.get_random_sub_rect(rect, rng);
 
             if self.is_possible(candidate) {
                 apply_room_to_map(&mut self.map, &candidate);
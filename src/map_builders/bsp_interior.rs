@@ -1,5 +1,5 @@
 use crate::map_builders::{build_snapshot, MapBuilder};
-use crate::{spawner, Map, Position, Rect, TileType, SHOW_MAPGEN_VISUALIZER, impl_map_builder_with_rooms};
+use crate::{spawner, Map, Position, Rect, TileType, impl_map_builder_with_rooms};
 use rltk::RandomNumberGenerator;
 use specs::World;
 
@@ -17,9 +17,9 @@ pub struct BspInteriorBuilder {
 impl_map_builder_with_rooms!(BspInteriorBuilder);
 
 impl BspInteriorBuilder {
-    pub fn new(new_depth: i32) -> BspInteriorBuilder {
+    pub fn new(new_depth: i32, width: i32, height: i32) -> BspInteriorBuilder {
         BspInteriorBuilder {
-            map: Map::new(new_depth),
+            map: Map::new(new_depth, width, height),
             starting_position: Position::origin(),
             depth: new_depth,
             rooms: Vec::new(),
@@ -28,13 +28,12 @@ impl BspInteriorBuilder {
         }
     }
 
-    pub fn build(&mut self) {
-        let mut rng = RandomNumberGenerator::new();
+    pub fn build(&mut self, rng: &mut RandomNumberGenerator) {
         self.rects.clear();
         self.rects
             .push(Rect::new(1, 1, self.map.width - 2, self.map.height - 2));
         let first_room = self.rects[0];
-        self.add_subrects(first_room, &mut rng);
+        self.add_subrects(first_room, rng);
 
         let rooms = self.rects.clone();
         for r in rooms.iter() {
@@ -44,7 +43,7 @@ impl BspInteriorBuilder {
                 for x in room.x1..room.x2 {
                     let i = self.map.xy_idx(x, y);
                     if i > 0 && i < ((self.map.width * self.map.height) - 1) as usize {
-                        self.map.tiles[i] = TileType::Floor;
+                        self.map.tiles[i] = TileType::StoneFloor;
                     }
                 }
             }
@@ -52,7 +51,7 @@ impl BspInteriorBuilder {
             self.take_snapshot();
         }
 
-        self.add_corridors(&mut rng);
+        self.add_corridors(rng);
 
         self.add_stairs();
 
@@ -101,7 +100,9 @@ impl BspInteriorBuilder {
             }
 
             let idx = self.map.xy_idx(x, y);
-            self.map.tiles[idx] = TileType::Floor;
+            if self.map.tiles[idx] == TileType::Wall {
+                self.map.tiles[idx] = TileType::Floor;
+            }
         }
     }
 
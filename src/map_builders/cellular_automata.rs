@@ -1,34 +1,33 @@
-use crate::{Map, Position, SHOW_MAPGEN_VISUALIZER, TileType, spawner, impl_map_builder_with_noise_areas};
+use crate::{Map, Position, TileType, spawner, impl_map_builder_with_noise_areas};
 use crate::map_builders::{build_snapshot, MapBuilder};
 use specs::World;
 use rltk::{RandomNumberGenerator};
-use std::collections::HashMap;
-use crate::map_builders::map_processing::{generate_voronoi_spawn_regions, remove_unreachable_areas};
+use std::collections::BTreeMap;
+use crate::map_builders::map_processing::{add_terrain_features, generate_voronoi_spawn_regions, remove_unreachable_areas};
 
 pub struct CellularAutomataBuilder {
     map: Map,
     starting_position: Position,
     depth: i32,
     history: Vec<Map>,
-    noise_areas: HashMap<i32, Vec<usize>>,
+    noise_areas: BTreeMap<i32, Vec<usize>>,
 }
 
 impl_map_builder_with_noise_areas!(CellularAutomataBuilder);
 
 impl CellularAutomataBuilder {
-    pub fn new(new_depth: i32) -> CellularAutomataBuilder {
+    pub fn new(new_depth: i32, width: i32, height: i32) -> CellularAutomataBuilder {
         CellularAutomataBuilder {
-            map: Map::new(new_depth),
+            map: Map::new(new_depth, width, height),
             starting_position: Position { x: 0, y: 0 },
             depth: new_depth,
             history: Vec::new(),
-            noise_areas: HashMap::new(),
+            noise_areas: BTreeMap::new(),
         }
     }
 
     #[allow(clippy::map_entry)]
-    pub fn build(&mut self) {
-        let mut rng = RandomNumberGenerator::new();
+    pub fn build(&mut self, rng: &mut RandomNumberGenerator) {
 
         for y in 1..self.map.height - 1 {
             for x in 1..self.map.width - 1 {
@@ -86,7 +85,10 @@ impl CellularAutomataBuilder {
         self.map.tiles[exit_tile] = TileType::StairsDown;
         self.take_snapshot();
 
-        self.noise_areas = generate_voronoi_spawn_regions(&self.map, &mut rng);
+        add_terrain_features(&mut self.map, start_index, rng);
+        self.take_snapshot();
+
+        self.noise_areas = generate_voronoi_spawn_regions(&self.map, rng);
         self.take_snapshot();
     }
 }
\ No newline at end of file
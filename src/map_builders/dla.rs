@@ -1,5 +1,5 @@
-use crate::{Position, Map, TileType, spawner, SHOW_MAPGEN_VISUALIZER, impl_map_builder_with_noise_areas};
-use std::collections::HashMap;
+use crate::{Position, Map, TileType, spawner, impl_map_builder_with_noise_areas};
+use std::collections::BTreeMap;
 use rltk::{RandomNumberGenerator, Point};
 use specs::World;
 use crate::map_builders::{build_snapshot, MapBuilder};
@@ -15,7 +15,7 @@ pub struct DLABuilder {
     starting_position: Position,
     depth: i32,
     history: Vec<Map>,
-    noise_areas: HashMap<i32, Vec<usize>>,
+    noise_areas: BTreeMap<i32, Vec<usize>>,
     algorithm: DLAAlgorithm,
     brush_size: i32,
     symmetry: Symmetry,
@@ -25,13 +25,13 @@ pub struct DLABuilder {
 impl_map_builder_with_noise_areas!(DLABuilder);
 
 impl DLABuilder {
-    fn create(new_depth: i32, algorithm: DLAAlgorithm, symmetry: Symmetry, brush_size: i32, floor_percent: f32) -> DLABuilder {
+    fn create(new_depth: i32, width: i32, height: i32, algorithm: DLAAlgorithm, symmetry: Symmetry, brush_size: i32, floor_percent: f32) -> DLABuilder {
         DLABuilder {
-            map: Map::new(new_depth),
+            map: Map::new(new_depth, width, height),
             starting_position: Position { x: 0, y: 0 },
             depth: new_depth,
             history: Vec::new(),
-            noise_areas: HashMap::new(),
+            noise_areas: BTreeMap::new(),
             algorithm,
             brush_size,
             symmetry,
@@ -39,20 +39,20 @@ impl DLABuilder {
         }
     }
 
-    pub fn walk_inwards(new_depth: i32) -> DLABuilder {
-        DLABuilder::create(new_depth, DLAAlgorithm::WalkInwards, Symmetry::None, 1, 0.25)
+    pub fn walk_inwards(new_depth: i32, width: i32, height: i32) -> DLABuilder {
+        DLABuilder::create(new_depth, width, height, DLAAlgorithm::WalkInwards, Symmetry::None, 1, 0.25)
     }
 
-    pub fn walk_outwards(new_depth: i32) -> DLABuilder {
-        DLABuilder::create(new_depth, DLAAlgorithm::WalkOutwards, Symmetry::None, 2, 0.25)
+    pub fn walk_outwards(new_depth: i32, width: i32, height: i32) -> DLABuilder {
+        DLABuilder::create(new_depth, width, height, DLAAlgorithm::WalkOutwards, Symmetry::None, 2, 0.25)
     }
 
-    pub fn central_attractor(new_depth: i32) -> DLABuilder {
-        DLABuilder::create(new_depth, DLAAlgorithm::CentralAttractor, Symmetry::None, 2, 0.25)
+    pub fn central_attractor(new_depth: i32, width: i32, height: i32) -> DLABuilder {
+        DLABuilder::create(new_depth, width, height, DLAAlgorithm::CentralAttractor, Symmetry::None, 2, 0.25)
     }
 
-    pub fn insectoid(new_depth: i32) -> DLABuilder {
-        DLABuilder::create(new_depth, DLAAlgorithm::CentralAttractor, Symmetry::Horizontal, 2, 0.25)
+    pub fn insectoid(new_depth: i32, width: i32, height: i32) -> DLABuilder {
+        DLABuilder::create(new_depth, width, height, DLAAlgorithm::CentralAttractor, Symmetry::Horizontal, 2, 0.25)
     }
 
     fn process_walk_inwards(&self, rng: &mut RandomNumberGenerator) -> (i32, i32) {
@@ -125,8 +125,7 @@ impl DLABuilder {
     }
 
     #[allow(clippy::map_entry)]
-    pub fn build(&mut self) {
-        let mut rng = RandomNumberGenerator::new();
+    pub fn build(&mut self, rng: &mut RandomNumberGenerator) {
 
         self.starting_position = Position { x: self.map.width / 2, y: self.map.height / 2 };
         let start_index = self.map.xy_idx(self.starting_position.x, self.starting_position.y);
@@ -147,15 +146,15 @@ impl DLABuilder {
         while floor_tile_count < desired_floor_tiles {
             match self.algorithm {
                 DLAAlgorithm::WalkInwards => {
-                    let (x, y) = self.process_walk_inwards(&mut rng);
+                    let (x, y) = self.process_walk_inwards(rng);
                     paint(&mut self.map, self.symmetry, self.brush_size, x, y);
                 },
                 DLAAlgorithm::WalkOutwards => {
-                    let (x, y) = self.process_walk_outwards(&mut rng);
+                    let (x, y) = self.process_walk_outwards(rng);
                     paint(&mut self.map, self.symmetry, self.brush_size, x, y);
                 }
                 DLAAlgorithm::CentralAttractor => {
-                    let (x, y) = self.process_central_attractor(&mut rng);
+                    let (x, y) = self.process_central_attractor(rng);
                     paint(&mut self.map, self.symmetry, self.brush_size, x, y);
                 }
             }
@@ -173,7 +172,7 @@ impl DLABuilder {
         self.map.tiles[exit_tile] = TileType::StairsDown;
         self.take_snapshot();
 
-        self.noise_areas = generate_voronoi_spawn_regions(&self.map, &mut rng);
+        self.noise_areas = generate_voronoi_spawn_regions(&self.map, rng);
 
         self.take_snapshot();
     }
@@ -1,5 +1,5 @@
-use std::collections::HashMap;
-use crate::{Map, Position, spawner, SHOW_MAPGEN_VISUALIZER, TileType, impl_map_builder_with_noise_areas};
+use std::collections::BTreeMap;
+use crate::{Map, Position, spawner, TileType, impl_map_builder_with_noise_areas};
 use crate::map_builders::{build_snapshot, MapBuilder};
 use specs::World;
 use rltk::RandomNumberGenerator;
@@ -23,26 +23,26 @@ pub struct DrunkardsWalkBuilder {
     starting_position: Position,
     depth: i32,
     history: Vec<Map>,
-    noise_areas: HashMap<i32, Vec<usize>>,
+    noise_areas: BTreeMap<i32, Vec<usize>>,
     settings: DrunkardSettings
 }
 
 impl_map_builder_with_noise_areas!(DrunkardsWalkBuilder);
 
 impl DrunkardsWalkBuilder {
-    pub fn new(new_depth: i32, settings: DrunkardSettings) -> DrunkardsWalkBuilder {
+    pub fn new(new_depth: i32, width: i32, height: i32, settings: DrunkardSettings) -> DrunkardsWalkBuilder {
         DrunkardsWalkBuilder {
-            map: Map::new(new_depth),
+            map: Map::new(new_depth, width, height),
             starting_position: Position {x: 0, y: 0 },
             depth: new_depth,
             history: Vec::new(),
-            noise_areas: HashMap::new(),
+            noise_areas: BTreeMap::new(),
             settings,
         }
     }
 
-    pub fn open_area(new_depth: i32) -> DrunkardsWalkBuilder {
-        DrunkardsWalkBuilder::new(new_depth, DrunkardSettings {
+    pub fn open_area(new_depth: i32, width: i32, height: i32) -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder::new(new_depth, width, height, DrunkardSettings {
             floor_percent: 0.5,
             drunken_lifetime: 400,
             spawn_mode: DrunkSpawnMode::StartingPoint,
@@ -51,8 +51,8 @@ impl DrunkardsWalkBuilder {
         })
     }
 
-    pub fn open_halls(new_depth: i32) -> DrunkardsWalkBuilder {
-        DrunkardsWalkBuilder::new(new_depth, DrunkardSettings {
+    pub fn open_halls(new_depth: i32, width: i32, height: i32) -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder::new(new_depth, width, height, DrunkardSettings {
             spawn_mode: DrunkSpawnMode::Random,
             drunken_lifetime: 400,
             floor_percent: 0.5,
@@ -61,8 +61,8 @@ impl DrunkardsWalkBuilder {
         })
     }
 
-    pub fn winding_passages(new_depth: i32) -> DrunkardsWalkBuilder {
-        DrunkardsWalkBuilder::new(new_depth, DrunkardSettings {
+    pub fn winding_passages(new_depth: i32, width: i32, height: i32) -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder::new(new_depth, width, height, DrunkardSettings {
             spawn_mode: DrunkSpawnMode::Random,
             floor_percent: 0.4,
             drunken_lifetime: 100,
@@ -71,8 +71,8 @@ impl DrunkardsWalkBuilder {
         })
     }
 
-    pub fn big_passages(new_depth: i32) -> DrunkardsWalkBuilder {
-        DrunkardsWalkBuilder::new(new_depth, DrunkardSettings {
+    pub fn big_passages(new_depth: i32, width: i32, height: i32) -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder::new(new_depth, width, height, DrunkardSettings {
             spawn_mode: DrunkSpawnMode::Random,
             drunken_lifetime: 100,
             floor_percent: 0.4,
@@ -81,8 +81,8 @@ impl DrunkardsWalkBuilder {
         })
     }
 
-    pub fn fearful_symmetry(new_depth: i32) -> DrunkardsWalkBuilder {
-        DrunkardsWalkBuilder::new(new_depth, DrunkardSettings {
+    pub fn fearful_symmetry(new_depth: i32, width: i32, height: i32) -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder::new(new_depth, width, height, DrunkardSettings {
             spawn_mode: DrunkSpawnMode::Random,
             drunken_lifetime: 100,
             floor_percent: 0.4,
@@ -92,8 +92,7 @@ impl DrunkardsWalkBuilder {
     }
 
     #[allow(clippy::map_entry)]
-    fn build(&mut self) {
-        let mut rng = RandomNumberGenerator::new();
+    fn build(&mut self, rng: &mut RandomNumberGenerator) {
 
         self.starting_position = Position { x: self.map.width / 2, y: self.map.height / 2 };
         let start_index = self.map.xy_idx(self.starting_position.x, self.starting_position.y);
@@ -162,7 +161,7 @@ impl DrunkardsWalkBuilder {
             floor_tile_count = self.map.tiles.iter().filter(|a| **a == TileType::Floor).count();
         }
 
-        rltk::console::log(format!("{} dwarves gave up their sobriety, of whom {} actually found a wall.", digger_count, active_digger_count));
+        crate::settings::log(crate::settings::LogLevel::Verbose, format!("{} dwarves gave up their sobriety, of whom {} actually found a wall.", digger_count, active_digger_count));
 
         let exit_tile = remove_unreachable_areas(&mut self.map, start_index);
         self.take_snapshot();
@@ -170,7 +169,7 @@ impl DrunkardsWalkBuilder {
         self.map.tiles[exit_tile] = TileType::StairsDown;
         self.take_snapshot();
 
-        self.noise_areas = generate_voronoi_spawn_regions(&self.map, &mut rng);
+        self.noise_areas = generate_voronoi_spawn_regions(&self.map, rng);
     }
 
 }
\ No newline at end of file
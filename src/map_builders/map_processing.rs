@@ -1,5 +1,6 @@
 use crate::{Map, TileType};
-use std::collections::HashMap;
+use rltk::BaseMap;
+use std::collections::{BTreeMap, VecDeque};
 
 pub fn  remove_unreachable_areas(map: &mut Map, start_index: usize) -> usize {
     map.populate_blocked();
@@ -21,9 +22,117 @@ pub fn  remove_unreachable_areas(map: &mut Map, start_index: usize) -> usize {
     exit_tile.0
 }
 
+/// Scatters pools of water, lava, chasms with a bridge across and patches of rubble over open
+/// floor. A feature that would cut off part of the level from `start_index` is taken out again,
+/// so everything that was reachable stays reachable.
+pub fn add_terrain_features(map: &mut Map, start_index: usize, rng: &mut rltk::RandomNumberGenerator) {
+    let feature_count = (map.width * map.height) / 800;
+    let start_x = start_index as i32 % map.width;
+    let start_y = start_index as i32 / map.width;
+    let mut reachable = count_reachable(map, start_index);
+
+    for _i in 0..feature_count {
+        let mut candidates: Vec<(i32, i32)> = Vec::new();
+        for y in 3..map.height - 3 {
+            for x in 3..map.width - 3 {
+                let near_start = (x - start_x).abs() <= 4 && (y - start_y).abs() <= 4;
+                if !near_start && is_open_floor(map, x, y, 2) {
+                    candidates.push((x, y));
+                }
+            }
+        }
+        if candidates.is_empty() {
+            break;
+        }
+        let (x, y) = candidates[(rng.roll_dice(1, candidates.len() as i32) - 1) as usize];
+
+        let before = map.tiles.clone();
+        let radius = rng.roll_dice(1, 2);
+        match rng.roll_dice(1, 6) {
+            1 | 2 => paint_pool(map, x, y, radius, TileType::DeepWater, TileType::ShallowWater),
+            3 if map.depth >= 3 => paint_pool(map, x, y, radius, TileType::Lava, TileType::Rubble),
+            4 => {
+                paint_pool(map, x, y, radius, TileType::Chasm, TileType::Floor);
+                for bx in x - radius..=x + radius {
+                    if map.get_tile(bx, y) == TileType::Chasm {
+                        map.set_tile(bx, y, TileType::Bridge);
+                    }
+                }
+            }
+            _ => {
+                for ry in y - 2..=y + 2 {
+                    for rx in x - 2..=x + 2 {
+                        if map.get_tile(rx, ry) == TileType::Floor && rng.roll_dice(1, 2) == 1 {
+                            map.set_tile(rx, ry, TileType::Rubble);
+                        }
+                    }
+                }
+            }
+        }
+
+        let blocked = before.iter().zip(map.tiles.iter())
+            .filter(|(old, new)| old.is_walkable() && !new.is_walkable())
+            .count();
+        let now_reachable = count_reachable(map, start_index);
+        if now_reachable + blocked == reachable {
+            reachable = now_reachable;
+        } else {
+            map.tiles = before;
+        }
+    }
+    map.populate_blocked();
+}
+
+fn is_open_floor(map: &Map, x: i32, y: i32, radius: i32) -> bool {
+    for ny in y - radius..=y + radius {
+        for nx in x - radius..=x + radius {
+            if nx < 1 || nx > map.width - 2 || ny < 1 || ny > map.height - 2 || map.get_tile(nx, ny) != TileType::Floor {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Fills floor within `radius` of (x, y) with `centre` and the floor around that with `edge`.
+fn paint_pool(map: &mut Map, x: i32, y: i32, radius: i32, centre: TileType, edge: TileType) {
+    for ny in y - radius - 1..=y + radius + 1 {
+        for nx in x - radius - 1..=x + radius + 1 {
+            if nx < 1 || nx > map.width - 2 || ny < 1 || ny > map.height - 2 || map.get_tile(nx, ny) != TileType::Floor {
+                continue;
+            }
+            let distance = rltk::DistanceAlg::Pythagoras.distance2d(rltk::Point::new(x, y), rltk::Point::new(nx, ny));
+            if distance <= radius as f32 {
+                map.set_tile(nx, ny, centre);
+            } else if distance <= radius as f32 + 1.0 {
+                map.set_tile(nx, ny, edge);
+            }
+        }
+    }
+}
+
+fn count_reachable(map: &mut Map, start_index: usize) -> usize {
+    map.populate_blocked();
+    let mut seen = vec![false; map.tiles.len()];
+    let mut open = VecDeque::new();
+    seen[start_index] = true;
+    open.push_back(start_index);
+    let mut count = 0;
+    while let Some(idx) = open.pop_front() {
+        count += 1;
+        for (exit, _cost) in map.get_available_exits(idx) {
+            if !seen[exit] {
+                seen[exit] = true;
+                open.push_back(exit);
+            }
+        }
+    }
+    count
+}
+
 #[allow(clippy::map_entry)]
-pub fn generate_voronoi_spawn_regions(map: &Map, rng: &mut rltk::RandomNumberGenerator) -> HashMap<i32, Vec<usize>> {
-    let mut noise_areas: HashMap<i32, Vec<usize>> = HashMap::new();
+pub fn generate_voronoi_spawn_regions(map: &Map, rng: &mut rltk::RandomNumberGenerator) -> BTreeMap<i32, Vec<usize>> {
+    let mut noise_areas: BTreeMap<i32, Vec<usize>> = BTreeMap::new();
     let mut noise = rltk::FastNoise::seeded(rng.roll_dice(1, 65536) as u64);
     noise.set_noise_type(rltk::NoiseType::Cellular);
     noise.set_frequency(0.08);
@@ -32,7 +141,7 @@ pub fn generate_voronoi_spawn_regions(map: &Map, rng: &mut rltk::RandomNumberGen
     for y in 1..map.height - 1 {
         for x in 1..map.width - 1 {
             let i = map.xy_idx(x, y);
-            if map.tiles[i] == TileType::Floor {
+            if map.tiles[i].allows_spawns() {
                 let cv = noise.get_noise(x as f32, y as f32) * 10240.0;
                 let cell_value = cv as i32;
 
@@ -45,4 +154,51 @@ pub fn generate_voronoi_spawn_regions(map: &Map, rng: &mut rltk::RandomNumberGen
         }
     }
     noise_areas
-}
\ No newline at end of file
+}
+pub fn find_doorways(map: &Map) -> Vec<usize> {
+    let mut doorways: Vec<usize> = Vec::new();
+
+    for y in 2..map.height - 2 {
+        for x in 2..map.width - 2 {
+            if map.get_tile(x, y) != TileType::Floor {
+                continue;
+            }
+
+            let walls_east_west = map.get_tile(x - 1, y) == TileType::Wall && map.get_tile(x + 1, y) == TileType::Wall;
+            let walls_north_south = map.get_tile(x, y - 1) == TileType::Wall && map.get_tile(x, y + 1) == TileType::Wall;
+
+            let opens_into_room = if walls_east_west && !walls_north_south {
+                is_open_area(map, x, y - 1) || is_open_area(map, x, y + 1)
+            } else if walls_north_south && !walls_east_west {
+                is_open_area(map, x - 1, y) || is_open_area(map, x + 1, y)
+            } else {
+                false
+            };
+
+            let next_to_doorway = doorways.iter().any(|d| {
+                let dx = (*d as i32 % map.width) - x;
+                let dy = (*d as i32 / map.width) - y;
+                dx.abs() <= 1 && dy.abs() <= 1
+            });
+
+            if opens_into_room && !next_to_doorway {
+                doorways.push(map.xy_idx(x, y));
+            }
+        }
+    }
+
+    doorways
+}
+
+fn is_open_area(map: &Map, x: i32, y: i32) -> bool {
+    let mut floor_count = 0;
+    for ny in y - 1..=y + 1 {
+        for nx in x - 1..=x + 1 {
+            if map.get_tile(nx, ny) != TileType::Wall {
+                floor_count += 1;
+            }
+        }
+    }
+
+    floor_count >= 6
+}
@@ -1,5 +1,5 @@
-use std::collections::HashMap;
-use crate::{Map, Position, spawner, SHOW_MAPGEN_VISUALIZER, TileType, impl_map_builder_with_noise_areas};
+use std::collections::BTreeMap;
+use crate::{Map, Position, spawner, TileType, impl_map_builder_with_noise_areas};
 use crate::map_builders::{build_snapshot, MapBuilder};
 use specs::World;
 use rltk::RandomNumberGenerator;
@@ -10,7 +10,7 @@ pub struct MazeBuilder {
     starting_position: Position,
     depth: i32,
     history: Vec<Map>,
-    noise_areas: HashMap<i32, Vec<usize>>,
+    noise_areas: BTreeMap<i32, Vec<usize>>,
 }
 
 impl_map_builder_with_noise_areas!(MazeBuilder);
@@ -186,21 +186,20 @@ impl<'a> Grid<'a> {
 }
 
 impl MazeBuilder {
-    pub fn new(new_depth: i32) -> MazeBuilder {
+    pub fn new(new_depth: i32, width: i32, height: i32) -> MazeBuilder {
         MazeBuilder {
-            map: Map::new(new_depth),
+            map: Map::new(new_depth, width, height),
             starting_position: Position::origin(),
             depth: new_depth,
             history: Vec::new(),
-            noise_areas: HashMap::new(),
+            noise_areas: BTreeMap::new(),
         }
     }
 
     #[allow(clippy::map_entry)]
-    fn build(&mut self) {
-        let mut rng = RandomNumberGenerator::new();
+    fn build(&mut self, rng: &mut RandomNumberGenerator) {
 
-        let mut grid = Grid::new((self.map.width / 2) - 2, (self.map.height / 2) - 2, &mut rng);
+        let mut grid = Grid::new((self.map.width / 2) - 2, (self.map.height / 2) - 2, rng);
         grid.generate_maze(self);
 
         self.starting_position = Position { x: 2, y: 2 };
@@ -213,6 +212,6 @@ impl MazeBuilder {
         self.map.tiles[exit_tile] = TileType::StairsDown;
         self.take_snapshot();
 
-        self.noise_areas = generate_voronoi_spawn_regions(&self.map, &mut rng);
+        self.noise_areas = generate_voronoi_spawn_regions(&self.map, rng);
     }
 }
\ No newline at end of file
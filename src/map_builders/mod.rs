@@ -12,8 +12,10 @@ mod voronoi;
 mod waveform_collapse;
 mod prefab_builder;
 mod prefab_levels;
+mod town;
 
-use crate::{Map, Position, SHOW_MAPGEN_VISUALIZER};
+use crate::settings::{self, LogLevel};
+use crate::{Map, Position, Theme};
 
 use specs::World;
 
@@ -24,11 +26,12 @@ use crate::map_builders::cellular_automata::CellularAutomataBuilder;
 use crate::map_builders::drunkard::DrunkardsWalkBuilder;
 use crate::map_builders::maze::MazeBuilder;
 use crate::map_builders::simple_map::SimpleMapBuilder;
+use crate::map_builders::town::TownBuilder;
 use crate::map_builders::voronoi::VoronoiBuilder;
 use crate::map_builders::waveform_collapse::WaveformCollapseBuilder;
 
 pub trait MapBuilder {
-    fn build_map(&mut self);
+    fn build_map(&mut self, rng: &mut rltk::RandomNumberGenerator);
     fn spawn_entities(&mut self, ecs: &mut World);
     fn get_map(&self) -> Map;
     fn get_starting_position(&self) -> Position;
@@ -40,8 +43,8 @@ pub trait MapBuilder {
 macro_rules! impl_map_builder_with_noise_areas {
     ($($t:ty),+ $(,)?) => ($(
         impl MapBuilder for $t {
-            fn build_map(&mut self) {
-                self.build();
+            fn build_map(&mut self, rng: &mut rltk::RandomNumberGenerator) {
+                self.build(rng);
             }
 
             fn spawn_entities(&mut self, ecs: &mut World) {
@@ -76,14 +79,17 @@ macro_rules! impl_map_builder_with_noise_areas {
 macro_rules! impl_map_builder_with_rooms {
     ($($t:ty),+ $(,)?) => ($(
         impl MapBuilder for $t {
-                fn build_map(&mut self) {
-                    self.build();
+                fn build_map(&mut self, rng: &mut rltk::RandomNumberGenerator) {
+                    self.build(rng);
                 }
 
                 fn spawn_entities(&mut self, ecs: &mut World) {
                     for room in self.rooms.iter().skip(1) {
                         spawner::spawn_room(ecs, room, self.depth);
                     }
+
+                    let doorways = $crate::map_builders::map_processing::find_doorways(&self.map);
+                    spawner::spawn_doors(ecs, &doorways, &self.rooms[0]);
                 }
 
                 fn get_map(&self) -> Map {
@@ -109,7 +115,7 @@ macro_rules! impl_map_builder_with_rooms {
 }
 
 pub fn build_snapshot(map: &Map) -> Option<Map> {
-    if SHOW_MAPGEN_VISUALIZER {
+    if settings::show_mapgen_visualizer() {
         let mut snapshot = map.clone();
         for v in snapshot.revealed_tiles.iter_mut() {
             *v = true;
@@ -121,37 +127,92 @@ pub fn build_snapshot(map: &Map) -> Option<Map> {
     None
 }
 
-pub fn static_builder(new_depth: i32) -> Box<dyn MapBuilder> {
-    Box::new(DLABuilder::walk_inwards(new_depth))
+pub fn level_dimensions(depth: i32) -> (i32, i32) {
+    if depth == 0 {
+        return (96, 56);
+    }
+
+    let width = i32::min(80 + (depth - 1) * 8, 160);
+    let height = i32::min(43 + (depth - 1) * 4, 80);
+    (width, height)
+}
+
+/// The town at depth 0, whatever the settings say about the dungeon levels.
+pub fn town_builder(width: i32, height: i32) -> Box<dyn MapBuilder> {
+    Box::new(TownBuilder::new(0, width, height))
 }
 
-pub fn random_builder(new_depth: i32) -> Box<dyn MapBuilder> {
-    let mut rng = rltk::RandomNumberGenerator::new();
-    let builder = rng.roll_dice(1, 16);
-    rltk::log(format!("Using builder # {}", builder));
-    let result: Box<dyn MapBuilder> = match builder {
-        1 => Box::new(BspDungeonBuilder::new(new_depth)),
-        2 => Box::new(BspInteriorBuilder::new(new_depth)),
-        3 => Box::new(CellularAutomataBuilder::new(new_depth)),
-        4 => Box::new(DrunkardsWalkBuilder::open_area(new_depth)),
-        5 => Box::new(DrunkardsWalkBuilder::open_halls(new_depth)),
-        6 => Box::new(DrunkardsWalkBuilder::winding_passages(new_depth)),
-        7 => Box::new(DrunkardsWalkBuilder::big_passages(new_depth)),
-        8 => Box::new(DrunkardsWalkBuilder::fearful_symmetry(new_depth)),
-        9 => Box::new(MazeBuilder::new(new_depth)),
-        10 => Box::new(DLABuilder::walk_inwards(new_depth)),
-        11 => Box::new(DLABuilder::walk_outwards(new_depth)),
-        12 => Box::new(DLABuilder::central_attractor(new_depth)),
-        13 => Box::new(DLABuilder::insectoid(new_depth)),
-        14 => Box::new(VoronoiBuilder::pythagoras(new_depth)),
-        15 => Box::new(VoronoiBuilder::manhattan(new_depth)),
-        _ => Box::new(SimpleMapBuilder::new(new_depth))
+pub fn static_builder(new_depth: i32, width: i32, height: i32) -> Box<dyn MapBuilder> {
+    Box::new(DLABuilder::walk_inwards(new_depth, width, height))
+}
+
+pub fn random_builder(new_depth: i32, width: i32, height: i32, theme: &Theme, rng: &mut rltk::RandomNumberGenerator) -> Box<dyn MapBuilder> {
+    let themed_builder = if theme.builders.is_empty() {
+        None
+    } else {
+        let choice = (rng.roll_dice(1, theme.builders.len() as i32) - 1) as usize;
+        settings::log(LogLevel::Verbose, format!("Theme {} using builder {}", theme.name, theme.builders[choice]));
+        named_builder(&theme.builders[choice], new_depth, width, height)
     };
 
-    if rng.roll_dice(1, 3) == 1 {
-        rltk::log("Layering the waveform collapse builder on top");
-        Box::new(WaveformCollapseBuilder::derived_map(new_depth, result))
+    let result: Box<dyn MapBuilder> = match themed_builder {
+        Some(builder) => builder,
+        None => {
+            let builder = rng.roll_dice(1, 16);
+            settings::log(LogLevel::Verbose, format!("Using builder # {}", builder));
+            match builder {
+                1 => Box::new(BspDungeonBuilder::new(new_depth, width, height)),
+                2 => Box::new(BspInteriorBuilder::new(new_depth, width, height)),
+                3 => Box::new(CellularAutomataBuilder::new(new_depth, width, height)),
+                4 => Box::new(DrunkardsWalkBuilder::open_area(new_depth, width, height)),
+                5 => Box::new(DrunkardsWalkBuilder::open_halls(new_depth, width, height)),
+                6 => Box::new(DrunkardsWalkBuilder::winding_passages(new_depth, width, height)),
+                7 => Box::new(DrunkardsWalkBuilder::big_passages(new_depth, width, height)),
+                8 => Box::new(DrunkardsWalkBuilder::fearful_symmetry(new_depth, width, height)),
+                9 => Box::new(MazeBuilder::new(new_depth, width, height)),
+                10 => Box::new(DLABuilder::walk_inwards(new_depth, width, height)),
+                11 => Box::new(DLABuilder::walk_outwards(new_depth, width, height)),
+                12 => Box::new(DLABuilder::central_attractor(new_depth, width, height)),
+                13 => Box::new(DLABuilder::insectoid(new_depth, width, height)),
+                14 => Box::new(VoronoiBuilder::pythagoras(new_depth, width, height)),
+                15 => Box::new(VoronoiBuilder::manhattan(new_depth, width, height)),
+                _ => Box::new(SimpleMapBuilder::new(new_depth, width, height))
+            }
+        }
+    };
+
+    if new_depth > 0 && rng.roll_dice(1, 3) == 1 {
+        settings::log(LogLevel::Verbose, "Layering the waveform collapse builder on top");
+        Box::new(WaveformCollapseBuilder::derived_map(new_depth, width, height, result))
     } else {
         result
     }
 }
+
+pub fn named_builder(name: &str, new_depth: i32, width: i32, height: i32) -> Option<Box<dyn MapBuilder>> {
+    let result: Box<dyn MapBuilder> = match name {
+        "town" => Box::new(TownBuilder::new(new_depth, width, height)),
+        "simple" => Box::new(SimpleMapBuilder::new(new_depth, width, height)),
+        "bsp_dungeon" => Box::new(BspDungeonBuilder::new(new_depth, width, height)),
+        "bsp_interior" => Box::new(BspInteriorBuilder::new(new_depth, width, height)),
+        "cellular_automata" => Box::new(CellularAutomataBuilder::new(new_depth, width, height)),
+        "drunkard_open_area" => Box::new(DrunkardsWalkBuilder::open_area(new_depth, width, height)),
+        "drunkard_open_halls" => Box::new(DrunkardsWalkBuilder::open_halls(new_depth, width, height)),
+        "drunkard_winding_passages" => Box::new(DrunkardsWalkBuilder::winding_passages(new_depth, width, height)),
+        "drunkard_big_passages" => Box::new(DrunkardsWalkBuilder::big_passages(new_depth, width, height)),
+        "drunkard_fearful_symmetry" => Box::new(DrunkardsWalkBuilder::fearful_symmetry(new_depth, width, height)),
+        "maze" => Box::new(MazeBuilder::new(new_depth, width, height)),
+        "dla_walk_inwards" => Box::new(DLABuilder::walk_inwards(new_depth, width, height)),
+        "dla_walk_outwards" => Box::new(DLABuilder::walk_outwards(new_depth, width, height)),
+        "dla_central_attractor" => Box::new(DLABuilder::central_attractor(new_depth, width, height)),
+        "dla_insectoid" => Box::new(DLABuilder::insectoid(new_depth, width, height)),
+        "voronoi_pythagoras" => Box::new(VoronoiBuilder::pythagoras(new_depth, width, height)),
+        "voronoi_manhattan" => Box::new(VoronoiBuilder::manhattan(new_depth, width, height)),
+        _ => {
+            rltk::log(format!("Unknown builder in theme: {}", name));
+            return None;
+        }
+    };
+
+    Some(result)
+}
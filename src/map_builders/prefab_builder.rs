@@ -1,6 +1,6 @@
-use rltk::XpCell;
+use rltk::{RandomNumberGenerator, XpCell};
 use specs::World;
-use crate::{Map, Position, SHOW_MAPGEN_VISUALIZER, spawner, TileType};
+use crate::{Map, Position, spawner, TileType};
 use crate::map_builders::{build_snapshot, MapBuilder, prefab_levels};
 use crate::map_builders::map_processing::remove_unreachable_areas;
 
@@ -8,7 +8,7 @@ use crate::map_builders::map_processing::remove_unreachable_areas;
 #[allow(dead_code)]
 pub enum PrefabMode {
     RexLevel{ template: &'static str },
-    Constant { level: prefab_levels::PrefablLevel },
+    Constant { level: prefab_levels::PrefabLevel },
 }
 
 pub struct PrefabBuilder {
@@ -21,13 +21,13 @@ pub struct PrefabBuilder {
 }
 
 impl MapBuilder for PrefabBuilder {
-    fn build_map(&mut self) {
+    fn build_map(&mut self, _rng: &mut RandomNumberGenerator) {
         self.build();
     }
 
     fn spawn_entities(&mut self, ecs: &mut World) {
         for (location, entity_name) in self.spawns.iter() {
-            assert!(self.map.tiles[location] == TileType::Floor);
+            assert!(self.map.tiles[*location] == TileType::Floor);
             spawner::spawn_entity(ecs, &(location, entity_name));
         }
     }
@@ -53,9 +53,9 @@ impl MapBuilder for PrefabBuilder {
 }
 
 impl PrefabBuilder {
-    pub fn new(new_depth: i32) -> PrefabBuilder {
+    pub fn new(new_depth: i32, width: i32, height: i32) -> PrefabBuilder {
         PrefabBuilder {
-            map: Map::new(new_depth),
+            map: Map::new(new_depth, width, height),
             starting_position: Position {x: 0, y: 0},
             depth: new_depth,
             history: Vec::new(),
@@ -110,7 +110,7 @@ impl PrefabBuilder {
                 self.map.tiles[i] = TileType::Floor;
                 self.starting_position = Position {
                     x: i as i32 % self.map.width,
-                    y: y as i32 / self.map.width,
+                    y: i as i32 / self.map.width,
                 }
             },
             '>' => self.map.tiles[i] = TileType::StairsDown,
@@ -131,7 +131,7 @@ impl PrefabBuilder {
                 self.spawns.push((i, "Health Potion".to_string()));
             }
             _ => {
-                rltk::console::log(format!("Unknown glyph found while loading map: {}", (cell.ch as u8) as char))
+                rltk::console::log(format!("Unknown glyph found while loading map: {}", cell))
             }
         }
     }
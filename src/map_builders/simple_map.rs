@@ -1,7 +1,7 @@
 use crate::map_builders::room_and_corridor_creation::{
     apply_horizontal_tunnel, apply_room_to_map, apply_vertical_tunnel,
 };
-use crate::{Map, spawner, Position, Rect, TileType, SHOW_MAPGEN_VISUALIZER, impl_map_builder_with_rooms};
+use crate::{Map, spawner, Position, Rect, TileType, impl_map_builder_with_rooms};
 use crate::map_builders::{build_snapshot, MapBuilder};
 use rltk::RandomNumberGenerator;
 use specs::prelude::*;
@@ -15,23 +15,21 @@ pub struct SimpleMapBuilder {
 }
 
 impl SimpleMapBuilder {
-    pub fn new(new_depth: i32) -> SimpleMapBuilder {
+    pub fn new(new_depth: i32, width: i32, height: i32) -> SimpleMapBuilder {
         SimpleMapBuilder {
             depth: new_depth,
-            map: Map::new(new_depth),
+            map: Map::new(new_depth, width, height),
             starting_position: Position { x: 0, y: 0 },
             rooms: Vec::new(),
             history: Vec::new(),
         }
     }
 
-    fn build(&mut self) {
+    fn build(&mut self, rng: &mut RandomNumberGenerator) {
         const MAX_ROOMS: i32 = 30;
         const MIN_SIZE: i32 = 6;
         const MAX_SIZE: i32 = 10;
 
-        let mut rng = RandomNumberGenerator::new();
-
         for _i in 0..MAX_ROOMS {
             let w = rng.range(MIN_SIZE, MAX_SIZE);
             let h = rng.range(MIN_SIZE, MAX_SIZE);
@@ -0,0 +1,234 @@
+use crate::map_builders::{build_snapshot, MapBuilder};
+use crate::settings::{self, LogLevel};
+use crate::{spawner, Map, Position, Rect, TileType};
+use rltk::RandomNumberGenerator;
+use specs::World;
+
+const MAX_BUILDINGS: i32 = 12;
+
+pub struct TownBuilder {
+    map: Map,
+    starting_position: Position,
+    depth: i32,
+    history: Vec<Map>,
+    buildings: Vec<Rect>,
+    doors: Vec<usize>,
+    spawns: Vec<(usize, String)>,
+}
+
+impl MapBuilder for TownBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.build(rng);
+    }
+
+    fn spawn_entities(&mut self, ecs: &mut World) {
+        for door in self.doors.iter() {
+            let x = *door as i32 % self.map.width;
+            let y = *door as i32 / self.map.width;
+            spawner::door(ecs, x, y, false);
+        }
+
+        for (location, entity_name) in self.spawns.iter() {
+            spawner::spawn_entity(ecs, &(location, entity_name));
+        }
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position.clone()
+    }
+
+    fn get_snapshot_history(&self) -> Vec<Map> {
+        self.history.clone()
+    }
+
+    fn take_snapshot(&mut self) {
+        match build_snapshot(&self.map) {
+            None => {}
+            Some(t) => self.history.push(t),
+        };
+    }
+}
+
+impl TownBuilder {
+    pub fn new(new_depth: i32, width: i32, height: i32) -> TownBuilder {
+        TownBuilder {
+            map: Map::new(new_depth, width, height),
+            starting_position: Position::origin(),
+            depth: new_depth,
+            history: Vec::new(),
+            buildings: Vec::new(),
+            doors: Vec::new(),
+            spawns: Vec::new(),
+        }
+    }
+
+    fn build(&mut self, rng: &mut RandomNumberGenerator) {
+
+        self.lay_grass();
+        self.take_snapshot();
+
+        let road_y = self.map.height / 2;
+        self.lay_road(road_y);
+        self.take_snapshot();
+
+        self.add_buildings(rng, road_y);
+
+        self.buildings
+            .sort_by_key(|b| std::cmp::Reverse((b.x2 - b.x1) * (b.y2 - b.y1)));
+        self.populate_buildings(rng);
+
+        let stairs_x = self.map.width - 4;
+        self.map.set_tile(stairs_x, road_y, TileType::StairsDown);
+        self.spawns.push((
+            self.map.xy_idx(stairs_x - 2, road_y - 1),
+            "Guard".to_string(),
+        ));
+        self.spawns.push((
+            self.map.xy_idx(stairs_x - 2, road_y + 1),
+            "Guard".to_string(),
+        ));
+        self.take_snapshot();
+
+        self.starting_position = Position { x: 3, y: road_y };
+        settings::log(LogLevel::Verbose, format!("Built the town at depth {}", self.depth));
+    }
+
+    fn lay_grass(&mut self) {
+        for y in 0..self.map.height {
+            for x in 0..self.map.width {
+                let on_edge =
+                    x == 0 || y == 0 || x == self.map.width - 1 || y == self.map.height - 1;
+                self.map.set_tile(
+                    x,
+                    y,
+                    if on_edge {
+                        TileType::Wall
+                    } else {
+                        TileType::Grass
+                    },
+                );
+            }
+        }
+    }
+
+    fn lay_road(&mut self, road_y: i32) {
+        for x in 1..self.map.width - 1 {
+            for y in road_y - 1..=road_y + 1 {
+                self.map.set_tile(x, y, TileType::Gravel);
+            }
+        }
+    }
+
+    fn add_buildings(&mut self, rng: &mut RandomNumberGenerator, road_y: i32) {
+        let mut attempts = 0;
+        while self.buildings.len() < MAX_BUILDINGS as usize && attempts < 200 {
+            attempts += 1;
+
+            let w = rng.range(7, 14);
+            let h = rng.range(5, 9);
+            let north_of_road = rng.range(0, 2) == 0;
+            let x = rng.range(2, self.map.width - w - 6);
+            let y = if north_of_road {
+                rng.range(2, i32::max(3, road_y - h - 3))
+            } else {
+                rng.range(road_y + 3, i32::max(road_y + 4, self.map.height - h - 2))
+            };
+
+            let building = Rect::new(x, y, w, h);
+            let mut padded = building;
+            padded.x1 -= 1;
+            padded.y1 -= 1;
+            padded.x2 += 1;
+            padded.y2 += 1;
+
+            let fits = padded.x1 > 0
+                && padded.y1 > 0
+                && padded.x2 < self.map.width - 1
+                && padded.y2 < self.map.height - 1
+                && (padded.y2 < road_y - 1 || padded.y1 > road_y + 1)
+                && !self.buildings.iter().any(|b| b.intersect(&padded));
+
+            if fits {
+                self.raise_building(&building, north_of_road);
+                self.buildings.push(building);
+                self.take_snapshot();
+            }
+        }
+    }
+
+    fn raise_building(&mut self, building: &Rect, faces_south: bool) {
+        for y in building.y1..=building.y2 {
+            for x in building.x1..=building.x2 {
+                let is_wall =
+                    x == building.x1 || x == building.x2 || y == building.y1 || y == building.y2;
+                self.map.set_tile(
+                    x,
+                    y,
+                    if is_wall {
+                        TileType::Wall
+                    } else {
+                        TileType::WoodFloor
+                    },
+                );
+            }
+        }
+
+        let (door_x, _) = building.center();
+        let door_y = if faces_south {
+            building.y2
+        } else {
+            building.y1
+        };
+        self.map.set_tile(door_x, door_y, TileType::WoodFloor);
+        self.doors.push(self.map.xy_idx(door_x, door_y));
+
+        let path_y = if faces_south { door_y + 1 } else { door_y - 1 };
+        let road_y = self.map.height / 2;
+        let (from, to) = if path_y < road_y {
+            (path_y, road_y)
+        } else {
+            (road_y, path_y)
+        };
+        for y in from..=to {
+            if self.map.get_tile(door_x, y) == TileType::Grass {
+                self.map.set_tile(door_x, y, TileType::Gravel);
+            }
+        }
+    }
+
+    fn populate_buildings(&mut self, rng: &mut RandomNumberGenerator) {
+        let buildings = self.buildings.clone();
+        for (i, building) in buildings.iter().enumerate() {
+            match i {
+                0 => {
+                    self.spawn_inside(building, "Barkeep", rng);
+                    for _patron in 0..rng.range(2, 5) {
+                        self.spawn_inside(building, "Townsperson", rng);
+                    }
+                }
+                1 | 2 => self.spawn_inside(building, "Merchant", rng),
+                _ => {
+                    if rng.range(0, 2) == 0 {
+                        self.spawn_inside(building, "Townsperson", rng);
+                    }
+                }
+            }
+        }
+    }
+
+    fn spawn_inside(&mut self, building: &Rect, name: &str, rng: &mut RandomNumberGenerator) {
+        for _attempt in 0..10 {
+            let x = rng.range(building.x1 + 1, building.x2);
+            let y = rng.range(building.y1 + 1, building.y2);
+            let idx = self.map.xy_idx(x, y);
+            if !self.spawns.iter().any(|(location, _)| *location == idx) {
+                self.spawns.push((idx, name.to_string()));
+                return;
+            }
+        }
+    }
+}
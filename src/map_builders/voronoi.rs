@@ -1,5 +1,5 @@
-use crate::{Position, Map, SHOW_MAPGEN_VISUALIZER, spawner, TileType, impl_map_builder_with_noise_areas};
-use std::collections::HashMap;
+use crate::{Position, Map, spawner, TileType, impl_map_builder_with_noise_areas};
+use std::collections::BTreeMap;
 use crate::map_builders::{build_snapshot, MapBuilder};
 use specs::World;
 use rltk::RandomNumberGenerator;
@@ -13,7 +13,7 @@ pub struct VoronoiBuilder {
     starting_position: Position,
     depth: i32,
     history: Vec<Map>,
-    noise_areas: HashMap<i32, Vec<usize>>,
+    noise_areas: BTreeMap<i32, Vec<usize>>,
     number_of_seeds: usize,
     distance_algorithm: DistanceAlgorithm
 }
@@ -21,33 +21,32 @@ pub struct VoronoiBuilder {
 impl_map_builder_with_noise_areas!(VoronoiBuilder);
 
 impl VoronoiBuilder {
-    fn new(new_depth: i32, number_of_seeds: usize, distance_algorithm: DistanceAlgorithm) -> VoronoiBuilder {
+    fn new(new_depth: i32, width: i32, height: i32, number_of_seeds: usize, distance_algorithm: DistanceAlgorithm) -> VoronoiBuilder {
         VoronoiBuilder {
-            map: Map::new(new_depth),
+            map: Map::new(new_depth, width, height),
             starting_position: Position::origin(),
             history: Vec::new(),
-            noise_areas: HashMap::new(),
+            noise_areas: BTreeMap::new(),
             number_of_seeds,
             depth: new_depth,
             distance_algorithm,
         }
     }
 
-    pub fn pythagoras(new_depth: i32) -> VoronoiBuilder {
-        VoronoiBuilder::new(new_depth, 64, DistanceAlgorithm::Pythagoras)
+    pub fn pythagoras(new_depth: i32, width: i32, height: i32) -> VoronoiBuilder {
+        VoronoiBuilder::new(new_depth, width, height, 64, DistanceAlgorithm::Pythagoras)
     }
 
-    pub fn manhattan(new_depth: i32) -> VoronoiBuilder {
-        VoronoiBuilder::new(new_depth, 64, DistanceAlgorithm::Manhattan)
+    pub fn manhattan(new_depth: i32, width: i32, height: i32) -> VoronoiBuilder {
+        VoronoiBuilder::new(new_depth, width, height, 64, DistanceAlgorithm::Manhattan)
     }
 
     #[allow(dead_code)]
-    pub fn chebyshev(new_depth: i32) -> VoronoiBuilder {
-        VoronoiBuilder::new(new_depth, 64, DistanceAlgorithm::Chebyshev)
+    pub fn chebyshev(new_depth: i32, width: i32, height: i32) -> VoronoiBuilder {
+        VoronoiBuilder::new(new_depth, width, height, 64, DistanceAlgorithm::Chebyshev)
     }
 
-    fn build(&mut self) {
-        let mut rng = RandomNumberGenerator::new();
+    fn build(&mut self, rng: &mut RandomNumberGenerator) {
 
         let mut voronoi_seeds: Vec<(usize, rltk::Point)> = Vec::new();
 
@@ -120,7 +119,7 @@ impl VoronoiBuilder {
         self.map.tiles[exit_tile] = TileType::StairsDown;
         self.take_snapshot();
 
-        self.noise_areas = generate_voronoi_spawn_regions(&self.map, &mut rng);
+        self.noise_areas = generate_voronoi_spawn_regions(&self.map, rng);
         self.take_snapshot();
     }
 }
\ No newline at end of file
@@ -1,4 +1,5 @@
 use std::collections::HashSet;
+use crate::settings::{self, LogLevel};
 use crate::{TileType, Map};
 use crate::map_builders::waveform_collapse::map_chunk::{MapChunk, tile_index_in_chunk};
 
@@ -48,10 +49,10 @@ pub fn build_patterns(map: &Map, chunk_size: i32, include_flipping: bool, dedupe
     }
 
     if dedupe {
-        rltk::console::log(format!("There are {} patterns before dedupe", patterns.len()));
-        let unique_patterns: HashSet<Vec<TileType>> = patterns.drain(..).collect();
-        patterns.extend(unique_patterns.into_iter());
-        rltk::console::log(format!("There are {} patterns after dedupe", patterns.len()));
+        settings::log(LogLevel::Verbose, format!("There are {} patterns before dedupe", patterns.len()));
+        let mut seen: HashSet<Vec<TileType>> = HashSet::new();
+        patterns.retain(|pattern| seen.insert(pattern.clone()));
+        settings::log(LogLevel::Verbose, format!("There are {} patterns after dedupe", patterns.len()));
     }
 
     patterns
@@ -2,7 +2,12 @@ use rltk::rex::XpFile;
 use crate::{Map};
 
 pub fn load_rex_map(new_depth: i32, xp_file: &XpFile) -> Map {
-    let mut map: Map = Map::new(new_depth);
+    let (width, height) = xp_file
+        .layers
+        .first()
+        .map(|layer| (layer.width as i32, layer.height as i32))
+        .unwrap_or((1, 1));
+    let mut map: Map = Map::new(new_depth, width, height);
 
     for layer in &xp_file.layers {
         for y in 0..layer.height {
@@ -2,11 +2,11 @@ mod constraints;
 mod map_chunk;
 mod solver;
 
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use specs::World;
 use rltk::{RandomNumberGenerator};
 
-use crate::{Map, Position, SHOW_MAPGEN_VISUALIZER, TileType, spawner, impl_map_builder_with_noise_areas};
+use crate::{Map, Position, TileType, spawner, impl_map_builder_with_noise_areas};
 use crate::map_builders::{build_snapshot, MapBuilder};
 use crate::map_builders::map_processing::{remove_unreachable_areas, generate_voronoi_spawn_regions};
 use crate::map_builders::waveform_collapse::constraints::{build_patterns, patterns_to_constraints, render_pattern_to_map};
@@ -18,49 +18,49 @@ pub struct WaveformCollapseBuilder {
     starting_position: Position,
     depth: i32,
     history: Vec<Map>,
-    noise_areas: HashMap<i32, Vec<usize>>,
+    noise_areas: BTreeMap<i32, Vec<usize>>,
     derive_from: Option<Box<dyn MapBuilder>>,
 }
 
 impl_map_builder_with_noise_areas!(WaveformCollapseBuilder);
 
 impl WaveformCollapseBuilder {
-    pub fn derived_map(new_depth: i32, builder: Box<dyn MapBuilder>) -> WaveformCollapseBuilder {
-        WaveformCollapseBuilder::new(new_depth, Some(builder))
+    pub fn derived_map(new_depth: i32, width: i32, height: i32, builder: Box<dyn MapBuilder>) -> WaveformCollapseBuilder {
+        WaveformCollapseBuilder::new(new_depth, width, height, Some(builder))
     }
 
-    pub fn new(new_depth: i32, derive_from: Option<Box<dyn MapBuilder>>) -> WaveformCollapseBuilder {
+    pub fn new(new_depth: i32, width: i32, height: i32, derive_from: Option<Box<dyn MapBuilder>>) -> WaveformCollapseBuilder {
         WaveformCollapseBuilder {
-            map: Map::new(new_depth),
+            map: Map::new(new_depth, width, height),
             starting_position: Position::origin(),
             depth: new_depth,
             history: Vec::new(),
-            noise_areas: HashMap::new(),
+            noise_areas: BTreeMap::new(),
             derive_from
         }
     }
 
-    fn build(&mut self) {
-        let mut rng = RandomNumberGenerator::new();
+    fn build(&mut self, rng: &mut RandomNumberGenerator) {
 
         const CHUNK_SIZE: i32 = 8;
 
         let derived_map = &mut self.derive_from.as_mut().unwrap();
-        derived_map.build_map();
+        derived_map.build_map(rng);
         self.map = derived_map.get_map();
         for t in self.map.tiles.iter_mut() {
             if *t == TileType::StairsDown { *t = TileType::Floor }
         }
         self.take_snapshot();
 
+        let (width, height) = (self.map.width, self.map.height);
         let patterns = build_patterns(&self.map, CHUNK_SIZE, true, true);
         let constraints = patterns_to_constraints(patterns, CHUNK_SIZE);
         self.render_tile_gallery(&constraints, CHUNK_SIZE);
 
-        self.map = Map::new(self.depth);
+        self.map = Map::new(self.depth, width, height);
         loop {
             let mut solver = Solver::new(constraints.clone(), CHUNK_SIZE, &self.map);
-            while !solver.iteration(&mut self.map, &mut rng) {
+            while !solver.iteration(&mut self.map, rng) {
                 self.take_snapshot();
             }
 
@@ -80,11 +80,12 @@ impl WaveformCollapseBuilder {
         self.map.tiles[exit_tile] = TileType::StairsDown;
         self.take_snapshot();
 
-        self.noise_areas = generate_voronoi_spawn_regions(&self.map, &mut rng);
+        self.noise_areas = generate_voronoi_spawn_regions(&self.map, rng);
     }
 
     fn render_tile_gallery(&mut self, constraints: &Vec<MapChunk>, chunk_size: i32) {
-        self.map = Map::new(0);
+        let (width, height) = (self.map.width, self.map.height);
+        self.map = Map::new(0, width, height);
         let mut counter = 0;
         let mut x = 1;
         let mut y = 1;
@@ -99,7 +100,7 @@ impl WaveformCollapseBuilder {
 
                 if y + chunk_size > self.map.height {
                     self.take_snapshot();
-                    self.map = Map::new(0);
+                    self.map = Map::new(0, width, height);
 
                     x = 1;
                     y = 1;
@@ -117,7 +117,7 @@ impl Solver {
             }
 
             if possible_options.is_empty() {
-                rltk::console::log("This chunk is not possible!");
+                crate::settings::log(crate::settings::LogLevel::Verbose, "This chunk is not possible!");
                 self.possible = false;
                 return true;
             } else {
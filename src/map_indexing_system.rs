@@ -1,4 +1,4 @@
-use super::{BlocksTile, Map, Position};
+use super::{BlocksTile, BlocksVisibility, Map, Position};
 use specs::prelude::*;
 
 pub struct MapIndexingSystem {}
@@ -9,14 +9,16 @@ impl<'a> System<'a> for MapIndexingSystem {
         WriteExpect<'a, Map>,
         ReadStorage<'a, Position>,
         ReadStorage<'a, BlocksTile>,
+        ReadStorage<'a, BlocksVisibility>,
         Entities<'a>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut map, position, blockers, entities) = data;
+        let (mut map, position, blockers, view_blockers, entities) = data;
 
         map.populate_blocked();
         map.clear_content_index();
+        map.view_blocked.clear();
 
         for (entity, position) in (&entities, &position).join() {
             let idx = map.xy_idx(position.x, position.y);
@@ -26,6 +28,10 @@ impl<'a> System<'a> for MapIndexingSystem {
                 map.blocked[idx] = true;
             }
 
+            if view_blockers.get(entity).is_some() {
+                map.view_blocked.insert(idx);
+            }
+
             map.tile_content[idx].push(entity);
         }
     }
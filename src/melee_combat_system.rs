@@ -1,4 +1,4 @@
-use super::{gamelog::GameLog, CombatStats, Name, SufferDamage, WantsToMelee};
+use super::{gamelog::GameLog, gamelog::LogCategory, CombatStats, Name, SufferDamage, WantsToMelee};
 use crate::{DefenseBonus, Equipped, MeleePowerBonus, ParticleBuilder, Position};
 use rltk::RGB;
 use specs::prelude::*;
@@ -46,6 +46,7 @@ impl<'a> System<'a> for MeleeCombatSystem {
     #[allow(clippy::type_complexity)]
     type SystemData = (
         Entities<'a>,
+        ReadExpect<'a, Entity>,
         WriteStorage<'a, WantsToMelee>,
         ReadStorage<'a, Name>,
         ReadStorage<'a, CombatStats>,
@@ -61,6 +62,7 @@ impl<'a> System<'a> for MeleeCombatSystem {
     fn run(&mut self, data: Self::SystemData) {
         let (
             entities,
+            player_entity,
             mut wants_melee,
             names,
             combat_stats,
@@ -109,16 +111,27 @@ impl<'a> System<'a> for MeleeCombatSystem {
                     );
 
                     if damage == 0 {
-                        log.entries.push(format!(
-                            "{} is unable to hurt {}",
-                            &name.name, &target_name.name
-                        ));
+                        log.entry(LogCategory::Combat)
+                            .name(&name.name)
+                            .text(" is unable to hurt ")
+                            .name(&target_name.name)
+                            .log();
                     } else {
-                        log.entries.push(format!(
-                            "{} hits {} for {} damage",
-                            &name.name, target_name.name, damage
-                        ));
-                        SufferDamage::new_damage(&mut inflict_data, wants_melee.target, damage);
+                        log.entry(LogCategory::Combat)
+                            .name(&name.name)
+                            .text(" hits ")
+                            .name(&target_name.name)
+                            .text(" for ")
+                            .damage(damage)
+                            .text(" damage")
+                            .log();
+                        SufferDamage::new_damage(
+                            &mut inflict_data,
+                            wants_melee.target,
+                            damage,
+                            &name.name,
+                            entity == *player_entity,
+                        );
                     }
                 }
             }
@@ -0,0 +1,201 @@
+use crate::keybindings::{Action, KeyBindings};
+use crate::map::tile_glyph;
+use crate::settings;
+use crate::{Hidden, Item, Map, Position, Renderable, TileType};
+use rltk::{Point, Rltk, VirtualKeyCode, RGB};
+use specs::prelude::*;
+
+/// Size of the HUD minimap, including its border.
+const CORNER_WIDTH: i32 = 22;
+const CORNER_HEIGHT: i32 = 12;
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum OverviewResult {
+    NoSelection,
+    Close,
+}
+
+/// A screen rectangle the level is squeezed into. Each cell stands for a `scale_x` by `scale_y`
+/// block of tiles.
+struct Layout {
+    x: i32,
+    y: i32,
+    scale_x: i32,
+    scale_y: i32,
+}
+
+impl Layout {
+    /// Fits the whole map into `width` by `height` cells, centred when it needs less room.
+    fn fit(map: &Map, x: i32, y: i32, width: i32, height: i32) -> Layout {
+        let scale_x = (map.width + width - 1) / width;
+        let scale_y = (map.height + height - 1) / height;
+        let used_width = (map.width + scale_x - 1) / scale_x;
+        let used_height = (map.height + scale_y - 1) / scale_y;
+        Layout {
+            x: x + (width - used_width) / 2,
+            y: y + (height - used_height) / 2,
+            scale_x,
+            scale_y,
+        }
+    }
+
+    fn cells(&self, map: &Map) -> (i32, i32) {
+        (
+            (map.width + self.scale_x - 1) / self.scale_x,
+            (map.height + self.scale_y - 1) / self.scale_y,
+        )
+    }
+
+    fn to_screen(&self, world: Point) -> Point {
+        Point::new(
+            self.x + world.x / self.scale_x,
+            self.y + world.y / self.scale_y,
+        )
+    }
+}
+
+/// The always-on minimap in the top right corner of the map view.
+pub fn draw_corner_minimap(ecs: &World, ctx: &mut Rltk) {
+    let x = crate::camera::VIEW_WIDTH - CORNER_WIDTH;
+    ctx.draw_box(
+        x,
+        0,
+        CORNER_WIDTH - 1,
+        CORNER_HEIGHT - 1,
+        RGB::named(rltk::GRAY),
+        RGB::named(rltk::BLACK),
+    );
+
+    let map = ecs.fetch::<Map>();
+    let layout = Layout::fit(&map, x + 1, 1, CORNER_WIDTH - 2, CORNER_HEIGHT - 2);
+    draw_level(ecs, &map, &layout, ctx);
+}
+
+/// The whole level on one screen, for finding the way around maps bigger than the view.
+pub fn show_overview(ecs: &World, ctx: &mut Rltk, keybindings: &KeyBindings) -> OverviewResult {
+    let bg = RGB::named(rltk::BLACK);
+    let map = ecs.fetch::<Map>();
+
+    ctx.draw_box(0, 0, 79, 49, RGB::named(rltk::WHITE), bg);
+    let title = if map.depth == 0 {
+        "Overview: the town".to_string()
+    } else {
+        format!("Overview: depth {}", map.depth)
+    };
+    ctx.print_color_centered(0, RGB::named(rltk::YELLOW), bg, &title);
+
+    let layout = Layout::fit(&map, 1, 1, 78, 47);
+    draw_level(ecs, &map, &layout, ctx);
+
+    if layout.scale_x > 1 || layout.scale_y > 1 {
+        let scale = format!(" 1:{}x{} ", layout.scale_x, layout.scale_y);
+        ctx.print_color(2, 49, RGB::named(rltk::GRAY), bg, &scale);
+    }
+    ctx.print_color_centered(49, RGB::named(rltk::YELLOW), bg, " Escape to close ");
+
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => OverviewResult::Close,
+        Some(key) if keybindings.action_for(key) == Some(Action::Overview) => OverviewResult::Close,
+        _ => OverviewResult::NoSelection,
+    }
+}
+
+/// Draws what the player has seen of the level, then the stairs, known items and the player on
+/// top so they stay visible however far the map is shrunk.
+fn draw_level(ecs: &World, map: &Map, layout: &Layout, ctx: &mut Rltk) {
+    let palette = settings::palette();
+    let black = RGB::named(rltk::BLACK);
+    let (cells_x, cells_y) = layout.cells(map);
+
+    for cell_y in 0..cells_y {
+        for cell_x in 0..cells_x {
+            if let Some((glyph, fg)) = cell_glyph(map, layout, cell_x, cell_y) {
+                ctx.set(
+                    layout.x + cell_x,
+                    layout.y + cell_y,
+                    palette.adjust(fg),
+                    black,
+                    glyph,
+                );
+            }
+        }
+    }
+
+    let positions = ecs.read_storage::<Position>();
+    let items = ecs.read_storage::<Item>();
+    let renderables = ecs.read_storage::<Renderable>();
+    let hidden = ecs.read_storage::<Hidden>();
+    for (pos, _item, render, _hidden) in (&positions, &items, &renderables, !&hidden).join() {
+        if map.revealed_tiles[map.xy_idx(pos.x, pos.y)] {
+            let screen = layout.to_screen(Point::new(pos.x, pos.y));
+            ctx.set(
+                screen.x,
+                screen.y,
+                palette.adjust(render.fg),
+                black,
+                render.glyph,
+            );
+        }
+    }
+
+    for (idx, tile) in map.tiles.iter().enumerate() {
+        let glyph = match tile {
+            TileType::StairsDown => '>',
+            TileType::StairsUp => '<',
+            _ => continue,
+        };
+        if map.revealed_tiles[idx] {
+            let world = Point::new(idx as i32 % map.width, idx as i32 / map.width);
+            let screen = layout.to_screen(world);
+            ctx.set(
+                screen.x,
+                screen.y,
+                palette.adjust(RGB::named(rltk::CYAN)),
+                black,
+                rltk::to_cp437(glyph),
+            );
+        }
+    }
+
+    let player = layout.to_screen(*ecs.fetch::<Point>());
+    ctx.set(
+        player.x,
+        player.y,
+        palette.adjust(RGB::named(rltk::YELLOW)),
+        black,
+        rltk::to_cp437('@'),
+    );
+}
+
+/// One cell of the shrunk map. At full size this is just the tile; otherwise anything but a wall
+/// that the player has seen in the block wins, so corridors don't vanish.
+fn cell_glyph(
+    map: &Map,
+    layout: &Layout,
+    cell_x: i32,
+    cell_y: i32,
+) -> Option<(rltk::FontCharType, RGB)> {
+    let mut wall = None;
+    for y in cell_y * layout.scale_y..i32::min((cell_y + 1) * layout.scale_y, map.height) {
+        for x in cell_x * layout.scale_x..i32::min((cell_x + 1) * layout.scale_x, map.width) {
+            let idx = map.xy_idx(x, y);
+            if !map.revealed_tiles[idx] {
+                continue;
+            }
+            if map.tiles[idx] != TileType::Wall {
+                let (glyph, fg, _bg) = tile_glyph(idx, map);
+                return Some((glyph, fg));
+            }
+            wall = wall.or(Some(idx));
+        }
+    }
+
+    wall.map(|idx| {
+        let (glyph, fg, _bg) = tile_glyph(idx, map);
+        if layout.scale_x > 1 || layout.scale_y > 1 {
+            (rltk::to_cp437('#'), fg)
+        } else {
+            (glyph, fg)
+        }
+    })
+}
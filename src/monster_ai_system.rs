@@ -1,5 +1,8 @@
 use super::{Map, Monster, Position, RunState, Viewshed};
-use crate::{Confusion, EntityMoved, ParticleBuilder, WantsToMelee};
+use crate::{
+    CannotOpenDoors, Confusion, Door, EntityMoved, InBackpack, Key, Locked, ParticleBuilder,
+    WantsToMelee, WantsToOpenDoor,
+};
 use rltk::{Point, RGB};
 use specs::prelude::*;
 
@@ -20,6 +23,12 @@ impl<'a> System<'a> for MonsterAI {
         WriteStorage<'a, Confusion>,
         WriteExpect<'a, ParticleBuilder>,
         WriteStorage<'a, EntityMoved>,
+        ReadStorage<'a, Door>,
+        ReadStorage<'a, CannotOpenDoors>,
+        WriteStorage<'a, WantsToOpenDoor>,
+        ReadStorage<'a, Locked>,
+        ReadStorage<'a, Key>,
+        ReadStorage<'a, InBackpack>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -36,12 +45,24 @@ impl<'a> System<'a> for MonsterAI {
             mut confused,
             mut particle_builder,
             mut entity_moved,
+            doors,
+            cannot_open_doors,
+            mut wants_to_open_door,
+            locked,
+            keys,
+            backpack,
         ) = data;
 
         if *run_state != RunState::MonsterTurn {
             return;
         }
 
+        let closed_doors: Vec<(Entity, usize, bool)> = (&entities, &doors, &position)
+            .join()
+            .filter(|(_e, door, _pos)| !door.open)
+            .map(|(e, _door, pos)| (e, map.xy_idx(pos.x, pos.y), locked.get(e).is_some()))
+            .collect();
+
         for (entity, mut viewshed, _monster, mut pos) in
             (&entities, &mut viewshed, &monster, &mut position).join()
         {
@@ -75,12 +96,42 @@ impl<'a> System<'a> for MonsterAI {
                     )
                     .expect("Unable to attack");
             } else if viewshed.visible_tiles.contains(&*player_pos) {
+                // Locked doors stay in the way of monsters that have no key to open them with.
+                let has_key = (&keys, &backpack)
+                    .join()
+                    .any(|(_key, pack)| pack.owner == entity);
+                let openable_doors: Vec<&(Entity, usize, bool)> =
+                    if cannot_open_doors.get(entity).is_none() {
+                        closed_doors
+                            .iter()
+                            .filter(|(_door, _idx, is_locked)| !is_locked || has_key)
+                            .collect()
+                    } else {
+                        Vec::new()
+                    };
+                for (_door, idx, _locked) in openable_doors.iter() {
+                    map.blocked[*idx] = false;
+                }
+
                 let path = rltk::a_star_search(
                     map.xy_idx(pos.x, pos.y),
                     map.xy_idx(player_pos.x, player_pos.y),
                     &*map,
                 );
-                if path.success && path.steps.len() > 1 {
+
+                for (_door, idx, _locked) in openable_doors.iter() {
+                    map.blocked[*idx] = true;
+                }
+
+                let door_in_the_way = openable_doors
+                    .iter()
+                    .find(|(_door, idx, _locked)| path.steps.len() > 1 && *idx == path.steps[1]);
+
+                if let Some((door, _idx, _locked)) = door_in_the_way {
+                    wants_to_open_door
+                        .insert(entity, WantsToOpenDoor { door: *door })
+                        .expect("Unable to open door");
+                } else if path.success && path.steps.len() > 1 {
                     let idx = map.xy_idx(pos.x, pos.y);
                     map.blocked[idx] = false;
                     pos.x = path.steps[1] as i32 % map.width;
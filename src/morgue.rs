@@ -0,0 +1,205 @@
+use crate::hall_of_fame;
+use crate::hardcore::RunInfo;
+use crate::map::tile_glyph;
+use crate::{
+    save_dir, CombatStats, Equipped, GameLog, Hidden, InBackpack, Map, Name, Position,
+    Renderable, ReplayRecorder, RunStats,
+};
+use specs::prelude::*;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MORGUE_LOG_ENTRIES: usize = 20;
+
+pub struct RunSummary {
+    pub depth: i32,
+    pub deepest_depth: i32,
+    pub turns: i32,
+    pub total_kills: i32,
+    pub cause_of_death: String,
+}
+
+pub fn summarize_run(ecs: &World) -> RunSummary {
+    let player_entity = ecs.fetch::<Entity>();
+    let map = ecs.fetch::<Map>();
+    let run_stats = ecs.read_storage::<RunStats>();
+    let stats = run_stats.get(*player_entity).cloned().unwrap_or_default();
+
+    RunSummary {
+        depth: map.depth,
+        deepest_depth: i32::max(stats.deepest_depth, map.depth),
+        turns: stats.turns,
+        total_kills: stats.kills.values().sum(),
+        cause_of_death: stats
+            .cause_of_death
+            .unwrap_or_else(|| "abandoned the run".to_string()),
+    }
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Formats seconds since the Unix epoch as "YYYY-MM-DD HH:MM UTC".
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let seconds_of_day = secs % 86_400;
+
+    // Civil-from-days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        (seconds_of_day % 3600) / 60
+    )
+}
+
+pub fn write_morgue(ecs: &World) -> Option<String> {
+    let timestamp = unix_time();
+    let path = save_dir().join(format!("morgue-{}.txt", timestamp));
+    let report = build_report(ecs, timestamp);
+
+    match fs::create_dir_all(save_dir()).and_then(|_| fs::write(&path, report)) {
+        Ok(_) => Some(path.display().to_string()),
+        Err(e) => {
+            rltk::console::log(format!("Unable to write morgue file: {}", e));
+            None
+        }
+    }
+}
+
+fn build_report(ecs: &World, timestamp: u64) -> String {
+    let player_entity = *ecs.fetch::<Entity>();
+    let summary = summarize_run(ecs);
+    let run_stats = ecs.read_storage::<RunStats>();
+    let stats = run_stats.get(player_entity).cloned().unwrap_or_default();
+    let names = ecs.read_storage::<Name>();
+
+    let mut lines: Vec<String> = Vec::new();
+    lines.push(format!("Deathlike morgue file - {}", format_timestamp(timestamp)));
+    lines.push(format!("Seed: {}", ecs.fetch::<ReplayRecorder>().seed));
+    lines.push(String::new());
+
+    lines.push("== Character ==".to_string());
+    if let Some(combat) = ecs.read_storage::<CombatStats>().get(player_entity) {
+        lines.push(format!("HP: {}/{}", combat.hp, combat.max_hp));
+        lines.push(format!("Attack power: {}", combat.attack_power));
+        lines.push(format!("Block: {}", combat.block));
+    }
+    lines.push(format!("Final depth: {} (deepest reached: {})", summary.depth, summary.deepest_depth));
+    lines.push(format!("Turns taken: {}", summary.turns));
+    lines.push(format!("Cause of death: {}", summary.cause_of_death));
+    lines.push(String::new());
+
+    lines.push("== Equipment ==".to_string());
+    for (name, equipped) in (&names, &ecs.read_storage::<Equipped>()).join() {
+        if equipped.owner == player_entity {
+            lines.push(format!("{:?}: {}", equipped.slot, name.name));
+        }
+    }
+    lines.push(String::new());
+
+    lines.push("== Inventory ==".to_string());
+    for (name, pack) in (&names, &ecs.read_storage::<InBackpack>()).join() {
+        if pack.owner == player_entity {
+            lines.push(name.name.clone());
+        }
+    }
+    lines.push(String::new());
+
+    lines.push(format!("== Kills ({}) ==", summary.total_kills));
+    for (monster, count) in stats.kills.iter() {
+        lines.push(format!("{:>4} {}", count, monster));
+    }
+    lines.push(String::new());
+
+    lines.push("== Last messages ==".to_string());
+    let log = ecs.fetch::<GameLog>();
+    let skip = log.entries().len().saturating_sub(MORGUE_LOG_ENTRIES);
+    for entry in log.entries().skip(skip) {
+        lines.push(format!("{:>6} {}", entry.turn, entry.text()));
+    }
+    lines.push(String::new());
+
+    lines.push("== Final map ==".to_string());
+    lines.push(dump_map(ecs));
+
+    lines.join("\n")
+}
+
+fn dump_map(ecs: &World) -> String {
+    let map = ecs.fetch::<Map>();
+    let mut cells: Vec<char> = map
+        .tiles
+        .iter()
+        .enumerate()
+        .map(|(idx, _tile)| {
+            if map.revealed_tiles[idx] {
+                let (glyph, _fg, _bg) = tile_glyph(idx, &map);
+                rltk::to_char(glyph as u8)
+            } else {
+                ' '
+            }
+        })
+        .collect();
+
+    let positions = ecs.read_storage::<Position>();
+    let renderables = ecs.read_storage::<Renderable>();
+    let hidden = ecs.read_storage::<Hidden>();
+    let mut visible_entities = (&positions, &renderables, !&hidden)
+        .join()
+        .filter(|(pos, _render, _hidden)| map.visible_tiles[map.xy_idx(pos.x, pos.y)])
+        .collect::<Vec<_>>();
+    visible_entities.sort_by_key(|&(_pos, render, _hidden)| std::cmp::Reverse(render.render_order));
+    for (pos, render, _hidden) in visible_entities {
+        let idx = map.xy_idx(pos.x, pos.y);
+        cells[idx] = rltk::to_char(render.glyph as u8);
+    }
+
+    let mut dump = String::new();
+    for row in cells.chunks(map.width as usize) {
+        let line: String = row.iter().collect();
+        dump.push_str(line.trim_end());
+        dump.push('\n');
+    }
+    dump
+}
+
+pub struct RunReport {
+    pub summary: RunSummary,
+    pub morgue_path: Option<String>,
+}
+
+pub fn finish_run(ecs: &World, write_file: bool) -> RunReport {
+    let summary = summarize_run(ecs);
+    let mut morgue_path = None;
+    if write_file {
+        morgue_path = write_morgue(ecs);
+        hall_of_fame::record_run(
+            &summary,
+            &ecs.fetch::<RunInfo>(),
+            ecs.fetch::<ReplayRecorder>().seed,
+            unix_time(),
+        );
+    }
+
+    RunReport {
+        summary,
+        morgue_path,
+    }
+}
@@ -2,28 +2,32 @@ use crate::{ParticleLifetime, Position, Renderable, Rltk};
 use rltk::RGB;
 use specs::prelude::*;
 
-pub fn remove_dead_particles(ecs: &mut World, ctx: &Rltk) {
-    let dead_particles = find_dead_particles(ecs, ctx);
-
-    for d in dead_particles.iter() {
-        ecs.delete_entity(*d)
-            .expect("particle could not be deleted");
+/// Hides particles whose lifetime has run out. The entities themselves are only deleted by
+/// `clear_particles` at the start of the player's turn, so that entity allocation does not
+/// depend on frame timing and replays stay deterministic.
+pub fn fade_particles(ecs: &mut World, ctx: &Rltk) {
+    let mut particles = ecs.write_storage::<ParticleLifetime>();
+    let mut renderables = ecs.write_storage::<Renderable>();
+    let entities = ecs.entities();
+    for (entity, particle) in (&entities, &mut particles).join() {
+        particle.lifetime_ms -= ctx.frame_time_ms;
+        if particle.lifetime_ms < 0. {
+            renderables.remove(entity);
+        }
     }
 }
 
-fn find_dead_particles(ecs: &mut World, ctx: &Rltk) -> Vec<Entity> {
-    let mut dead_particles: Vec<Entity> = Vec::new();
-    {
-        let mut particles = ecs.write_storage::<ParticleLifetime>();
+pub fn clear_particles(ecs: &mut World) {
+    let particles: Vec<Entity> = {
+        let lifetimes = ecs.read_storage::<ParticleLifetime>();
         let entities = ecs.entities();
-        for (entity, mut particle) in (&entities, &mut particles).join() {
-            particle.lifetime_ms -= ctx.frame_time_ms;
-            if particle.lifetime_ms < 0. {
-                dead_particles.push(entity);
-            }
-        }
+        (&entities, &lifetimes).join().map(|(entity, _)| entity).collect()
+    };
+
+    for particle in particles {
+        ecs.delete_entity(particle)
+            .expect("particle could not be deleted");
     }
-    dead_particles
 }
 
 struct ParticleRequest {
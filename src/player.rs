@@ -1,9 +1,14 @@
 use super::{
-    CombatStats, GameLog, Item, Map, Player, Position, RunState, State, Viewshed, WantsToMelee,
-    MAP_HEIGHT, MAP_WIDTH,
+    CombatStats, GameLog, Item, LogCategory, Map, Player, Position, RunState, State, Viewshed, WantsToMelee,
 };
-use crate::{EntityMoved, Monster, TileType, WantsToPickupItem};
-use rltk::{Point, Rltk, VirtualKeyCode};
+use crate::{
+    Door, EntityMoved, Monster, PlayerCommand, ReplayRecorder, TileType, WantsToCloseDoor,
+    WantsToDropItem, WantsToOpenDoor, WantsToPickupItem, WantsToRemoveItem, WantsToUseItem,
+};
+use crate::camera;
+use crate::keybindings::Action;
+use crate::travel::{Travel, TravelGoal, TravelStop};
+use rltk::{Point, Rltk};
 use specs::prelude::*;
 use std::cmp::{max, min};
 
@@ -16,6 +21,8 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
     let mut wants_to_melee = ecs.write_storage::<WantsToMelee>();
     let map = ecs.fetch::<Map>();
     let mut entity_moved = ecs.write_storage::<EntityMoved>();
+    let doors = ecs.read_storage::<Door>();
+    let mut wants_to_open_door = ecs.write_storage::<WantsToOpenDoor>();
 
     for (entity, _player, pos, viewshed) in
         (&entities, &players, &mut positions, &mut viewsheds).join()
@@ -35,11 +42,24 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
                     )
                     .expect("Add target failed");
             }
+
+            if let Some(door) = doors.get(*potential_target) {
+                if !door.open {
+                    wants_to_open_door
+                        .insert(
+                            entity,
+                            WantsToOpenDoor {
+                                door: *potential_target,
+                            },
+                        )
+                        .expect("Open door failed");
+                }
+            }
         }
 
         if !map.blocked[destination_idx] {
-            pos.x = min(MAP_WIDTH as i32 - 1, max(0, pos.x + delta_x));
-            pos.y = min(MAP_HEIGHT as i32 - 1, max(0, pos.y + delta_y));
+            pos.x = min(map.width - 1, max(0, pos.x + delta_x));
+            pos.y = min(map.height - 1, max(0, pos.y + delta_y));
 
             let mut player_position = ecs.write_resource::<Point>();
             player_position.x = pos.x;
@@ -55,40 +75,177 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
 }
 
 pub fn player_input(gs: &mut State, ctx: &mut Rltk) -> RunState {
-    match ctx.key {
+    if ctx.left_click {
+        if let Some(target) = camera::mouse_world_position(&gs.ecs, ctx) {
+            start_travel(gs, TravelGoal::Point(target));
+        }
+        return RunState::AwaitingInput;
+    }
+
+    let action = match ctx.key.and_then(|key| gs.keybindings.action_for(key)) {
         None => return RunState::AwaitingInput,
-        Some(key) => match key {
-            VirtualKeyCode::Left | VirtualKeyCode::Numpad4 | VirtualKeyCode::H => {
-                try_move_player(-1, 0, &mut gs.ecs)
-            }
-            VirtualKeyCode::Right | VirtualKeyCode::Numpad6 | VirtualKeyCode::L => {
-                try_move_player(1, 0, &mut gs.ecs)
+        Some(action) => action,
+    };
+
+    if ctx.shift {
+        if let Some((dx, dy)) = action.direction() {
+            start_travel(gs, TravelGoal::Run { dx, dy });
+            return RunState::AwaitingInput;
+        }
+    }
+
+    let command = match action {
+        Action::MoveW => PlayerCommand::Move { dx: -1, dy: 0 },
+        Action::MoveE => PlayerCommand::Move { dx: 1, dy: 0 },
+        Action::MoveN => PlayerCommand::Move { dx: 0, dy: -1 },
+        Action::MoveS => PlayerCommand::Move { dx: 0, dy: 1 },
+        Action::MoveNE => PlayerCommand::Move { dx: 1, dy: -1 },
+        Action::MoveNW => PlayerCommand::Move { dx: -1, dy: -1 },
+        Action::MoveSW => PlayerCommand::Move { dx: -1, dy: 1 },
+        Action::MoveSE => PlayerCommand::Move { dx: 1, dy: 1 },
+        Action::PickUp => PlayerCommand::PickUp,
+        Action::Inventory => return RunState::ShowInventory,
+        Action::Drop => return RunState::ShowDropItem,
+        Action::SaveAndQuit => return RunState::SaveGame,
+        Action::Wait => PlayerCommand::Wait,
+        Action::RemoveItem => return RunState::ShowRemoveItem,
+        Action::CloseDoor => PlayerCommand::CloseDoor,
+        Action::AbandonRun => return RunState::GameOver,
+        Action::Descend => PlayerCommand::Descend,
+        Action::Ascend => PlayerCommand::Ascend,
+        Action::AutoExplore => {
+            start_travel(gs, TravelGoal::Explore);
+            return RunState::AwaitingInput;
+        }
+        Action::TravelToStairs => {
+            start_travel(gs, TravelGoal::StairsDown);
+            return RunState::AwaitingInput;
+        }
+        Action::Rest => {
+            start_travel(gs, TravelGoal::Rest);
+            return RunState::AwaitingInput;
+        }
+        Action::Help => return RunState::ShowHelp,
+        Action::Look => {
+            let player_pos = *gs.ecs.fetch::<Point>();
+            return RunState::LookMode {
+                x: player_pos.x,
+                y: player_pos.y,
+            };
+        }
+        Action::Overview => return RunState::ShowOverview,
+        Action::Encyclopedia => return RunState::ShowEncyclopedia { selection: 0 },
+        Action::ToggleGraphics => {
+            toggle_graphics(gs);
+            return RunState::AwaitingInput;
+        }
+        Action::MessageLog => {
+            return RunState::ShowLog {
+                offset: 0,
+                filter: None,
             }
-            VirtualKeyCode::Up | VirtualKeyCode::Numpad8 | VirtualKeyCode::K => {
-                try_move_player(0, -1, &mut gs.ecs)
+        }
+    };
+
+    perform_command(&mut gs.ecs, command)
+}
+
+/// Switches the map between ASCII and the sprite tileset, if one was loaded at startup.
+fn toggle_graphics(gs: &mut State) {
+    let message = if gs.tileset.is_none() {
+        "There is no tileset to switch to."
+    } else {
+        gs.settings.use_tileset = !gs.settings.use_tileset;
+        gs.settings.save();
+        if gs.settings.use_tileset {
+            "Switched to tiles."
+        } else {
+            "Switched to ASCII."
+        }
+    };
+    gs.ecs.write_resource::<GameLog>().add(LogCategory::System, message);
+}
+
+fn start_travel(gs: &mut State, goal: TravelGoal) {
+    match Travel::start(&gs.ecs, goal) {
+        Ok(travel) => gs.travel = Some(travel),
+        Err(stop) => {
+            if let Some(message) = stop.message() {
+                gs.ecs.write_resource::<GameLog>().add(LogCategory::System, message);
             }
-            VirtualKeyCode::Down | VirtualKeyCode::Numpad2 | VirtualKeyCode::J => {
-                try_move_player(0, 1, &mut gs.ecs)
+        }
+    }
+}
+
+/// Takes the next step of an automatic walk, if there is one. Any key press stops the walk.
+pub fn continue_travel(gs: &mut State, ctx: &mut Rltk) -> RunState {
+    let step = match gs.travel.as_mut() {
+        None => return RunState::AwaitingInput,
+        Some(_) if ctx.key.is_some() || ctx.left_click => Err(TravelStop::Interrupted),
+        Some(travel) => travel.next_step(&gs.ecs),
+    };
+
+    match step {
+        Ok(command) => perform_command(&mut gs.ecs, command),
+        Err(stop) => {
+            gs.travel = None;
+            if let Some(message) = stop.message() {
+                gs.ecs.write_resource::<GameLog>().add(LogCategory::System, message);
             }
+            RunState::AwaitingInput
+        }
+    }
+}
 
-            VirtualKeyCode::Numpad9 => try_move_player(1, -1, &mut gs.ecs),
-            VirtualKeyCode::Numpad7 => try_move_player(-1, -1, &mut gs.ecs),
-            VirtualKeyCode::Numpad1 => try_move_player(-1, 1, &mut gs.ecs),
-            VirtualKeyCode::Numpad3 => try_move_player(1, 1, &mut gs.ecs),
-            VirtualKeyCode::G => get_item(&mut gs.ecs),
-            VirtualKeyCode::I => return RunState::ShowInventory,
-            VirtualKeyCode::D => return RunState::ShowDropItem,
-            VirtualKeyCode::Escape => return RunState::SaveGame,
-            VirtualKeyCode::Numpad5 | VirtualKeyCode::Space => return skip_turn(&mut gs.ecs),
-            VirtualKeyCode::R => return RunState::ShowRemoveItem,
-            VirtualKeyCode::F12 => return RunState::GameOver,
-            VirtualKeyCode::Period => {
-                if try_next_level(&mut gs.ecs) {
-                    return RunState::NextLevel;
-                }
+/// Records the command for replays and then carries it out. Live input and replay playback
+/// both come through here so that they drive exactly the same turn pipeline.
+pub fn perform_command(ecs: &mut World, command: PlayerCommand) -> RunState {
+    ecs.write_resource::<ReplayRecorder>().record(command);
+
+    match command {
+        PlayerCommand::Move { dx, dy } => try_move_player(dx, dy, ecs),
+        PlayerCommand::Wait => return skip_turn(ecs),
+        PlayerCommand::PickUp => get_item(ecs),
+        PlayerCommand::CloseDoor => {
+            if !try_close_door(ecs) {
+                return RunState::AwaitingInput;
             }
-            _ => return RunState::AwaitingInput,
-        },
+        }
+        PlayerCommand::Descend => {
+            return if try_next_level(ecs) {
+                RunState::NextLevel
+            } else {
+                RunState::AwaitingInput
+            };
+        }
+        PlayerCommand::Ascend => {
+            return if try_previous_level(ecs) {
+                RunState::PreviousLevel
+            } else {
+                RunState::AwaitingInput
+            };
+        }
+        PlayerCommand::UseItem { item, target } => {
+            let item = ecs.entities().entity(item);
+            let player_entity = *ecs.fetch::<Entity>();
+            ecs.write_storage::<WantsToUseItem>()
+                .insert(player_entity, WantsToUseItem { item, target })
+                .expect("Unable to insert use item intent");
+        }
+        PlayerCommand::DropItem { item } => {
+            let item = ecs.entities().entity(item);
+            let player_entity = *ecs.fetch::<Entity>();
+            ecs.write_storage::<WantsToDropItem>()
+                .insert(player_entity, WantsToDropItem { item })
+                .expect("Unable to insert drop item intent");
+        }
+        PlayerCommand::RemoveItem { item } => {
+            let item = ecs.entities().entity(item);
+            let player_entity = *ecs.fetch::<Entity>();
+            ecs.write_storage::<WantsToRemoveItem>()
+                .insert(player_entity, WantsToRemoveItem { item })
+                .expect("Unable to insert remove item intent");
+        }
     }
 
     RunState::PlayerTurn
@@ -134,8 +291,55 @@ pub fn try_next_level(ecs: &mut World) -> bool {
         true
     } else {
         let mut log = ecs.write_resource::<GameLog>();
-        log.entries
-            .push("There is no way to go down from here.".to_string());
+        log.add(LogCategory::System, "There is no way to go down from here.");
+        false
+    }
+}
+
+pub fn try_close_door(ecs: &mut World) -> bool {
+    let player_pos = ecs.fetch::<Point>();
+    let player_entity = ecs.fetch::<Entity>();
+    let map = ecs.fetch::<Map>();
+    let doors = ecs.read_storage::<Door>();
+
+    for y in player_pos.y - 1..=player_pos.y + 1 {
+        for x in player_pos.x - 1..=player_pos.x + 1 {
+            if x < 0 || x >= map.width || y < 0 || y >= map.height {
+                continue;
+            }
+
+            let idx = map.xy_idx(x, y);
+            if map.tile_content[idx].len() != 1 {
+                continue;
+            }
+
+            let door_entity = map.tile_content[idx][0];
+            if let Some(door) = doors.get(door_entity) {
+                if door.open {
+                    let mut wants_to_close = ecs.write_storage::<WantsToCloseDoor>();
+                    wants_to_close
+                        .insert(*player_entity, WantsToCloseDoor { door: door_entity })
+                        .expect("Unable to insert close door action");
+                    return true;
+                }
+            }
+        }
+    }
+
+    let mut log = ecs.write_resource::<GameLog>();
+    log.add(LogCategory::System, "There is no open door here to close.");
+    false
+}
+
+pub fn try_previous_level(ecs: &mut World) -> bool {
+    let player_position = ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+    let player_index = map.xy_idx(player_position.x, player_position.y);
+    if map.tiles[player_index] == TileType::StairsUp {
+        true
+    } else {
+        let mut log = ecs.write_resource::<GameLog>();
+        log.add(LogCategory::System, "There is no way to go up from here.");
         false
     }
 }
@@ -156,9 +360,7 @@ fn get_item(ecs: &mut World) {
     }
 
     match target_item {
-        None => log
-            .entries
-            .push("There is nothing here to pick up".to_string()),
+        None => log.add(LogCategory::Item, "There is nothing here to pick up"),
         Some(item) => {
             let mut pickup = ecs.write_storage::<WantsToPickupItem>();
             pickup
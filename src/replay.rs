@@ -0,0 +1,189 @@
+use crate::save_dir;
+use rltk::{Point, RandomNumberGenerator, Rltk, VirtualKeyCode, RGB};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::fs::File;
+use std::path::PathBuf;
+
+const REPLAY_FILE: &str = "replay.json";
+const REPLAY_VERSION: u32 = 1;
+const TURN_DELAYS_MS: [f32; 5] = [1000.0, 500.0, 250.0, 100.0, 25.0];
+
+/// Everything the player can do that advances the game. Items are referred to by entity id,
+/// which is stable because a replay always starts from a freshly built world.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum PlayerCommand {
+    Move { dx: i32, dy: i32 },
+    Wait,
+    PickUp,
+    CloseDoor,
+    Descend,
+    Ascend,
+    UseItem { item: u32, target: Option<Point> },
+    DropItem { item: u32 },
+    RemoveItem { item: u32 },
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    pub commands: Vec<PlayerCommand>,
+}
+
+pub struct ReplayRecorder {
+    pub seed: u64,
+    pub commands: Vec<PlayerCommand>,
+    pub enabled: bool,
+}
+
+impl ReplayRecorder {
+    pub fn new(seed: u64) -> ReplayRecorder {
+        ReplayRecorder {
+            seed,
+            commands: Vec::new(),
+            enabled: true,
+        }
+    }
+
+    pub fn record(&mut self, command: PlayerCommand) {
+        if self.enabled {
+            self.commands.push(command);
+        }
+    }
+
+    pub fn save(&self) {
+        if !self.enabled {
+            return;
+        }
+
+        let replay = Replay {
+            version: REPLAY_VERSION,
+            seed: self.seed,
+            commands: self.commands.clone(),
+        };
+
+        match fs::create_dir_all(save_dir()).and_then(|_| File::create(replay_path())) {
+            Ok(writer) => {
+                if let Err(e) = serde_json::to_writer(writer, &replay) {
+                    rltk::console::log(format!("Unable to write replay: {}", e));
+                }
+            }
+            Err(e) => rltk::console::log(format!("Unable to create replay file: {}", e)),
+        }
+    }
+}
+
+pub fn fresh_seed() -> u64 {
+    RandomNumberGenerator::new().next_u64()
+}
+
+/// The last run's replay is kept next to the saves.
+fn replay_path() -> PathBuf {
+    save_dir().join(REPLAY_FILE)
+}
+
+pub fn replay_exists() -> bool {
+    replay_path().exists()
+}
+
+pub fn load_replay() -> Option<Replay> {
+    let data = fs::read_to_string(replay_path()).ok()?;
+    match serde_json::from_str::<Replay>(&data) {
+        Ok(replay) if replay.version == REPLAY_VERSION => Some(replay),
+        Ok(replay) => {
+            rltk::console::log(format!("Unsupported replay version {}", replay.version));
+            None
+        }
+        Err(e) => {
+            rltk::console::log(format!("Unable to read replay: {}", e));
+            None
+        }
+    }
+}
+
+pub enum PlaybackInput {
+    Idle,
+    Command(PlayerCommand),
+    Stop,
+}
+
+pub struct ReplayPlayback {
+    commands: Vec<PlayerCommand>,
+    next: usize,
+    paused: bool,
+    speed: usize,
+    timer: f32,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> ReplayPlayback {
+        ReplayPlayback {
+            commands: replay.commands,
+            next: 0,
+            paused: false,
+            speed: 2,
+            timer: 0.0,
+        }
+    }
+
+    pub fn poll(&mut self, ctx: &Rltk) -> PlaybackInput {
+        let mut step = false;
+        match ctx.key {
+            Some(VirtualKeyCode::Escape) => return PlaybackInput::Stop,
+            Some(VirtualKeyCode::Space) => self.paused = !self.paused,
+            Some(VirtualKeyCode::Equals) | Some(VirtualKeyCode::Plus) | Some(VirtualKeyCode::NumpadAdd) => {
+                self.speed = usize::min(self.speed + 1, TURN_DELAYS_MS.len() - 1)
+            }
+            Some(VirtualKeyCode::Minus) | Some(VirtualKeyCode::NumpadSubtract) => {
+                self.speed = self.speed.saturating_sub(1)
+            }
+            Some(VirtualKeyCode::S) => step = true,
+            _ => {}
+        }
+
+        if !step {
+            if self.paused {
+                return PlaybackInput::Idle;
+            }
+
+            self.timer += ctx.frame_time_ms;
+            if self.timer < TURN_DELAYS_MS[self.speed] {
+                return PlaybackInput::Idle;
+            }
+        }
+        self.timer = 0.0;
+
+        match self.commands.get(self.next) {
+            Some(command) => {
+                self.next += 1;
+                PlaybackInput::Command(*command)
+            }
+            None => PlaybackInput::Idle,
+        }
+    }
+
+    pub fn draw_status(&self, ctx: &mut Rltk) {
+        let state = if self.next >= self.commands.len() {
+            "finished"
+        } else if self.paused {
+            "paused"
+        } else {
+            "playing"
+        };
+
+        ctx.print_color(
+            0,
+            0,
+            RGB::named(rltk::BLACK),
+            RGB::named(rltk::YELLOW),
+            format!(
+                " Replay {} - turn {}/{} - speed {} | Space: pause, S: step, +/-: speed, Esc: stop ",
+                state,
+                self.next,
+                self.commands.len(),
+                self.speed + 1
+            ),
+        );
+    }
+}
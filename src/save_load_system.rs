@@ -1,14 +1,164 @@
+use crate::hardcore::{Ledger, LedgerCheck, RunInfo};
+use crate::settings::{self, LogLevel};
 use crate::*;
-use serde_json::de::StrRead;
-use serde_json::{Deserializer, Serializer};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::{Deserializer, Value};
 use specs::error::NoError;
 use specs::prelude::*;
 use specs::saveload::{
     DeserializeComponents, MarkedBuilder, SerializeComponents, SimpleMarker, SimpleMarkerAllocator,
 };
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
 use std::fs;
-use std::fs::File;
-use std::path::Path;
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+const SAVE_DIR_ENV: &str = "DEATHLIKE_SAVE_DIR";
+const SAVE_FORMAT_ENV: &str = "DEATHLIKE_SAVE_FORMAT";
+const SAVE_BACKUP_ENV: &str = "DEATHLIKE_SAVE_BACKUP";
+const SAVE_EXTENSION: &str = "sav";
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const METADATA_EXTENSION: &str = "meta.json";
+const BACKUP_EXTENSION: &str = "bak";
+const TEMP_EXTENSION: &str = "tmp";
+
+/// Bump this whenever the saved component list or a saved component's fields change, and add a
+/// step to `migrate` that upgrades the previous format.
+const SAVE_FORMAT_VERSION: u32 = 4;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum SaveEncoding {
+    Json,
+    MessagePack,
+}
+
+/// How a save is written. Loading detects the encoding and compression from the file itself, so
+/// saves written with any options can always be read back.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct SaveOptions {
+    pub encoding: SaveEncoding,
+    pub compress: bool,
+    /// Keep the previous save of a slot as a backup, used if the newer one can't be loaded.
+    pub keep_backup: bool,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        SaveOptions {
+            encoding: SaveEncoding::MessagePack,
+            compress: true,
+            keep_backup: true,
+        }
+    }
+}
+
+impl SaveOptions {
+    /// Parses "json", "json.gz", "msgpack" or "msgpack.gz".
+    pub fn parse(name: &str) -> Option<SaveOptions> {
+        let (encoding, compress) = match name.strip_suffix(".gz") {
+            Some(encoding) => (encoding, true),
+            None => (name, false),
+        };
+        let encoding = match encoding {
+            "json" => SaveEncoding::Json,
+            "msgpack" => SaveEncoding::MessagePack,
+            _ => return None,
+        };
+        Some(SaveOptions {
+            encoding,
+            compress,
+            ..SaveOptions::default()
+        })
+    }
+
+    /// Uses `DEATHLIKE_SAVE_FORMAT` when it is set, e.g. to write readable JSON while debugging,
+    /// and turns backups off when `DEATHLIKE_SAVE_BACKUP` is "0" or "false".
+    pub fn from_env() -> SaveOptions {
+        let mut options = match env::var(SAVE_FORMAT_ENV) {
+            Ok(name) => SaveOptions::parse(&name).unwrap_or_else(|| {
+                rltk::console::log(format!("Unknown save format {}, using the default", name));
+                SaveOptions::default()
+            }),
+            Err(_) => SaveOptions::default(),
+        };
+        if let Ok(backup) = env::var(SAVE_BACKUP_ENV) {
+            options.keep_backup = !matches!(backup.as_str(), "0" | "false");
+        }
+        options
+    }
+}
+
+/// Stored next to each save so the load screen can describe a slot without deserializing the
+/// whole world.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SaveMetadata {
+    pub slot: String,
+    pub character_name: String,
+    pub depth: i32,
+    pub turn: i32,
+    pub timestamp: u64,
+    pub seed: u64,
+}
+
+/// Written at the start of every save file. Saves from before the header existed are treated as
+/// format version 0.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SaveHeader {
+    pub format_version: u32,
+    pub game_version: String,
+    #[serde(default)]
+    pub run: Option<RunInfo>,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Parse(String),
+    UnsupportedVersion(u32),
+    Invalid(String),
+    RunEnded,
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "could not access save file: {}", e),
+            SaveError::Parse(e) => write!(f, "save file is corrupt: {}", e),
+            SaveError::UnsupportedVersion(v) => write!(f, "save format {} is not supported", v),
+            SaveError::Invalid(e) => write!(f, "save file is invalid: {}", e),
+            SaveError::RunEnded => write!(f, "this hardcore run has already ended"),
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(e: io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(e: serde_json::Error) -> Self {
+        SaveError::Parse(e.to_string())
+    }
+}
+
+impl From<rmp_serde::encode::Error> for SaveError {
+    fn from(e: rmp_serde::encode::Error) -> Self {
+        SaveError::Parse(e.to_string())
+    }
+}
+
+impl From<rmp_serde::decode::Error> for SaveError {
+    fn from(e: rmp_serde::decode::Error) -> Self {
+        SaveError::Parse(e.to_string())
+    }
+}
 
 macro_rules! serialize_individual_items {
     ($ecs: expr, $ser: expr, $data: expr, $( $type: ty ), *) => {
@@ -19,7 +169,7 @@ macro_rules! serialize_individual_items {
                 &$data.1,
                 &mut $ser,
             )
-            .unwrap();
+            .map_err(|e| SaveError::Parse(format!("{}: {}", stringify!($type), e)))?;
         )
         *
     };
@@ -35,96 +185,440 @@ macro_rules! deserialize_individual_items {
                 &mut $data.2,
                 &mut $de,
             )
-            .unwrap();
+            .map_err(|e| SaveError::Parse(format!("{}: {}", stringify!($type), e)))?;
         )
         *
     };
 }
 
-pub fn save_game(ecs: &mut World) {
-    rltk::console::log("Saving world");
+pub fn save_game(ecs: &mut World, options: SaveOptions) -> Result<(), SaveError> {
+    settings::log(LogLevel::Normal, format!("Saving world as {:?}", options));
+    // A hardcore save is only written if the ledger can record it afterwards.
+    let ledger = if ecs.fetch::<RunInfo>().hardcore {
+        Some(Ledger::load()?)
+    } else {
+        None
+    };
+    let metadata = build_metadata(ecs);
+    ecs.write_resource::<RunInfo>().save_sequence += 1;
+    let data = serialize_world(ecs, options)?;
+
+    fs::create_dir_all(save_dir())?;
+    let path = slot_path(&metadata.slot);
+    if options.keep_backup && path.exists() {
+        fs::copy(&path, with_extension(&path, BACKUP_EXTENSION))?;
+    }
+    write_atomically(&path, &data)?;
+    write_atomically(
+        &metadata_path(&metadata.slot),
+        serde_json::to_string_pretty(&metadata)?.as_bytes(),
+    )?;
+
+    if let Some(ledger) = ledger {
+        hardcore::record_save(ledger, &ecs.fetch::<RunInfo>(), hardcore::checksum(&data))?;
+    }
+    Ok(())
+}
+
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// Writes to a temporary file next to `path` and renames it into place, so a crash part way
+/// through leaves the previous file untouched.
+pub fn write_atomically(path: &Path, data: &[u8]) -> Result<(), SaveError> {
+    let temp = with_extension(path, TEMP_EXTENSION);
+    {
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+    }
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+fn serialize_world(ecs: &mut World, options: SaveOptions) -> Result<Vec<u8>, SaveError> {
     let map_copy = ecs.get_mut::<Map>().unwrap().clone();
+    let town_copy = ecs.fetch::<StoredTown>().map.clone();
     let save_helper = ecs
         .create_entity()
-        .with(SerializationHelper { map: map_copy })
+        .with(SerializationHelper {
+            map: map_copy,
+            town: town_copy,
+        })
         .marked::<SimpleMarker<Savable>>()
         .build();
 
-    {
-        let data = (ecs.entities(), ecs.read_storage::<SimpleMarker<Savable>>());
-
-        let writer = File::create("./savegame.json").unwrap();
-        let mut serializer = Serializer::new(writer);
-        serialize_individual_items!(
-            ecs,
-            serializer,
-            data,
-            Position,
-            Renderable,
-            Player,
-            Viewshed,
-            Monster,
-            Name,
-            BlocksTile,
-            CombatStats,
-            SufferDamage,
-            WantsToMelee,
-            Item,
-            Consumable,
-            Ranged,
-            InflictsDamage,
-            AreaOfEffect,
-            Confusion,
-            ProvidesHealing,
-            InBackpack,
-            WantsToPickupItem,
-            WantsToUseItem,
-            WantsToDropItem,
-            SerializationHelper,
-            Equippable,
-            Equipped,
-            MeleePowerBonus,
-            DefenseBonus,
-            WantsToRemoveItem,
-            ParticleLifetime,
-            MagicMapper,
-            Hidden,
-            EntryTrigger,
-            EntityMoved,
-            SingleActivation
-        );
-    }
+    let mut buffer = Vec::new();
+    let result = match options.encoding {
+        SaveEncoding::Json => {
+            write_components(ecs, &mut serde_json::Serializer::new(&mut buffer))
+        }
+        SaveEncoding::MessagePack => {
+            write_components(ecs, &mut rmp_serde::Serializer::new(&mut buffer))
+        }
+    };
 
     ecs.delete_entity(save_helper)
         .expect("failed to cleanup saver");
+    result?;
+
+    if options.compress {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&buffer)?;
+        buffer = encoder.finish()?;
+    }
+    Ok(buffer)
+}
+
+fn write_components<S>(ecs: &World, serializer: &mut S) -> Result<(), SaveError>
+where
+    for<'a> &'a mut S: serde::Serializer,
+{
+    let header = SaveHeader {
+        format_version: SAVE_FORMAT_VERSION,
+        game_version: env!("CARGO_PKG_VERSION").to_string(),
+        run: Some((*ecs.fetch::<RunInfo>()).clone()),
+    };
+    header
+        .serialize(&mut *serializer)
+        .map_err(|e| SaveError::Parse(format!("header: {}", e)))?;
+
+    let data = (ecs.entities(), ecs.read_storage::<SimpleMarker<Savable>>());
+    with_components!(saved, serialize_individual_items, ecs, *serializer, data);
+    Ok(())
+}
+
+/// A world with just enough registered to restore a save into, without the game's resources.
+fn scratch_world() -> World {
+    let mut scratch = World::new();
+    component_registry::register_components(&mut scratch);
+    scratch.insert(SimpleMarkerAllocator::<Savable>::new());
+    scratch.insert(Map::new(0, 1, 1));
+    scratch.insert(StoredTown::default());
+    scratch.insert(Point::new(0, 0));
+    let placeholder = scratch.create_entity().build();
+    scratch.insert(placeholder);
+    scratch
+}
+
+/// Rewrites a save file with different options, e.g. to turn a binary save into JSON to inspect it.
+pub fn convert_save(input: &Path, output: &Path, options: SaveOptions) -> Result<(), SaveError> {
+    let mut scratch = scratch_world();
+    restore_world(&mut scratch, &fs::read(input)?)?;
+    let data = serialize_world(&mut scratch, options)?;
+    fs::write(output, data)?;
+    Ok(())
+}
+
+/// Saves live in the platform data directory unless `DEATHLIKE_SAVE_DIR` points somewhere else.
+pub fn save_dir() -> PathBuf {
+    if let Some(dir) = env::var_os(SAVE_DIR_ENV) {
+        return PathBuf::from(dir);
+    }
+
+    match dirs::data_dir() {
+        Some(dir) => dir.join("deathlike").join("saves"),
+        None => PathBuf::from("./saves"),
+    }
+}
+
+fn slot_path(slot: &str) -> PathBuf {
+    save_dir().join(format!("{}.{}", slot, SAVE_EXTENSION))
+}
+
+fn metadata_path(slot: &str) -> PathBuf {
+    save_dir().join(format!("{}.{}", slot, METADATA_EXTENSION))
+}
+
+/// Each run gets its own slot, named after the character and the seed the run started from.
+fn slot_name(character_name: &str, seed: u64) -> String {
+    let name: String = character_name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    format!("{}-{:016x}", name, seed)
+}
+
+fn character_name(ecs: &World) -> String {
+    let player_entity = *ecs.fetch::<Entity>();
+    ecs.read_storage::<Name>()
+        .get(player_entity)
+        .map(|name| name.name.clone())
+        .unwrap_or_else(|| "Player".to_string())
+}
+
+/// Removes the slot the current run autosaves into, so a dead character can't be loaded again.
+pub fn delete_current_slot(ecs: &World) {
+    let seed = ecs.fetch::<ReplayRecorder>().seed;
+    delete_save_slot(&slot_name(&character_name(ecs), seed));
+}
+
+fn build_metadata(ecs: &World) -> SaveMetadata {
+    let player_entity = *ecs.fetch::<Entity>();
+    let character_name = character_name(ecs);
+    let turn = ecs
+        .read_storage::<RunStats>()
+        .get(player_entity)
+        .map(|stats| stats.turns)
+        .unwrap_or(0);
+    let seed = ecs.fetch::<ReplayRecorder>().seed;
+
+    SaveMetadata {
+        slot: slot_name(&character_name, seed),
+        character_name,
+        depth: ecs.fetch::<Map>().depth,
+        turn,
+        timestamp: crate::morgue::unix_time(),
+        seed,
+    }
+}
+
+pub fn list_save_slots() -> Vec<SaveMetadata> {
+    let entries = match fs::read_dir(save_dir()) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let suffix = format!(".{}", METADATA_EXTENSION);
+    let mut slots: Vec<SaveMetadata> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(&suffix))
+        .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+        .filter_map(|data| serde_json::from_str::<SaveMetadata>(&data).ok())
+        .filter(|meta| slot_path(&meta.slot).exists())
+        .collect();
+
+    slots.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| a.slot.cmp(&b.slot)));
+    slots
 }
 
 pub fn save_exists() -> bool {
-    Path::new("./savegame.json").exists()
+    !list_save_slots().is_empty()
 }
 
-pub fn delete_saved_game() {
-    if save_exists() {
-        fs::remove_file("./savegame.json").expect("could not delete saved game file");
+pub fn delete_save_slot(slot: &str) {
+    let backup = with_extension(&slot_path(slot), BACKUP_EXTENSION);
+    for path in [slot_path(slot), metadata_path(slot), backup].iter() {
+        if path.exists() {
+            if let Err(e) = fs::remove_file(path) {
+                rltk::console::log(format!("Unable to delete {}: {}", path.display(), e));
+            }
+        }
     }
 }
 
-pub fn load_game(ecs: &mut World) {
-    rltk::console::log("Loading world");
-    clear_game_world(ecs);
+/// Loads a slot into the world. On failure the world is left partially cleared, so the caller
+/// should rebuild it before continuing.
+pub fn load_game(ecs: &mut World, slot: &SaveMetadata) -> Result<(), SaveError> {
+    settings::log(LogLevel::Normal, format!("Loading world from slot {}", slot.slot));
+    let path = slot_path(&slot.slot);
+    let loaded = fs::read(&path)
+        .map_err(SaveError::from)
+        .and_then(|data| restore_world(ecs, &data).map(|_| data));
 
-    let data = fs::read_to_string("./savegame.json").unwrap();
-    let mut deserializer = serde_json::Deserializer::from_str(&data);
+    let backup = with_extension(&path, BACKUP_EXTENSION);
+    let data = match loaded {
+        Err(e) if backup.exists() => {
+            settings::log(LogLevel::Normal, format!("Save {} failed to load ({}), trying its backup", slot.slot, e));
+            let data = fs::read(&backup)?;
+            restore_world(ecs, &data)?;
+            data
+        }
+        loaded => loaded?,
+    };
 
-    load_game_resources(ecs, &mut deserializer);
+    check_ledger(ecs, &data)
+}
 
-    let delete_me = populate_world_from_save_file(ecs);
+/// Hardcore saves must be the latest one written for a run that is still alive. Older saves still
+/// load, but the run is flagged so its score shows it was restored. Without a readable ledger
+/// hardcore saves don't load at all.
+fn check_ledger(ecs: &mut World, data: &[u8]) -> Result<(), SaveError> {
+    let mut run = ecs.write_resource::<RunInfo>();
+    if !run.hardcore {
+        return Ok(());
+    }
 
-    ecs.delete_entity(delete_me.unwrap())
+    match Ledger::load()?.check(&run, &hardcore::checksum(data)) {
+        LedgerCheck::Current => Ok(()),
+        LedgerCheck::OutOfOrder => {
+            settings::log(LogLevel::Normal, "Hardcore save was restored out of order");
+            run.restored_out_of_order = true;
+            Ok(())
+        }
+        LedgerCheck::RunEnded => Err(SaveError::RunEnded),
+    }
+}
+
+fn restore_world(ecs: &mut World, data: &[u8]) -> Result<(), SaveError> {
+    let data = if data.starts_with(&GZIP_MAGIC) {
+        let mut decompressed = Vec::new();
+        GzDecoder::new(data).read_to_end(&mut decompressed)?;
+        decompressed
+    } else {
+        data.to_vec()
+    };
+
+    // JSON saves always start with an object or an array, MessagePack saves with a binary header.
+    let is_json = data
+        .iter()
+        .find(|b| !b.is_ascii_whitespace())
+        .is_some_and(|b| *b == b'{' || *b == b'[');
+
+    let header = if is_json {
+        let data = std::str::from_utf8(&data).map_err(|e| SaveError::Parse(e.to_string()))?;
+        let (header, body) = read_header(data)?;
+        if check_version(&header)? {
+            clear_game_world(ecs);
+            load_game_resources(ecs, &mut serde_json::Deserializer::from_str(body))?;
+        } else {
+            let values = Deserializer::from_str(body)
+                .into_iter::<Value>()
+                .collect::<Result<Vec<Value>, _>>()?;
+            load_migrated(ecs, header.format_version, values)?;
+        }
+        header
+    } else {
+        let mut deserializer = rmp_serde::Deserializer::new(&data[..]);
+        let header = SaveHeader::deserialize(&mut deserializer)?;
+        if check_version(&header)? {
+            clear_game_world(ecs);
+            load_game_resources(ecs, &mut deserializer)?;
+        } else {
+            let mut values = Vec::new();
+            while !deserializer.get_ref().is_empty() {
+                values.push(Value::deserialize(&mut deserializer)?);
+            }
+            load_migrated(ecs, header.format_version, values)?;
+        }
+        header
+    };
+
+    let delete_me = populate_world_from_save_file(ecs)?;
+    ecs.delete_entity(delete_me)
         .expect("could not delete helper");
+
+    // Saves from before runs were tracked are treated as ordinary, non-hardcore runs.
+    ecs.insert(header.run.unwrap_or_else(|| RunInfo::new(false)));
+    Ok(())
 }
 
-fn populate_world_from_save_file(ecs: &mut World) -> Option<Entity> {
+/// Returns whether the save is in the current format, or needs migrating first.
+fn check_version(header: &SaveHeader) -> Result<bool, SaveError> {
+    if header.format_version > SAVE_FORMAT_VERSION {
+        return Err(SaveError::UnsupportedVersion(header.format_version));
+    }
+    if header.format_version != SAVE_FORMAT_VERSION {
+        settings::log(LogLevel::Normal, format!(
+            "Migrating save from format {} (game {})",
+            header.format_version, header.game_version
+        ));
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Older saves are decoded into one value per saved component list, migrated, and then loaded
+/// as JSON regardless of the encoding they were written in.
+fn load_migrated(ecs: &mut World, version: u32, values: Vec<Value>) -> Result<(), SaveError> {
+    let values = migrate(version, values)?;
+    let mut body = String::new();
+    for value in values.iter() {
+        body.push_str(&serde_json::to_string(value)?);
+    }
+
+    clear_game_world(ecs);
+    load_game_resources(ecs, &mut serde_json::Deserializer::from_str(&body))
+}
+
+/// Splits off the header. Saves written before the header was introduced start directly with the
+/// first component list.
+fn read_header(data: &str) -> Result<(SaveHeader, &str), SaveError> {
+    if data.trim_start().starts_with('[') {
+        let legacy = SaveHeader {
+            format_version: 0,
+            game_version: "unknown".to_string(),
+            run: None,
+        };
+        return Ok((legacy, data));
+    }
+
+    let mut stream = Deserializer::from_str(data).into_iter::<SaveHeader>();
+    let header = match stream.next() {
+        Some(header) => header?,
+        None => return Err(SaveError::Invalid("file is empty".to_string())),
+    };
+    Ok((header, &data[stream.byte_offset()..]))
+}
+
+/// Upgrades the component lists of an older save one format version at a time. Each entry in
+/// `values` is the list for one saved component, in registry order.
+fn migrate(mut version: u32, mut values: Vec<Value>) -> Result<Vec<Value>, SaveError> {
+    while version < SAVE_FORMAT_VERSION {
+        match version {
+            // Format 1 added the header. Saves from before it stop at SingleActivation, or at
+            // whichever component had been added last, and their damage and map lack fields that
+            // were added along the way.
+            0 => {
+                const FORMAT_1_LISTS: usize = 44;
+                const SUFFER_DAMAGE: usize = 8;
+                const SERIALIZATION_HELPER: usize = 21;
+                if values.len() <= SERIALIZATION_HELPER || values.len() > FORMAT_1_LISTS {
+                    return Err(SaveError::Invalid("unexpected number of component lists".to_string()));
+                }
+                values.resize(FORMAT_1_LISTS, Value::Array(vec![]));
+
+                for damage in saved_components_mut(&mut values[SUFFER_DAMAGE]) {
+                    fill_missing(damage, "source", Value::String(String::new()));
+                    fill_missing(damage, "by_player", Value::Bool(false));
+                }
+                let theme = serde_json::to_value(Theme::default())?;
+                for helper in saved_components_mut(&mut values[SERIALIZATION_HELPER]) {
+                    if let Some(map) = helper.get_mut("map") {
+                        fill_missing(map, "theme", theme.clone());
+                    }
+                }
+            }
+            // Format 2 stopped saving ParticleLifetime, which followed WantsToRemoveItem.
+            1 => {
+                const PARTICLE_LIFETIME: usize = 27;
+                if values.len() <= PARTICLE_LIFETIME {
+                    return Err(SaveError::Invalid("too few component lists".to_string()));
+                }
+                values.remove(PARTICLE_LIFETIME);
+            }
+            // Format 3 added RunStats::encountered, which older saves start off without.
+            2 => {}
+            // Format 4 added OtherLevelPosition at the end of the saved components.
+            3 => values.push(Value::Array(vec![])),
+            _ => return Err(SaveError::UnsupportedVersion(version)),
+        }
+        version += 1;
+    }
+    Ok(values)
+}
+
+/// The components in one saved list, skipping the entities that don't have one.
+fn saved_components_mut(list: &mut Value) -> impl Iterator<Item = &mut Value> {
+    list.as_array_mut()
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.get_mut("components")?.get_mut(0))
+        .filter(|component| component.is_object())
+}
+
+fn fill_missing(object: &mut Value, field: &str, value: Value) {
+    if let Some(object) = object.as_object_mut() {
+        object.entry(field).or_insert(value);
+    }
+}
+
+fn populate_world_from_save_file(ecs: &mut World) -> Result<Entity, SaveError> {
     let entities = ecs.entities();
     let helper = ecs.read_storage::<SerializationHelper>();
     let player = ecs.read_storage::<Player>();
@@ -133,12 +627,23 @@ fn populate_world_from_save_file(ecs: &mut World) -> Option<Entity> {
     let mut delete_me: Option<Entity> = None;
 
     for (e, h) in (&entities, &helper).join() {
+        if delete_me.is_some() {
+            return Err(SaveError::Invalid("more than one map was saved".to_string()));
+        }
+        let tile_count = (h.map.width * h.map.height) as usize;
+        if h.map.width <= 0 || h.map.height <= 0 || h.map.tiles.len() != tile_count {
+            return Err(SaveError::Invalid("map dimensions do not match its tiles".to_string()));
+        }
+
         let mut world_map = ecs.write_resource::<Map>();
         *world_map = h.map.clone();
-        world_map.tile_content = vec![Vec::new(); MAP_COUNT];
+        world_map.tile_content = vec![Vec::new(); tile_count];
+        ecs.write_resource::<StoredTown>().map = h.town.clone();
         delete_me = Some(e);
     }
 
+    let delete_me = delete_me.ok_or_else(|| SaveError::Invalid("no map was saved".to_string()))?;
+
     let mut player_assigned = 0;
 
     for (e, _p, pos) in (&entities, &player, &position).join() {
@@ -149,58 +654,28 @@ fn populate_world_from_save_file(ecs: &mut World) -> Option<Entity> {
         player_assigned += 1;
     }
 
-    if player_assigned == 0 {
-        rltk::console::log("Did not assign any player resources");
+    if player_assigned != 1 {
+        return Err(SaveError::Invalid(format!(
+            "expected one player, found {}",
+            player_assigned
+        )));
     }
 
-    delete_me
+    Ok(delete_me)
 }
 
-fn load_game_resources(ecs: &mut World, deserializer: &mut Deserializer<StrRead>) {
+fn load_game_resources<'de, D>(ecs: &mut World, deserializer: &mut D) -> Result<(), SaveError>
+where
+    for<'a> &'a mut D: serde::Deserializer<'de>,
+{
     let mut d = (
         &mut ecs.entities(),
         &mut ecs.write_storage::<SimpleMarker<Savable>>(),
         &mut ecs.write_resource::<SimpleMarkerAllocator<Savable>>(),
     );
 
-    deserialize_individual_items!(
-        ecs,
-        *deserializer,
-        d,
-        Position,
-        Renderable,
-        Player,
-        Viewshed,
-        Monster,
-        Name,
-        BlocksTile,
-        CombatStats,
-        SufferDamage,
-        WantsToMelee,
-        Item,
-        Consumable,
-        Ranged,
-        InflictsDamage,
-        AreaOfEffect,
-        Confusion,
-        ProvidesHealing,
-        InBackpack,
-        WantsToPickupItem,
-        WantsToUseItem,
-        WantsToDropItem,
-        SerializationHelper,
-        Equippable,
-        Equipped,
-        MeleePowerBonus,
-        DefenseBonus,
-        WantsToRemoveItem,
-        ParticleLifetime,
-        MagicMapper,
-        Hidden,
-        EntryTrigger,
-        EntityMoved,
-        SingleActivation
-    );
+    with_components!(saved, deserialize_individual_items, ecs, *deserializer, d);
+    Ok(())
 }
 
 fn clear_game_world(ecs: &mut World) {
@@ -212,3 +687,280 @@ fn clear_game_world(ecs: &mut World) {
         ecs.delete_entity(*del).expect("Could not delete item");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encyclopedia::{EncyclopediaEntry, EntryKind};
+    use rltk::RGB;
+    use std::collections::BTreeMap;
+
+    macro_rules! component_values {
+        ($ecs: expr, $values: expr, $( $type: ty ), *) => {
+            $(
+                let value = SerializeComponents::<NoError, SimpleMarker<Savable>>::serialize(
+                    &( $ecs.read_storage::<$type>(), ),
+                    &$ecs.entities(),
+                    &$ecs.read_storage::<SimpleMarker<Savable>>(),
+                    serde_json::value::Serializer,
+                )
+                .expect(concat!("unable to serialize ", stringify!($type)));
+                $values.push((stringify!($type), value));
+            )
+            *
+        };
+    }
+
+    /// Every saved component as marker and field values, in registry order.
+    fn saved_components(ecs: &World) -> Vec<(&'static str, Value)> {
+        let mut values = Vec::new();
+        with_components!(saved, component_values, ecs, values);
+        values
+    }
+
+    /// A world holding one entity with every saved component, plus a second one for the
+    /// components that refer to other entities.
+    fn world_with_every_component() -> World {
+        let mut ecs = scratch_world();
+        ecs.insert(RunInfo::new(true));
+
+        let mut map = Map::new(3, 4, 3);
+        map.tiles[5] = TileType::Lava;
+        map.revealed_tiles[6] = true;
+        map.blood_stains.insert(5);
+        *ecs.write_resource::<Map>() = map;
+        ecs.write_resource::<StoredTown>().map = Some(Map::new(0, 2, 2));
+
+        let other = ecs
+            .create_entity()
+            .with(Name { name: "Orc".to_string() })
+            .marked::<SimpleMarker<Savable>>()
+            .build();
+
+        let mut kills = BTreeMap::new();
+        kills.insert("Goblin".to_string(), 3);
+        let mut encountered = BTreeMap::new();
+        encountered.insert(
+            "Goblin".to_string(),
+            EncyclopediaEntry {
+                kind: EntryKind::Creature,
+                glyph: rltk::to_cp437('g'),
+                fg: RGB::named(rltk::RED),
+                first_seen_depth: 2,
+                facts: vec!["Attack 3, block 1".to_string()],
+            },
+        );
+
+        ecs.create_entity()
+            .with(Position { x: 1, y: 2 })
+            .with(Renderable {
+                glyph: rltk::to_cp437('@'),
+                fg: RGB::named(rltk::YELLOW),
+                bg: RGB::named(rltk::BLACK),
+                render_order: 1,
+            })
+            .with(Player {})
+            .with(Viewshed {
+                visible_tiles: vec![Point::new(1, 1), Point::new(2, 1)],
+                range: 8,
+                dirty: false,
+            })
+            .with(Monster {})
+            .with(Name { name: "Player".to_string() })
+            .with(BlocksTile {})
+            .with(CombatStats { max_hp: 30, hp: 21, block: 2, attack_power: 5 })
+            .with(SufferDamage { amount: vec![4, 2], source: "Orc".to_string(), by_player: false })
+            .with(WantsToMelee { target: other })
+            .with(Item {})
+            .with(Consumable {})
+            .with(Ranged { range: 6 })
+            .with(InflictsDamage { damage: 8 })
+            .with(AreaOfEffect { radius: 3 })
+            .with(Confusion { turns: 4 })
+            .with(ProvidesHealing { heal_amount: 7 })
+            .with(InBackpack { owner: other })
+            .with(WantsToPickupItem { collected_by: other, item: other })
+            .with(WantsToUseItem { item: other, target: Some(Point::new(2, 1)) })
+            .with(WantsToDropItem { item: other })
+            .with(Equippable { slot: EquipmentSlot::Shield })
+            .with(Equipped { owner: other, slot: EquipmentSlot::Melee })
+            .with(MeleePowerBonus { power: 2 })
+            .with(DefenseBonus { defense: 1 })
+            .with(WantsToRemoveItem { item: other })
+            .with(MagicMapper {})
+            .with(Hidden {})
+            .with(EntryTrigger {})
+            .with(EntityMoved {})
+            .with(SingleActivation {})
+            .with(ProvidesFood {})
+            .with(Door { open: true })
+            .with(BlocksVisibility {})
+            .with(Locked {})
+            .with(Key {})
+            .with(CannotOpenDoors {})
+            .with(WantsToOpenDoor { door: other })
+            .with(WantsToCloseDoor { door: other })
+            .with(Bystander {})
+            .with(Vendor {})
+            .with(RunStats {
+                kills,
+                turns: 120,
+                deepest_depth: 3,
+                cause_of_death: Some("Orc".to_string()),
+                encountered,
+            })
+            .with(OtherLevelPosition { x: 3, y: 1, depth: 0 })
+            .marked::<SimpleMarker<Savable>>()
+            .build();
+
+        ecs
+    }
+
+    fn assert_round_trip(encoding: SaveEncoding, compress: bool) {
+        let mut original = world_with_every_component();
+        let options = SaveOptions { encoding, compress, keep_backup: false };
+        let data = serialize_world(&mut original, options).expect("unable to save");
+
+        let mut restored = scratch_world();
+        restore_world(&mut restored, &data).expect("unable to restore");
+
+        let before = saved_components(&original);
+        let after = saved_components(&restored);
+        for ((name, saved), (_name, loaded)) in before.iter().zip(after.iter()) {
+            // The map travels in its own helper entity, which is gone again on both sides.
+            if *name == "SerializationHelper" {
+                continue;
+            }
+            assert!(
+                saved.as_array().is_some_and(|list| !list.is_empty()),
+                "{} is missing from the test entity",
+                name
+            );
+            assert_eq!(saved, loaded, "{} changed in a {:?} round trip", name, encoding);
+        }
+
+        assert_restored_fields(&restored);
+
+        let map_value = |ecs: &World| serde_json::to_value(&*ecs.fetch::<Map>()).unwrap();
+        assert_eq!(map_value(&original), map_value(&restored));
+        let town_value = |ecs: &World| serde_json::to_value(&ecs.fetch::<StoredTown>().map).unwrap();
+        assert_eq!(town_value(&original), town_value(&restored));
+        assert_eq!(original.fetch::<RunInfo>().run_id, restored.fetch::<RunInfo>().run_id);
+    }
+
+    fn component<T: Component + Clone>(ecs: &World, entity: Entity) -> T {
+        ecs.read_storage::<T>().get(entity).cloned().expect("component was not restored")
+    }
+
+    /// Checks the restored entity field by field, as comparing serialized values alone would not
+    /// notice a field that is never written.
+    fn assert_restored_fields(ecs: &World) {
+        let names = ecs.read_storage::<Name>();
+        let (player, _p) = (&ecs.entities(), &ecs.read_storage::<Player>()).join().next().unwrap();
+        let (other, _n) = (&ecs.entities(), &names)
+            .join()
+            .find(|(_e, name)| name.name == "Orc")
+            .unwrap();
+
+        let pos: Position = component(ecs, player);
+        assert_eq!((pos.x, pos.y), (1, 2));
+        let render: Renderable = component(ecs, player);
+        assert_eq!((render.glyph, render.render_order), (rltk::to_cp437('@'), 1));
+        assert_eq!((render.fg, render.bg), (RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK)));
+        let viewshed: Viewshed = component(ecs, player);
+        assert_eq!(viewshed.visible_tiles, vec![Point::new(1, 1), Point::new(2, 1)]);
+        assert_eq!((viewshed.range, viewshed.dirty), (8, false));
+        assert_eq!(names.get(player).unwrap().name, "Player");
+        let stats: CombatStats = component(ecs, player);
+        assert_eq!((stats.max_hp, stats.hp, stats.block, stats.attack_power), (30, 21, 2, 5));
+        let damage: SufferDamage = component(ecs, player);
+        assert_eq!(damage.amount, vec![4, 2]);
+        assert_eq!((damage.source.as_str(), damage.by_player), ("Orc", false));
+        let melee: WantsToMelee = component(ecs, player);
+        assert_eq!(melee.target, other);
+        let ranged: Ranged = component(ecs, player);
+        assert_eq!(ranged.range, 6);
+        let inflicts: InflictsDamage = component(ecs, player);
+        assert_eq!(inflicts.damage, 8);
+        let aoe: AreaOfEffect = component(ecs, player);
+        assert_eq!(aoe.radius, 3);
+        let confusion: Confusion = component(ecs, player);
+        assert_eq!(confusion.turns, 4);
+        let healing: ProvidesHealing = component(ecs, player);
+        assert_eq!(healing.heal_amount, 7);
+        assert_eq!(ecs.read_storage::<InBackpack>().get(player).unwrap().owner, other);
+        let pickup = ecs.read_storage::<WantsToPickupItem>();
+        let pickup = pickup.get(player).unwrap();
+        assert_eq!((pickup.collected_by, pickup.item), (other, other));
+        let use_item = ecs.read_storage::<WantsToUseItem>();
+        let use_item = use_item.get(player).unwrap();
+        assert_eq!((use_item.item, use_item.target), (other, Some(Point::new(2, 1))));
+        assert_eq!(ecs.read_storage::<WantsToDropItem>().get(player).unwrap().item, other);
+        let equippable: Equippable = component(ecs, player);
+        assert_eq!(equippable.slot, EquipmentSlot::Shield);
+        let equipped: Equipped = component(ecs, player);
+        assert_eq!((equipped.owner, equipped.slot), (other, EquipmentSlot::Melee));
+        let power: MeleePowerBonus = component(ecs, player);
+        assert_eq!(power.power, 2);
+        let defense: DefenseBonus = component(ecs, player);
+        assert_eq!(defense.defense, 1);
+        assert_eq!(ecs.read_storage::<WantsToRemoveItem>().get(player).unwrap().item, other);
+        let door: Door = component(ecs, player);
+        assert!(door.open);
+        assert_eq!(ecs.read_storage::<WantsToOpenDoor>().get(player).unwrap().door, other);
+        assert_eq!(ecs.read_storage::<WantsToCloseDoor>().get(player).unwrap().door, other);
+        let run: RunStats = component(ecs, player);
+        assert_eq!((run.turns, run.deepest_depth), (120, 3));
+        assert_eq!(run.kills.get("Goblin"), Some(&3));
+        assert_eq!(run.cause_of_death.as_deref(), Some("Orc"));
+        let entry = run.encountered.get("Goblin").unwrap();
+        assert_eq!((entry.kind, entry.glyph), (EntryKind::Creature, rltk::to_cp437('g')));
+        assert_eq!((entry.fg, entry.first_seen_depth), (RGB::named(rltk::RED), 2));
+        assert_eq!(entry.facts, vec!["Attack 3, block 1".to_string()]);
+        let parked: OtherLevelPosition = component(ecs, player);
+        assert_eq!((parked.x, parked.y, parked.depth), (3, 1, 0));
+    }
+
+    /// A save from before format 1: no header, no components after SingleActivation, and no
+    /// damage source or map theme.
+    #[test]
+    fn format_0_save_is_migrated() {
+        let mut ecs = scratch_world();
+        let data = include_bytes!("../tests/fixtures/format0_save.json");
+        restore_world(&mut ecs, data).expect("unable to restore a format 0 save");
+
+        let player = *ecs.fetch::<Entity>();
+        let pos: Position = component(&ecs, player);
+        assert_eq!((pos.x, pos.y), (1, 1));
+        let stats: CombatStats = component(&ecs, player);
+        assert_eq!((stats.hp, stats.max_hp), (25, 30));
+        let damage: SufferDamage = component(&ecs, player);
+        assert_eq!((damage.amount, damage.source.as_str(), damage.by_player), (vec![3], "", false));
+
+        let names = ecs.read_storage::<Name>();
+        let backpack = ecs.read_storage::<InBackpack>();
+        let carried: Vec<&str> = (&names, &backpack)
+            .join()
+            .filter(|(_name, pack)| pack.owner == player)
+            .map(|(name, _pack)| name.name.as_str())
+            .collect();
+        assert_eq!(carried, vec!["Health Potion"]);
+
+        let map = ecs.fetch::<Map>();
+        assert_eq!((map.width, map.height, map.depth), (4, 3, 1));
+        assert_eq!(map.tiles[6], TileType::StairsDown);
+        assert_eq!(map.theme.name, Theme::default().name);
+        assert!(ecs.fetch::<StoredTown>().map.is_none());
+        assert!(!ecs.fetch::<RunInfo>().hardcore);
+    }
+
+    #[test]
+    fn json_round_trip_keeps_every_saved_component() {
+        assert_round_trip(SaveEncoding::Json, false);
+    }
+
+    #[test]
+    fn message_pack_round_trip_keeps_every_saved_component() {
+        assert_round_trip(SaveEncoding::MessagePack, true);
+    }
+}
@@ -0,0 +1,382 @@
+use crate::keybindings::config_dir;
+use crate::tileset::Tileset;
+use rltk::RGB;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+const SETTINGS_FILE: &str = "settings.json";
+const SETTINGS_VERSION: u32 = 1;
+
+pub const MAPGEN_STEP_CHOICES: [f32; 4] = [75.0, 150.0, 300.0, 600.0];
+pub const AUTOSAVE_CHOICES: [i32; 5] = [0, 50, 100, 250, 500];
+pub const WINDOW_SCALE_CHOICES: [u32; 3] = [1, 2, 3];
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Font {
+    Terminal8x8,
+    Vga8x16,
+}
+
+impl Font {
+    pub const ALL: [Font; 2] = [Font::Terminal8x8, Font::Vga8x16];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Font::Terminal8x8 => "8x8 terminal",
+            Font::Vga8x16 => "8x16 VGA",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug)]
+pub enum LogLevel {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 3] = [LogLevel::Quiet, LogLevel::Normal, LogLevel::Verbose];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LogLevel::Quiet => "errors only",
+            LogLevel::Normal => "normal",
+            LogLevel::Verbose => "verbose",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Palette {
+    Standard,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 4] = [
+        Palette::Standard,
+        Palette::Protanopia,
+        Palette::Deuteranopia,
+        Palette::Tritanopia,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Palette::Standard => "standard",
+            Palette::Protanopia => "protanopia",
+            Palette::Deuteranopia => "deuteranopia",
+            Palette::Tritanopia => "tritanopia",
+        }
+    }
+
+    /// Daltonizes a colour: works out what is lost when it is seen with this colour deficiency
+    /// and shifts that difference into the channels that are still distinguishable.
+    pub fn adjust(self, color: RGB) -> RGB {
+        if self == Palette::Standard {
+            return color;
+        }
+
+        let l = 17.8824 * color.r + 43.5161 * color.g + 4.11935 * color.b;
+        let m = 3.45565 * color.r + 27.1554 * color.g + 3.86714 * color.b;
+        let s = 0.0299566 * color.r + 0.184309 * color.g + 1.46709 * color.b;
+
+        let (l, m, s) = match self {
+            Palette::Protanopia => (2.02344 * m - 2.52581 * s, m, s),
+            Palette::Deuteranopia => (l, 0.494207 * l + 1.24827 * s, s),
+            _ => (l, m, -0.395913 * l + 0.801109 * m),
+        };
+
+        let seen_r = 0.080_944_45 * l - 0.130_504_41 * m + 0.116_721_07 * s;
+        let seen_g = -0.010_248_534 * l + 0.054_019_33 * m - 0.113_614_71 * s;
+        let seen_b = -0.000_365_296_94 * l - 0.004_121_615 * m + 0.693_511_4 * s;
+
+        let (err_r, err_g, err_b) = (color.r - seen_r, color.g - seen_g, color.b - seen_b);
+        RGB::from_f32(
+            color.r,
+            (color.g + 0.7 * err_r + err_g).clamp(0.0, 1.0),
+            (color.b + 0.7 * err_r + err_b).clamp(0.0, 1.0),
+        )
+    }
+}
+
+/// Player preferences, kept in `settings.json` next to the key bindings. Fields missing from an
+/// older file take their defaults.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    pub show_mapgen_visualizer: bool,
+    /// How long each map generation snapshot stays on screen.
+    pub mapgen_step_ms: f32,
+    /// Only read when the window is created, so changes apply after a restart.
+    pub font: Font,
+    pub window_scale: u32,
+    pub log_level: LogLevel,
+    /// Turns between autosaves; 0 turns periodic autosaving off. Level changes always autosave.
+    pub autosave_interval: i32,
+    pub palette: Palette,
+    /// Shows a small map of the whole level in the corner of the map view.
+    pub show_minimap: bool,
+    /// Draws the map with the tileset sprites instead of ASCII.
+    pub use_tileset: bool,
+    /// Debug switch for building every level with the fixed builder. Not shown in the options
+    /// screen.
+    pub random_maps: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            version: SETTINGS_VERSION,
+            show_mapgen_visualizer: true,
+            mapgen_step_ms: 300.0,
+            font: Font::Terminal8x8,
+            window_scale: 1,
+            log_level: LogLevel::Verbose,
+            autosave_interval: 100,
+            palette: Palette::Standard,
+            show_minimap: false,
+            use_tileset: false,
+            random_maps: true,
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings file, writing one with the defaults if there isn't one yet.
+    pub fn load() -> Settings {
+        match load_versioned_json(&config_dir().join(SETTINGS_FILE), SETTINGS_VERSION) {
+            Ok(Some(settings)) => settings,
+            Ok(None) => {
+                let defaults = Settings::default();
+                defaults.save();
+                defaults
+            }
+            Err(e) => {
+                rltk::console::log(format!("Unable to read settings: {}", e));
+                Settings::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let data = match serde_json::to_string_pretty(self) {
+            Ok(data) => data,
+            Err(e) => {
+                rltk::console::log(format!("Unable to serialize settings: {}", e));
+                return;
+            }
+        };
+
+        let result = fs::create_dir_all(config_dir())
+            .and_then(|_| fs::write(config_dir().join(SETTINGS_FILE), data));
+        if let Err(e) = result {
+            rltk::console::log(format!("Unable to write settings: {}", e));
+        }
+    }
+
+    /// The window builder for the chosen font and scale, with sprite layers if there is a
+    /// tileset.
+    pub fn window_builder(&self, tileset: Option<&Tileset>) -> rltk::RltkBuilder {
+        let scale = self.window_scale.max(1);
+        if let Some(tileset) = tileset {
+            return match self.font {
+                Font::Terminal8x8 => tileset.window_builder("terminal8x8.png", 8, 8, scale),
+                Font::Vga8x16 => tileset.window_builder("vga8x16.png", 8, 16, scale),
+            };
+        }
+
+        match self.font {
+            Font::Terminal8x8 => {
+                rltk::RltkBuilder::simple80x50().with_tile_dimensions(8 * scale, 8 * scale)
+            }
+            Font::Vga8x16 => {
+                rltk::RltkBuilder::vga80x50().with_tile_dimensions(8 * scale, 16 * scale)
+            }
+        }
+    }
+
+    /// Publishes the settings that code without access to `State` reads: the map builders,
+    /// the renderer and logging.
+    pub fn apply(&self) {
+        SHOW_MAPGEN_VISUALIZER.store(self.show_mapgen_visualizer, Ordering::Relaxed);
+        LOG_LEVEL.store(self.log_level as u8, Ordering::Relaxed);
+        PALETTE.store(self.palette as u8, Ordering::Relaxed);
+    }
+}
+
+static SHOW_MAPGEN_VISUALIZER: AtomicBool = AtomicBool::new(true);
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Verbose as u8);
+static PALETTE: AtomicU8 = AtomicU8::new(Palette::Standard as u8);
+
+pub fn show_mapgen_visualizer() -> bool {
+    SHOW_MAPGEN_VISUALIZER.load(Ordering::Relaxed)
+}
+
+pub fn palette() -> Palette {
+    Palette::ALL[PALETTE.load(Ordering::Relaxed) as usize]
+}
+
+/// Writes to the console if the player asked for messages at `level`. Errors should keep using
+/// `rltk::console::log` directly so they are never hidden.
+pub fn log<S: ToString>(level: LogLevel, message: S) {
+    if level as u8 <= LOG_LEVEL.load(Ordering::Relaxed) {
+        rltk::console::log(message);
+    }
+}
+
+/// Reads a JSON file with a top-level `version`. `Ok(None)` means there is no file; one that
+/// can't be read, doesn't parse or has a different version is an error saying why.
+pub fn load_versioned_json<T: DeserializeOwned>(path: &Path, version: u32) -> Result<Option<T>, String> {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let value: serde_json::Value = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    match value.get("version").and_then(serde_json::Value::as_u64) {
+        Some(found) if found == version as u64 => {}
+        Some(found) => return Err(format!("unsupported version {}", found)),
+        None => return Err("no version".to_string()),
+    }
+    serde_json::from_value(value).map(Some).map_err(|e| e.to_string())
+}
+
+/// The entry after (or before) `current` in `choices`, wrapping around. Values that aren't in
+/// the list, e.g. from a hand-edited file, move to the first choice.
+pub fn cycle<T: PartialEq + Copy>(choices: &[T], current: T, forward: bool) -> T {
+    match choices.iter().position(|choice| *choice == current) {
+        Some(i) if forward => choices[(i + 1) % choices.len()],
+        Some(i) => choices[(i + choices.len() - 1) % choices.len()],
+        None => choices[0],
+    }
+}
+
+/// The rows of the options screen.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum OptionField {
+    Visualizer,
+    VisualizerSpeed,
+    Font,
+    WindowScale,
+    LogLevel,
+    AutosaveInterval,
+    Palette,
+    Minimap,
+    Graphics,
+}
+
+impl OptionField {
+    pub const ALL: [OptionField; 9] = [
+        OptionField::Visualizer,
+        OptionField::VisualizerSpeed,
+        OptionField::Font,
+        OptionField::WindowScale,
+        OptionField::LogLevel,
+        OptionField::AutosaveInterval,
+        OptionField::Palette,
+        OptionField::Minimap,
+        OptionField::Graphics,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            OptionField::Visualizer => "Map generation visualizer",
+            OptionField::VisualizerSpeed => "Visualizer step",
+            OptionField::Font => "Font *",
+            OptionField::WindowScale => "Window scale *",
+            OptionField::LogLevel => "Console log",
+            OptionField::AutosaveInterval => "Autosave",
+            OptionField::Palette => "Colour palette",
+            OptionField::Minimap => "Corner minimap",
+            OptionField::Graphics => "Map graphics",
+        }
+    }
+
+    pub fn value(self, settings: &Settings) -> String {
+        match self {
+            OptionField::Visualizer => {
+                if settings.show_mapgen_visualizer { "on" } else { "off" }.to_string()
+            }
+            OptionField::VisualizerSpeed => format!("{} ms", settings.mapgen_step_ms),
+            OptionField::Font => settings.font.label().to_string(),
+            OptionField::WindowScale => format!("{}x", settings.window_scale),
+            OptionField::LogLevel => settings.log_level.label().to_string(),
+            OptionField::AutosaveInterval => match settings.autosave_interval {
+                0 => "level changes only".to_string(),
+                turns => format!("every {} turns", turns),
+            },
+            OptionField::Palette => settings.palette.label().to_string(),
+            OptionField::Minimap => if settings.show_minimap { "on" } else { "off" }.to_string(),
+            OptionField::Graphics => if settings.use_tileset { "tiles" } else { "ASCII" }.to_string(),
+        }
+    }
+
+    /// Moves the field to its next (or previous) value.
+    pub fn change(self, settings: &mut Settings, forward: bool) {
+        match self {
+            OptionField::Visualizer => {
+                settings.show_mapgen_visualizer = !settings.show_mapgen_visualizer
+            }
+            OptionField::VisualizerSpeed => {
+                settings.mapgen_step_ms =
+                    cycle(&MAPGEN_STEP_CHOICES, settings.mapgen_step_ms, forward)
+            }
+            OptionField::Font => settings.font = cycle(&Font::ALL, settings.font, forward),
+            OptionField::WindowScale => {
+                settings.window_scale =
+                    cycle(&WINDOW_SCALE_CHOICES, settings.window_scale, forward)
+            }
+            OptionField::LogLevel => {
+                settings.log_level = cycle(&LogLevel::ALL, settings.log_level, forward)
+            }
+            OptionField::AutosaveInterval => {
+                settings.autosave_interval =
+                    cycle(&AUTOSAVE_CHOICES, settings.autosave_interval, forward)
+            }
+            OptionField::Palette => {
+                settings.palette = cycle(&Palette::ALL, settings.palette, forward)
+            }
+            OptionField::Minimap => settings.show_minimap = !settings.show_minimap,
+            OptionField::Graphics => settings.use_tileset = !settings.use_tileset,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Versioned {
+        version: u32,
+        value: i32,
+    }
+
+    #[test]
+    fn versioned_json_is_only_loaded_at_its_version() {
+        let path = std::env::temp_dir().join(format!("deathlike-versioned-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        assert!(matches!(load_versioned_json::<Versioned>(&path, 2), Ok(None)));
+
+        fs::write(&path, r#"{"version": 2, "value": 7}"#).unwrap();
+        let loaded = load_versioned_json::<Versioned>(&path, 2).unwrap().unwrap();
+        assert_eq!((loaded.version, loaded.value), (2, 7));
+
+        fs::write(&path, r#"{"version": 3, "value": "newer"}"#).unwrap();
+        let newer = load_versioned_json::<Versioned>(&path, 2);
+        assert_eq!(newer.err().as_deref(), Some("unsupported version 3"));
+
+        fs::write(&path, "garbage").unwrap();
+        assert!(load_versioned_json::<Versioned>(&path, 2).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,8 +1,8 @@
-use crate::{AreaOfEffect, BlocksTile, CombatStats, Confusion, Consumable, DefenseBonus, EntryTrigger, EquipmentSlot, Equippable, Hidden, InflictsDamage, Item, MagicMapper, MeleePowerBonus, Monster, Name, Player, Position, ProvidesHealing, RandomTable, Ranged, Rect, Renderable, Savable, SingleActivation, Viewshed, MAP_WIDTH, Map, TileType};
+use crate::{AreaOfEffect, BlocksTile, BlocksVisibility, Bystander, CannotOpenDoors, CombatStats, Confusion, Consumable, DefenseBonus, Door, EntryTrigger, EquipmentSlot, Equippable, Equipped, Hidden, InBackpack, InflictsDamage, Item, Key, Locked, MagicMapper, MeleePowerBonus, Monster, Name, Player, Position, ProvidesFood, ProvidesHealing, RandomTable, Ranged, Rect, Renderable, Rollable, RunStats, Savable, SingleActivation, Theme, Vendor, Viewshed, Map};
 use rltk::{RandomNumberGenerator, RGB};
 use specs::prelude::*;
 use specs::saveload::{MarkedBuilder, SimpleMarker};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 const MAX_MONSTERS: i32 = 4;
 
@@ -33,6 +33,7 @@ pub fn player(ecs: &mut World, player_x: i32, player_y: i32) -> Entity {
             block: 2,
             attack_power: 5,
         })
+        .with(RunStats::default())
         .marked::<SimpleMarker<Savable>>()
         .build()
 }
@@ -48,7 +49,7 @@ fn determine_possible_targets(ecs: &mut World, room: &Rect, possible_targets: &m
     for y in room.y1 + 1..room.y2 {
         for x in room.x1 + 1..room.x2 {
             let i = map.xy_idx(x, y);
-            if map.tiles[i] == TileType::Floor {
+            if map.tiles[i].allows_spawns() {
                 possible_targets.push(i);
             }
         }
@@ -56,8 +57,8 @@ fn determine_possible_targets(ecs: &mut World, room: &Rect, possible_targets: &m
 }
 
 pub fn spawn_region(ecs: &mut World, area: &[usize], map_depth: i32) {
-    let spawn_table = room_table(map_depth);
-    let mut spawn_points: HashMap<usize, String> = HashMap::new();
+    let spawn_table = room_table(map_depth, &ecs.fetch::<Map>().theme);
+    let mut spawn_points: BTreeMap<usize, String> = BTreeMap::new();
     let areas: Vec<usize> = Vec::from(area);
 
     add_area_spawns(ecs, spawn_table, &mut spawn_points, areas);
@@ -67,7 +68,7 @@ pub fn spawn_region(ecs: &mut World, area: &[usize], map_depth: i32) {
     }
 }
 
-fn add_area_spawns(ecs: &mut World, spawn_table: RandomTable, spawn_points: &mut HashMap<usize, String>, mut areas: Vec<usize>) {
+fn add_area_spawns(ecs: &mut World, spawn_table: RandomTable, spawn_points: &mut BTreeMap<usize, String>, mut areas: Vec<usize>) {
     let mut rng = ecs.write_resource::<RandomNumberGenerator>();
     let num_spawns = i32::min(areas.len() as i32, rng.roll_dice(1, MAX_MONSTERS + 3));
     if num_spawns == 0 { return; }
@@ -80,54 +81,125 @@ fn add_area_spawns(ecs: &mut World, spawn_table: RandomTable, spawn_points: &mut
     }
 }
 
-pub fn spawn_entity(ecs: &mut World, spawn: &(&usize, &String)) {
+pub fn spawn_entity(ecs: &mut World, spawn: &(&usize, &String)) -> Option<Entity> {
     let (location, entity_name) = *spawn;
 
-    let x = (location % MAP_WIDTH) as i32;
-    let y = (location / MAP_WIDTH) as i32;
-
-    match entity_name.as_ref() {
-        "Goblin" => goblin(ecs, x, y),
-        "Orc" => orc(ecs, x, y),
-        "Health Potion" => health_potion(ecs, x, y),
-        "Fireball Scroll" => fireball_scroll(ecs, x, y),
-        "Confusion Scroll" => confusion_scroll(ecs, x, y),
-        "Magic Missile Scroll" => magic_missile_scroll(ecs, x, y),
-        "Shield" => shield(ecs, x, y),
-        "Dagger" => dagger(ecs, x, y),
-        "Longsword" => longsword(ecs, x, y),
-        "Tower Shield" => tower_shield(ecs, x, y),
-        "Magic Mapping Scroll" => magic_mapper(ecs, x, y),
-        "Bear Trap" => bear_trap(ecs, x, y),
-        _ => {}
+    let width = ecs.fetch::<Map>().width as usize;
+    let x = (location % width) as i32;
+    let y = (location / width) as i32;
+
+    spawn_named_entity(ecs, entity_name, x, y)
+}
+
+fn spawn_named_entity(ecs: &mut World, entity_name: &str, x: i32, y: i32) -> Option<Entity> {
+    match entity_name {
+        "Goblin" => Some(goblin(ecs, x, y)),
+        "Orc" => Some(orc(ecs, x, y)),
+        "Health Potion" => Some(health_potion(ecs, x, y)),
+        "Fireball Scroll" => Some(fireball_scroll(ecs, x, y)),
+        "Confusion Scroll" => Some(confusion_scroll(ecs, x, y)),
+        "Magic Missile Scroll" => Some(magic_missile_scroll(ecs, x, y)),
+        "Shield" => Some(shield(ecs, x, y)),
+        "Dagger" => Some(dagger(ecs, x, y)),
+        "Longsword" => Some(longsword(ecs, x, y)),
+        "Tower Shield" => Some(tower_shield(ecs, x, y)),
+        "Magic Mapping Scroll" => Some(magic_mapper(ecs, x, y)),
+        "Bear Trap" => Some(bear_trap(ecs, x, y)),
+        "Townsperson" => Some(townsperson(ecs, x, y)),
+        "Guard" => Some(guard(ecs, x, y)),
+        "Barkeep" => Some(vendor(ecs, x, y, "Barkeep")),
+        "Merchant" => Some(vendor(ecs, x, y, "Merchant")),
+        _ => None,
     }
 }
 
-fn room_table(map_depth: i32) -> RandomTable {
+fn room_table(map_depth: i32, theme: &Theme) -> RandomTable {
     RandomTable::new()
-        .add("Goblin", 10)
-        .add("Orc", 1 + map_depth)
-        .add("Health Potion", 7)
-        .add("Fireball Scroll", 2 + map_depth)
-        .add("Confusion Scroll", 2 + map_depth)
-        .add("Magic Missile Scroll", 4)
-        .add("Shield", 3)
+        .add("Goblin", theme.spawn_weight("Goblin", 10))
+        .add("Orc", theme.spawn_weight("Orc", 1 + map_depth))
+        .add("Health Potion", theme.spawn_weight("Health Potion", 7))
+        .add("Fireball Scroll", theme.spawn_weight("Fireball Scroll", 2 + map_depth))
+        .add("Confusion Scroll", theme.spawn_weight("Confusion Scroll", 2 + map_depth))
+        .add("Magic Missile Scroll", theme.spawn_weight("Magic Missile Scroll", 4))
+        .add("Shield", theme.spawn_weight("Shield", 3))
+        .add("Dagger", theme.spawn_weight("Dagger", 3))
+        .add("Longsword", theme.spawn_weight("Longsword", map_depth - 1))
+        .add("Tower Shield", theme.spawn_weight("Tower Shield", map_depth - 1))
+        .add("Magic Mapping Scroll", theme.spawn_weight("Magic Mapping Scroll", 2))
+        .add("Bear Trap", theme.spawn_weight("Bear Trap", 2))
+}
+
+fn monster_loot_table(map_depth: i32) -> RandomTable {
+    RandomTable::new()
+        .add("None", 12)
+        .add("Health Potion", 4)
+        .add("Magic Missile Scroll", 2)
+        .add("Confusion Scroll", 1 + map_depth / 2)
         .add("Dagger", 3)
-        .add("Longsword", map_depth - 1)
-        .add("Tower Shield", map_depth - 1)
-        .add("Magic Mapping Scroll", 2)
-        .add("Bear Trap", 2)
+        .add("Shield", 2)
+        .add("Longsword", map_depth - 2)
+        .add("Tower Shield", map_depth - 2)
+}
+
+fn orc(ecs: &mut World, x: i32, y: i32) -> Entity {
+    let orc = monster(ecs, x, y, rltk::to_cp437('o'), "Orc");
+    give_monster_loot(ecs, orc, 2);
+    orc
+}
+
+fn goblin(ecs: &mut World, x: i32, y: i32) -> Entity {
+    let goblin = monster(ecs, x, y, rltk::to_cp437('g'), "Goblin");
+    ecs.write_storage::<CannotOpenDoors>()
+        .insert(goblin, CannotOpenDoors {})
+        .expect("unable to stop goblin opening doors");
+    give_monster_loot(ecs, goblin, 1);
+    goblin
 }
 
-fn orc(ecs: &mut World, x: i32, y: i32) {
-    monster(ecs, x, y, rltk::to_cp437('o'), "Orc");
+fn give_monster_loot(ecs: &mut World, owner: Entity, max_items: i32) {
+    let map_depth = ecs.fetch::<Map>().depth;
+    let loot_table = monster_loot_table(map_depth);
+
+    for _i in 0..max_items {
+        let loot_name = {
+            let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+            loot_table.roll(&mut rng)
+        };
+
+        if let Some(item) = spawn_named_entity(ecs, &loot_name, 0, 0) {
+            carry_item(ecs, owner, item);
+        }
+    }
 }
 
-fn goblin(ecs: &mut World, x: i32, y: i32) {
-    monster(ecs, x, y, rltk::to_cp437('g'), "Goblin");
+fn carry_item(ecs: &mut World, owner: Entity, item: Entity) {
+    ecs.write_storage::<Position>().remove(item);
+
+    let free_slot = ecs
+        .read_storage::<Equippable>()
+        .get(item)
+        .map(|e| e.slot)
+        .filter(|slot| {
+            !(&ecs.read_storage::<Equipped>())
+                .join()
+                .any(|e| e.owner == owner && e.slot == *slot)
+        });
+
+    match free_slot {
+        Some(slot) => {
+            ecs.write_storage::<Equipped>()
+                .insert(item, Equipped { owner, slot })
+                .expect("unable to equip monster loot");
+        }
+        None => {
+            ecs.write_storage::<InBackpack>()
+                .insert(item, InBackpack { owner })
+                .expect("unable to store monster loot");
+        }
+    }
 }
 
-fn monster<S: ToString>(ecs: &mut World, x: i32, y: i32, glyph: rltk::FontCharType, name: S) {
+fn monster<S: ToString>(ecs: &mut World, x: i32, y: i32, glyph: rltk::FontCharType, name: S) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
@@ -153,10 +225,134 @@ fn monster<S: ToString>(ecs: &mut World, x: i32, y: i32, glyph: rltk::FontCharTy
             attack_power: 4,
         })
         .marked::<SimpleMarker<Savable>>()
-        .build();
+        .build()
+}
+
+fn townsperson(ecs: &mut World, x: i32, y: i32) -> Entity {
+    bystander(ecs, x, y, rltk::to_cp437('t'), RGB::named(rltk::LIGHT_GREEN), "Townsperson")
+}
+
+fn guard(ecs: &mut World, x: i32, y: i32) -> Entity {
+    bystander(ecs, x, y, rltk::to_cp437('G'), RGB::named(rltk::STEEL_BLUE), "Guard")
 }
 
-fn health_potion(ecs: &mut World, x: i32, y: i32) {
+fn bystander<S: ToString>(ecs: &mut World, x: i32, y: i32, glyph: rltk::FontCharType, fg: RGB, name: S) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph,
+            fg,
+            bg: RGB::named(rltk::BLACK),
+            render_order: 1,
+        })
+        .with(Name {
+            name: name.to_string(),
+        })
+        .with(BlocksTile {})
+        .with(Bystander {})
+        .marked::<SimpleMarker<Savable>>()
+        .build()
+}
+
+fn vendor<S: ToString>(ecs: &mut World, x: i32, y: i32, name: S) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437('@'),
+            fg: RGB::named(rltk::GOLD),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 1,
+        })
+        .with(Name {
+            name: name.to_string(),
+        })
+        .with(BlocksTile {})
+        .with(Vendor {})
+        .marked::<SimpleMarker<Savable>>()
+        .build()
+}
+
+pub fn corpse<S: ToString>(ecs: &mut World, x: i32, y: i32, name: S) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437('%'),
+            fg: RGB::named(rltk::DARK_RED),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 3,
+        })
+        .with(Name {
+            name: format!("{} corpse", name.to_string()),
+        })
+        .with(Item {})
+        .with(ProvidesFood {})
+        .marked::<SimpleMarker<Savable>>()
+        .build()
+}
+
+pub fn spawn_doors(ecs: &mut World, doorways: &[usize], key_room: &Rect) {
+    let mut key_targets: Vec<usize> = Vec::new();
+    determine_possible_targets(ecs, key_room, &mut key_targets);
+
+    let width = ecs.fetch::<Map>().width as usize;
+    for doorway in doorways.iter() {
+        let x = (doorway % width) as i32;
+        let y = (doorway / width) as i32;
+
+        let locked = !key_targets.is_empty() && ecs.roll(1, 8) == 1;
+        door(ecs, x, y, locked);
+
+        if locked {
+            let key_index = (ecs.roll(1, key_targets.len() as i32) - 1) as usize;
+            let key_location = key_targets[key_index];
+            key(ecs, (key_location % width) as i32, (key_location / width) as i32);
+        }
+    }
+}
+
+pub fn door(ecs: &mut World, x: i32, y: i32, locked: bool) -> Entity {
+    let mut builder = ecs
+        .create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437('+'),
+            fg: RGB::named(rltk::CHOCOLATE),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: if locked { "Locked Door" } else { "Door" }.to_string(),
+        })
+        .with(Door { open: false })
+        .with(BlocksTile {})
+        .with(BlocksVisibility {});
+
+    if locked {
+        builder = builder.with(Locked {});
+    }
+
+    builder.marked::<SimpleMarker<Savable>>().build()
+}
+
+fn key(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437('-'),
+            fg: RGB::named(rltk::GOLD),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Iron Key".to_string(),
+        })
+        .with(Item {})
+        .with(Key {})
+        .marked::<SimpleMarker<Savable>>()
+        .build()
+}
+
+fn health_potion(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
@@ -172,10 +368,10 @@ fn health_potion(ecs: &mut World, x: i32, y: i32) {
         .with(Consumable {})
         .with(ProvidesHealing { heal_amount: 8 })
         .marked::<SimpleMarker<Savable>>()
-        .build();
+        .build()
 }
 
-fn magic_missile_scroll(ecs: &mut World, x: i32, y: i32) {
+fn magic_missile_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
@@ -192,10 +388,10 @@ fn magic_missile_scroll(ecs: &mut World, x: i32, y: i32) {
         .with(Ranged { range: 6 })
         .with(InflictsDamage { damage: 8 })
         .marked::<SimpleMarker<Savable>>()
-        .build();
+        .build()
 }
 
-fn fireball_scroll(ecs: &mut World, x: i32, y: i32) {
+fn fireball_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
@@ -213,10 +409,10 @@ fn fireball_scroll(ecs: &mut World, x: i32, y: i32) {
         .with(InflictsDamage { damage: 20 })
         .with(AreaOfEffect { radius: 3 })
         .marked::<SimpleMarker<Savable>>()
-        .build();
+        .build()
 }
 
-fn confusion_scroll(ecs: &mut World, x: i32, y: i32) {
+fn confusion_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
@@ -233,10 +429,10 @@ fn confusion_scroll(ecs: &mut World, x: i32, y: i32) {
         .with(Ranged { range: 6 })
         .with(Confusion { turns: 4 })
         .marked::<SimpleMarker<Savable>>()
-        .build();
+        .build()
 }
 
-fn dagger(ecs: &mut World, x: i32, y: i32) {
+fn dagger(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
@@ -254,10 +450,10 @@ fn dagger(ecs: &mut World, x: i32, y: i32) {
         })
         .with(MeleePowerBonus { power: 2 })
         .marked::<SimpleMarker<Savable>>()
-        .build();
+        .build()
 }
 
-fn shield(ecs: &mut World, x: i32, y: i32) {
+fn shield(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
@@ -277,10 +473,10 @@ fn shield(ecs: &mut World, x: i32, y: i32) {
         })
         .with(DefenseBonus { defense: 1 })
         .marked::<SimpleMarker<Savable>>()
-        .build();
+        .build()
 }
 
-fn longsword(ecs: &mut World, x: i32, y: i32) {
+fn longsword(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
@@ -298,10 +494,10 @@ fn longsword(ecs: &mut World, x: i32, y: i32) {
         })
         .with(MeleePowerBonus { power: 4 })
         .marked::<SimpleMarker<Savable>>()
-        .build();
+        .build()
 }
 
-fn tower_shield(ecs: &mut World, x: i32, y: i32) {
+fn tower_shield(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
@@ -319,10 +515,10 @@ fn tower_shield(ecs: &mut World, x: i32, y: i32) {
         })
         .with(DefenseBonus { defense: 3 })
         .marked::<SimpleMarker<Savable>>()
-        .build();
+        .build()
 }
 
-fn magic_mapper(ecs: &mut World, x: i32, y: i32) {
+fn magic_mapper(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
@@ -338,10 +534,10 @@ fn magic_mapper(ecs: &mut World, x: i32, y: i32) {
         .with(MagicMapper {})
         .with(Consumable {})
         .marked::<SimpleMarker<Savable>>()
-        .build();
+        .build()
 }
 
-fn bear_trap(ecs: &mut World, x: i32, y: i32) {
+fn bear_trap(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
@@ -358,5 +554,5 @@ fn bear_trap(ecs: &mut World, x: i32, y: i32) {
         .with(InflictsDamage { damage: 6 })
         .with(SingleActivation {})
         .marked::<SimpleMarker<Savable>>()
-        .build();
+        .build()
 }
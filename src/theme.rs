@@ -0,0 +1,89 @@
+use rltk::{RandomNumberGenerator, RGB};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+rltk::embedded_resource!(THEME_FILE, "resources/themes.json");
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TileStyle {
+    pub glyph: Option<char>,
+    pub fg: [f32; 3],
+}
+
+impl TileStyle {
+    pub fn color(&self) -> RGB {
+        RGB::from_f32(self.fg[0], self.fg[1], self.fg[2])
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Theme {
+    pub name: String,
+    pub min_depth: i32,
+    pub max_depth: i32,
+    pub wall: TileStyle,
+    pub floor: TileStyle,
+    pub builders: Vec<String>,
+    pub spawn_weights: HashMap<String, i32>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            name: "Dungeon".to_string(),
+            min_depth: 1,
+            max_depth: 100,
+            wall: TileStyle {
+                glyph: None,
+                fg: [0.0, 1.0, 0.0],
+            },
+            floor: TileStyle {
+                glyph: Some('.'),
+                fg: [0.5, 0.5, 0.5],
+            },
+            builders: Vec::new(),
+            spawn_weights: HashMap::new(),
+        }
+    }
+}
+
+impl Theme {
+    pub fn spawn_weight(&self, name: &str, base_weight: i32) -> i32 {
+        base_weight + self.spawn_weights.get(name).copied().unwrap_or(0)
+    }
+}
+
+pub struct ThemeLibrary {
+    pub themes: Vec<Theme>,
+}
+
+impl ThemeLibrary {
+    pub fn load() -> ThemeLibrary {
+        rltk::link_resource!(THEME_FILE, "resources/themes.json");
+
+        let raw_data = rltk::embedding::EMBED
+            .lock()
+            .get_resource("resources/themes.json".to_string())
+            .unwrap();
+        let raw_string = std::str::from_utf8(raw_data).expect("Unable to read theme file as UTF-8");
+
+        ThemeLibrary {
+            themes: serde_json::from_str(raw_string).expect("Unable to parse theme file"),
+        }
+    }
+
+    pub fn pick_for_depth(&self, depth: i32, rng: &mut RandomNumberGenerator) -> Theme {
+        let candidates: Vec<&Theme> = self
+            .themes
+            .iter()
+            .filter(|t| depth >= t.min_depth && depth <= t.max_depth)
+            .collect();
+
+        if candidates.is_empty() {
+            return Theme::default();
+        }
+
+        let index = (rng.roll_dice(1, candidates.len() as i32) - 1) as usize;
+        candidates[index].clone()
+    }
+}
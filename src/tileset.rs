@@ -0,0 +1,231 @@
+use crate::keybindings::config_dir;
+use crate::map::wall_mask;
+use crate::settings::load_versioned_json;
+use crate::{Map, TileType};
+use rltk::{Rltk, RGB};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+
+const TILESET_FILE: &str = "tileset.json";
+const TILESET_VERSION: u32 = 1;
+
+/// The tileset used when the config directory doesn't have one: 8x8 sprites for every tile type,
+/// all sixteen walls and a few monsters.
+const SAMPLE_TILESET: &str = include_str!("../resources/tileset.json");
+const SAMPLE_IMAGE: &[u8] = include_bytes!("../resources/tileset.png");
+
+/// The name the sprite sheet is registered under with the renderer.
+pub const SPRITE_FONT: &str = "deathlike-tileset.png";
+
+/// With a tileset loaded the window has three layers: map tile sprites, entity sprites over them,
+/// and a transparent text layer on top that holds the interface and anything without a sprite.
+pub const TILE_LAYER: usize = 0;
+pub const ENTITY_LAYER: usize = 1;
+pub const TEXT_LAYER: usize = 2;
+
+/// `tileset.json` in the config directory. Sprites are numbered left to right, top to bottom
+/// across the sheet, starting at 0.
+#[derive(Serialize, Deserialize)]
+struct TilesetFile {
+    version: u32,
+    /// The sprite sheet, relative to the config directory. PNG or JPEG.
+    image: String,
+    tile_width: u32,
+    tile_height: u32,
+    #[serde(default)]
+    tiles: HashMap<TileType, rltk::FontCharType>,
+    /// Sixteen wall sprites picked by which neighbours are also walls, using the same mask as the
+    /// ASCII walls: north 1, south 2, west 4, east 8. Walls use `tiles` when this is empty.
+    #[serde(default)]
+    walls: Vec<rltk::FontCharType>,
+    /// Sprites for entities, by name.
+    #[serde(default)]
+    entities: HashMap<String, rltk::FontCharType>,
+    /// Sprites for entities without a named sprite, by the character they show in ASCII.
+    #[serde(default)]
+    glyphs: HashMap<char, rltk::FontCharType>,
+}
+
+/// A loaded sprite tileset. Anything it has no sprite for is drawn as ASCII instead.
+pub struct Tileset {
+    pub tile_width: u32,
+    pub tile_height: u32,
+    tiles: HashMap<TileType, rltk::FontCharType>,
+    walls: Vec<rltk::FontCharType>,
+    entities: HashMap<String, rltk::FontCharType>,
+    glyphs: HashMap<rltk::FontCharType, rltk::FontCharType>,
+}
+
+impl Tileset {
+    /// Reads `tileset.json` from the config directory, or the sample tileset in `resources/` when
+    /// there isn't one, and hands its sprite sheet to the renderer. Has to happen before the window
+    /// is built. Copying the sample files into the config directory is the easiest way to start
+    /// a tileset of your own.
+    pub fn load() -> Option<Tileset> {
+        let path = config_dir().join(TILESET_FILE);
+        match load_versioned_json::<TilesetFile>(&path, TILESET_VERSION) {
+            Ok(Some(file)) => {
+                let image = match fs::read(config_dir().join(&file.image)) {
+                    Ok(image) => image,
+                    Err(e) => {
+                        rltk::console::log(format!(
+                            "Unable to read tileset image {}: {}",
+                            file.image, e
+                        ));
+                        return None;
+                    }
+                };
+                Tileset::from_file(file, image)
+            }
+            Ok(None) => {
+                let file = serde_json::from_str(SAMPLE_TILESET).expect("sample tileset is valid");
+                Tileset::from_file(file, SAMPLE_IMAGE.to_vec())
+            }
+            Err(e) => {
+                rltk::console::log(format!("Unable to read tileset: {}", e));
+                None
+            }
+        }
+    }
+
+    fn from_file(file: TilesetFile, image: Vec<u8>) -> Option<Tileset> {
+        if file.tile_width == 0 || file.tile_height == 0 {
+            rltk::console::log("Tileset tiles must be at least one pixel across");
+            return None;
+        }
+        if !file.walls.is_empty() && file.walls.len() != 16 {
+            rltk::console::log(format!(
+                "Tileset needs 16 wall sprites, found {}",
+                file.walls.len()
+            ));
+            return None;
+        }
+
+        // The renderer panics on images it can't decode, so only pass on the formats it reads.
+        if !image.starts_with(b"\x89PNG") && !image.starts_with(&[0xff, 0xd8]) {
+            rltk::console::log(format!("Tileset image {} is not a PNG or JPEG", file.image));
+            return None;
+        }
+        rltk::EMBED.lock().add_resource(
+            format!("resources/{}", SPRITE_FONT),
+            Box::leak(image.into_boxed_slice()),
+        );
+
+        Some(Tileset {
+            tile_width: file.tile_width,
+            tile_height: file.tile_height,
+            tiles: file.tiles,
+            walls: file.walls,
+            entities: file.entities,
+            glyphs: file
+                .glyphs
+                .into_iter()
+                .map(|(glyph, sprite)| (rltk::to_cp437(glyph), sprite))
+                .collect(),
+        })
+    }
+
+    /// The sprite for a map tile, with walls joined up to their neighbours.
+    pub fn tile_sprite(&self, map: &Map, idx: usize) -> Option<rltk::FontCharType> {
+        let tile = map.tiles[idx];
+        if tile == TileType::Wall && !self.walls.is_empty() {
+            let x = idx as i32 % map.width;
+            let y = idx as i32 / map.width;
+            if let Some(mask) = wall_mask(map, x, y) {
+                return Some(self.walls[mask as usize]);
+            }
+        }
+        self.tiles.get(&tile).copied()
+    }
+
+    /// Adds the sprite layers to a window that would otherwise only have `text_font`.
+    pub fn window_builder(
+        &self,
+        text_font: &str,
+        font_width: u32,
+        font_height: u32,
+        scale: u32,
+    ) -> rltk::RltkBuilder {
+        rltk::RltkBuilder::new()
+            .with_dimensions(80, 50)
+            .with_tile_dimensions(font_width * scale, font_height * scale)
+            .with_font(text_font, font_width, font_height)
+            .with_font(SPRITE_FONT, self.tile_width, self.tile_height)
+            .with_simple_console(80, 50, SPRITE_FONT)
+            .with_sparse_console_no_bg(80, 50, SPRITE_FONT)
+            .with_sparse_console(80, 50, text_font)
+    }
+
+    /// The sprite for an entity, by name first and then by its ASCII glyph.
+    pub fn entity_sprite(
+        &self,
+        name: Option<&str>,
+        glyph: rltk::FontCharType,
+    ) -> Option<rltk::FontCharType> {
+        name.and_then(|name| self.entities.get(name))
+            .or_else(|| self.glyphs.get(&glyph))
+            .copied()
+    }
+}
+
+/// Clears the sprite layers; the caller clears the text layer as usual.
+pub fn clear_sprites(ctx: &mut Rltk) {
+    for layer in [TILE_LAYER, ENTITY_LAYER].iter() {
+        ctx.set_active_console(*layer);
+        ctx.cls();
+    }
+    ctx.set_active_console(TEXT_LAYER);
+}
+
+/// Draws a sprite on one of the sprite layers, leaving the text layer active afterwards.
+pub fn draw_sprite(
+    ctx: &mut Rltk,
+    layer: usize,
+    x: i32,
+    y: i32,
+    tint: RGB,
+    sprite: rltk::FontCharType,
+) {
+    ctx.set_active_console(layer);
+    ctx.set(x, y, tint, RGB::named(rltk::BLACK), sprite);
+    ctx.set_active_console(TEXT_LAYER);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    #[test]
+    fn sample_tileset_covers_every_tile() {
+        let file: TilesetFile = serde_json::from_str(SAMPLE_TILESET).unwrap();
+        let all = [
+            TileType::Wall,
+            TileType::Floor,
+            TileType::StairsDown,
+            TileType::StairsUp,
+            TileType::ShallowWater,
+            TileType::DeepWater,
+            TileType::Lava,
+            TileType::Chasm,
+            TileType::Rubble,
+            TileType::Bridge,
+            TileType::Grass,
+            TileType::WoodFloor,
+            TileType::StoneFloor,
+            TileType::Gravel,
+        ];
+        for tile in all.iter() {
+            assert!(file.tiles.contains_key(tile), "no sprite for {:?}", tile);
+        }
+        assert_eq!(file.walls.len(), 16);
+
+        // The PNG header holds the sheet's size in pixels.
+        let width = u32::from_be_bytes(SAMPLE_IMAGE[16..20].try_into().unwrap());
+        let height = u32::from_be_bytes(SAMPLE_IMAGE[20..24].try_into().unwrap());
+        let sprites = (width / file.tile_width) * (height / file.tile_height);
+        let used = file.tiles.values().chain(&file.walls).chain(file.entities.values());
+        assert!(used.into_iter().all(|sprite| (*sprite as u32) < sprites));
+    }
+}
@@ -0,0 +1,459 @@
+use crate::{
+    BlocksTile, CombatStats, Door, Hidden, Item, Locked, Map, Monster, PlayerCommand, Position,
+    TileType,
+};
+use rltk::{Algorithm2D, BaseMap, DijkstraMap, Point};
+use specs::prelude::*;
+use std::collections::HashSet;
+
+/// What an automatic multi-turn action is trying to do.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum TravelGoal {
+    Explore,
+    StairsDown,
+    Point(Point),
+    /// Keep going in a direction, following corridors, until something interesting turns up.
+    Run {
+        dx: i32,
+        dy: i32,
+    },
+    /// Wait until fully healed.
+    Rest,
+}
+
+/// Why an automatic action ended.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum TravelStop {
+    Arrived,
+    Rested,
+    /// A run reached a junction, a door, an item, a hazard or the end of a corridor.
+    EndOfRun,
+    FullyExplored,
+    UnknownStairs,
+    NoPath,
+    MonsterInView,
+    ItemSpotted,
+    Hurt,
+    Interrupted,
+}
+
+impl TravelStop {
+    /// What to tell the player, if anything. Runs end too often to mention.
+    pub fn message(self) -> Option<&'static str> {
+        match self {
+            TravelStop::Arrived => Some("You arrive."),
+            TravelStop::Rested => Some("You feel rested."),
+            TravelStop::EndOfRun => None,
+            TravelStop::FullyExplored => Some("There is nothing left to explore here."),
+            TravelStop::UnknownStairs => Some("You don't know where the way down is."),
+            TravelStop::NoPath => Some("You can't find a way there."),
+            TravelStop::MonsterInView => Some("There is a monster in view."),
+            TravelStop::ItemSpotted => Some("You spot something and stop."),
+            TravelStop::Hurt => Some("You are hurt and stop."),
+            TravelStop::Interrupted => Some("You stop."),
+        }
+    }
+}
+
+/// The map as the player knows it: revealed, walkable tiles that aren't occupied by anything
+/// other than a door that would open when walked into. Tiles that hurt to enter are avoided unless
+/// the player clicked on one. Blockers are read from their positions rather than `Map::blocked`,
+/// which is only refreshed at the start of a turn.
+struct KnownMap<'a> {
+    map: &'a Map,
+    passable: Vec<bool>,
+}
+
+impl<'a> KnownMap<'a> {
+    fn new(ecs: &World, map: &'a Map, goal: TravelGoal) -> KnownMap<'a> {
+        let player_entity = ecs.fetch::<Entity>();
+        let entities = ecs.entities();
+        let blockers = ecs.read_storage::<BlocksTile>();
+        let doors = ecs.read_storage::<Door>();
+        let locked = ecs.read_storage::<Locked>();
+        let positions = ecs.read_storage::<Position>();
+
+        let clicked = match goal {
+            TravelGoal::Point(point) => Some(map.xy_idx(point.x, point.y)),
+            _ => None,
+        };
+        let mut passable: Vec<bool> = (0..map.tiles.len())
+            .map(|idx| {
+                let tile = map.tiles[idx];
+                map.revealed_tiles[idx]
+                    && tile.is_walkable()
+                    && (tile.entry_damage() == 0 || clicked == Some(idx))
+            })
+            .collect();
+        for (entity, _blocker, pos) in (&entities, &blockers, &positions).join() {
+            let openable_door = doors.get(entity).is_some() && locked.get(entity).is_none();
+            if entity != *player_entity && !openable_door {
+                passable[map.xy_idx(pos.x, pos.y)] = false;
+            }
+        }
+
+        KnownMap { map, passable }
+    }
+}
+
+impl<'a> BaseMap for KnownMap<'a> {
+    fn get_available_exits(&self, idx: usize) -> rltk::SmallVec<[(usize, f32); 10]> {
+        let mut exits = rltk::SmallVec::new();
+        let x = idx as i32 % self.map.width;
+        let y = idx as i32 / self.map.width;
+
+        for (dx, dy) in NEIGHBOURS.iter() {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || nx >= self.map.width || ny < 0 || ny >= self.map.height {
+                continue;
+            }
+            let next = self.map.xy_idx(nx, ny);
+            if self.passable[next] {
+                let base = if *dx != 0 && *dy != 0 { 1.45 } else { 1.0 };
+                exits.push((next, base * self.map.tiles[next].movement_cost()));
+            }
+        }
+
+        exits
+    }
+}
+
+impl<'a> Algorithm2D for KnownMap<'a> {
+    fn dimensions(&self) -> Point {
+        Point::new(self.map.width, self.map.height)
+    }
+}
+
+const NEIGHBOURS: [(i32, i32); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// A multi-turn action. Each step is an ordinary move or wait command, so replays only ever see
+/// the individual commands.
+pub struct Travel {
+    goal: TravelGoal,
+    hp: i32,
+    seen_items: HashSet<Entity>,
+    last_position: Option<Point>,
+    opening_door: bool,
+    run: RunState,
+}
+
+/// How a run is following the map: along a corridor, turning with it, or straight across an
+/// open area until the walls beside it change.
+#[derive(Copy, Clone)]
+enum RunState {
+    Starting,
+    Corridor,
+    Open { neighbours: usize },
+}
+
+impl Travel {
+    /// Refuses to set off with a monster in view. Items already in sight don't stop the walk.
+    pub fn start(ecs: &World, goal: TravelGoal) -> Result<Travel, TravelStop> {
+        if monster_in_view(ecs) {
+            return Err(TravelStop::MonsterInView);
+        }
+
+        Ok(Travel {
+            goal,
+            hp: player_hp(ecs).0,
+            seen_items: visible_items(ecs),
+            last_position: None,
+            opening_door: false,
+            run: RunState::Starting,
+        })
+    }
+
+    /// The next command to perform, or the reason to stop.
+    pub fn next_step(&mut self, ecs: &World) -> Result<PlayerCommand, TravelStop> {
+        if monster_in_view(ecs) {
+            return Err(TravelStop::MonsterInView);
+        }
+
+        let (hp, max_hp) = player_hp(ecs);
+        if hp < self.hp {
+            return Err(TravelStop::Hurt);
+        }
+        self.hp = hp;
+
+        if self.goal == TravelGoal::Rest {
+            return if hp >= max_hp {
+                Err(TravelStop::Rested)
+            } else {
+                Ok(PlayerCommand::Wait)
+            };
+        }
+
+        let items = visible_items(ecs);
+        if items.iter().any(|item| !self.seen_items.contains(item)) {
+            return Err(TravelStop::ItemSpotted);
+        }
+        self.seen_items.extend(items);
+
+        let map = ecs.fetch::<Map>();
+        let player_pos = *ecs.fetch::<Point>();
+        let player_idx = map.xy_idx(player_pos.x, player_pos.y);
+        let known = KnownMap::new(ecs, &map, self.goal);
+
+        // Bumping a door open takes a turn without moving; anything else that keeps the player
+        // in place would repeat forever.
+        if self.last_position == Some(player_pos) && !self.opening_door {
+            return Err(match self.goal {
+                TravelGoal::Run { .. } => TravelStop::EndOfRun,
+                _ => TravelStop::NoPath,
+            });
+        }
+
+        if let TravelGoal::Run { dx, dy } = self.goal {
+            let (dx, dy) = self.run_step(ecs, &map, &known, player_pos, (dx, dy))?;
+            self.goal = TravelGoal::Run { dx, dy };
+            self.opening_door =
+                is_closed_door(ecs, &map, map.xy_idx(player_pos.x + dx, player_pos.y + dy));
+            self.last_position = Some(player_pos);
+            return Ok(PlayerCommand::Move { dx, dy });
+        }
+
+        let targets = self.targets(&map, &known)?;
+        if targets.contains(&player_idx) {
+            return Err(TravelStop::Arrived);
+        }
+
+        // `DijkstraMap::build` leaves the starting tiles themselves unscored.
+        let mut dijkstra = DijkstraMap::new_empty(map.width, map.height, 2000.0);
+        for target in targets.iter() {
+            dijkstra.map[*target] = 0.0;
+        }
+        DijkstraMap::build(&mut dijkstra, &targets, &known);
+        let next = match DijkstraMap::find_lowest_exit(&dijkstra, player_idx, &known) {
+            Some(next) if dijkstra.map[next] < dijkstra.map[player_idx] => next,
+            _ => return Err(TravelStop::NoPath),
+        };
+
+        self.opening_door = is_closed_door(ecs, &map, next);
+        self.last_position = Some(player_pos);
+
+        let next_x = next as i32 % map.width;
+        let next_y = next as i32 / map.width;
+        Ok(PlayerCommand::Move {
+            dx: next_x - player_pos.x,
+            dy: next_y - player_pos.y,
+        })
+    }
+
+    /// Picks the direction of the next running step.
+    fn run_step(
+        &mut self,
+        ecs: &World,
+        map: &Map,
+        known: &KnownMap,
+        pos: Point,
+        (dx, dy): (i32, i32),
+    ) -> Result<(i32, i32), TravelStop> {
+        let open = |x: i32, y: i32| {
+            x >= 0 && x < map.width && y >= 0 && y < map.height && known.passable[map.xy_idx(x, y)]
+        };
+        let ahead: Vec<(i32, i32)> = NEIGHBOURS
+            .iter()
+            .copied()
+            .filter(|(nx, ny)| nx * dx + ny * dy >= 0 && open(pos.x + nx, pos.y + ny))
+            .collect();
+        let neighbours = NEIGHBOURS
+            .iter()
+            .filter(|(nx, ny)| open(pos.x + nx, pos.y + ny))
+            .count();
+
+        if let RunState::Starting = self.run {
+            if !open(pos.x + dx, pos.y + dy) {
+                return Err(TravelStop::EndOfRun);
+            }
+            self.run = if ahead.len() <= 2 && clusters(&ahead) == 1 {
+                RunState::Corridor
+            } else {
+                RunState::Open { neighbours }
+            };
+            return Ok((dx, dy));
+        }
+
+        if item_or_door_nearby(ecs, map, pos) || hazard_nearby(map, pos) {
+            return Err(TravelStop::EndOfRun);
+        }
+
+        match self.run {
+            RunState::Open { neighbours: before } => {
+                if neighbours != before || !open(pos.x + dx, pos.y + dy) {
+                    return Err(TravelStop::EndOfRun);
+                }
+                self.run = RunState::Open { neighbours };
+                Ok((dx, dy))
+            }
+            _ => {
+                if ahead.is_empty() || ahead.len() > 2 || clusters(&ahead) != 1 {
+                    return Err(TravelStop::EndOfRun);
+                }
+                if ahead.contains(&(dx, dy)) {
+                    return Ok((dx, dy));
+                }
+                let turn = ahead
+                    .iter()
+                    .copied()
+                    .find(|(nx, ny)| nx * ny == 0)
+                    .unwrap_or(ahead[0]);
+                Ok(turn)
+            }
+        }
+    }
+
+    fn targets(&self, map: &Map, known: &KnownMap) -> Result<Vec<usize>, TravelStop> {
+        let targets: Vec<usize> = match self.goal {
+            TravelGoal::Explore => (0..map.tiles.len())
+                .filter(|idx| known.passable[*idx] && borders_unrevealed(map, *idx))
+                .collect(),
+            TravelGoal::StairsDown => (0..map.tiles.len())
+                .filter(|idx| map.revealed_tiles[*idx] && map.tiles[*idx] == TileType::StairsDown)
+                .collect(),
+            TravelGoal::Point(point) => {
+                let idx = map.xy_idx(point.x, point.y);
+                if known.passable[idx] {
+                    vec![idx]
+                } else {
+                    Vec::new()
+                }
+            }
+            TravelGoal::Run { .. } | TravelGoal::Rest => Vec::new(),
+        };
+
+        if targets.is_empty() {
+            return Err(match self.goal {
+                TravelGoal::Explore => TravelStop::FullyExplored,
+                TravelGoal::StairsDown => TravelStop::UnknownStairs,
+                TravelGoal::Point(_) | TravelGoal::Run { .. } | TravelGoal::Rest => {
+                    TravelStop::NoPath
+                }
+            });
+        }
+        Ok(targets)
+    }
+}
+
+fn is_closed_door(ecs: &World, map: &Map, idx: usize) -> bool {
+    let doors = ecs.read_storage::<Door>();
+    map.tile_content[idx]
+        .iter()
+        .any(|entity| doors.get(*entity).is_some_and(|door| !door.open))
+}
+
+/// How many separate groups the given neighbouring offsets form. One group means the way on is
+/// unambiguous; more means a junction.
+fn clusters(offsets: &[(i32, i32)]) -> usize {
+    let mut groups = 0;
+    let mut seen = vec![false; offsets.len()];
+    for start in 0..offsets.len() {
+        if seen[start] {
+            continue;
+        }
+        groups += 1;
+        let mut stack = vec![start];
+        seen[start] = true;
+        while let Some(i) = stack.pop() {
+            for j in 0..offsets.len() {
+                let adjacent = (offsets[i].0 - offsets[j].0).abs() <= 1
+                    && (offsets[i].1 - offsets[j].1).abs() <= 1;
+                if !seen[j] && adjacent {
+                    seen[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+    }
+    groups
+}
+
+/// Runs stop next to doors and on top of items.
+fn item_or_door_nearby(ecs: &World, map: &Map, pos: Point) -> bool {
+    let items = ecs.read_storage::<Item>();
+    let doors = ecs.read_storage::<Door>();
+    let here = map.xy_idx(pos.x, pos.y);
+    if map.tile_content[here]
+        .iter()
+        .any(|e| items.get(*e).is_some())
+    {
+        return true;
+    }
+
+    NEIGHBOURS.iter().any(|(dx, dy)| {
+        let (x, y) = (pos.x + dx, pos.y + dy);
+        x >= 0
+            && x < map.width
+            && y >= 0
+            && y < map.height
+            && map.tile_content[map.xy_idx(x, y)]
+                .iter()
+                .any(|e| doors.get(*e).is_some())
+    })
+}
+
+/// Runs also stop next to any known tile that would hurt to step on.
+fn hazard_nearby(map: &Map, pos: Point) -> bool {
+    NEIGHBOURS.iter().any(|(dx, dy)| {
+        let (x, y) = (pos.x + dx, pos.y + dy);
+        x >= 0
+            && x < map.width
+            && y >= 0
+            && y < map.height
+            && map.revealed_tiles[map.xy_idx(x, y)]
+            && map.tiles[map.xy_idx(x, y)].entry_damage() > 0
+    })
+}
+
+fn borders_unrevealed(map: &Map, idx: usize) -> bool {
+    let x = idx as i32 % map.width;
+    let y = idx as i32 / map.width;
+    NEIGHBOURS.iter().any(|(dx, dy)| {
+        let (nx, ny) = (x + dx, y + dy);
+        nx >= 0
+            && nx < map.width
+            && ny >= 0
+            && ny < map.height
+            && !map.revealed_tiles[map.xy_idx(nx, ny)]
+    })
+}
+
+fn player_hp(ecs: &World) -> (i32, i32) {
+    let player_entity = ecs.fetch::<Entity>();
+    ecs.read_storage::<CombatStats>()
+        .get(*player_entity)
+        .map_or((0, 0), |stats| (stats.hp, stats.max_hp))
+}
+
+fn monster_in_view(ecs: &World) -> bool {
+    let map = ecs.fetch::<Map>();
+    let monsters = ecs.read_storage::<Monster>();
+    let positions = ecs.read_storage::<Position>();
+    let hidden = ecs.read_storage::<Hidden>();
+
+    (&monsters, &positions, !&hidden)
+        .join()
+        .any(|(_monster, pos, _hidden)| map.visible_tiles[map.xy_idx(pos.x, pos.y)])
+}
+
+fn visible_items(ecs: &World) -> HashSet<Entity> {
+    let map = ecs.fetch::<Map>();
+    let entities = ecs.entities();
+    let items = ecs.read_storage::<Item>();
+    let positions = ecs.read_storage::<Position>();
+    let hidden = ecs.read_storage::<Hidden>();
+
+    (&entities, &items, &positions, !&hidden)
+        .join()
+        .filter(|(_entity, _item, pos, _hidden)| map.visible_tiles[map.xy_idx(pos.x, pos.y)])
+        .map(|(entity, _item, _pos, _hidden)| entity)
+        .collect()
+}
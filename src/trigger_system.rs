@@ -1,5 +1,5 @@
 use crate::{
-    EntityMoved, EntryTrigger, GameLog, Hidden, InflictsDamage, Map, Name, ParticleBuilder,
+    EntityMoved, EntryTrigger, GameLog, Hidden, LogCategory, InflictsDamage, Map, Name, ParticleBuilder,
     Position, SingleActivation, SufferDamage,
 };
 use rltk::RGB;
@@ -43,13 +43,38 @@ impl<'a> System<'a> for TriggerSystem {
         let mut remove_activated_items: Vec<Entity> = Vec::new();
         for (entity, mut _entity_moved, pos) in (&entities, &entity_moved, &position).join() {
             let i = map.xy_idx(pos.x, pos.y);
+
+            let terrain_damage = map.tiles[i].entry_damage();
+            if terrain_damage > 0 {
+                if let Some(name) = names.get(entity) {
+                    log.entry(LogCategory::Combat).name(&name.name).text(" is burned!").log();
+                }
+
+                particle_builder.request(
+                    pos.x,
+                    pos.y,
+                    RGB::named(rltk::ORANGE),
+                    RGB::named(rltk::BLACK),
+                    rltk::to_cp437('▲'),
+                    200.0,
+                );
+
+                SufferDamage::new_damage(
+                    &mut suffer_damage,
+                    entity,
+                    terrain_damage,
+                    "burning terrain",
+                    false,
+                );
+            }
+
             for entity_id in map.tile_content[i].iter() {
                 if entity != *entity_id {
                     let trigger_option = entry_triggers.get(*entity_id);
                     if trigger_option.is_some() {
                         let name = names.get(*entity_id);
                         if let Some(name) = name {
-                            log.entries.push(format!("{} triggers!", name.name));
+                            log.entry(LogCategory::System).item(&name.name).text(" triggers!").log();
                         }
 
                         let damage = inflicts_damage.get(*entity_id);
@@ -63,7 +88,14 @@ impl<'a> System<'a> for TriggerSystem {
                                 200.0,
                             );
 
-                            SufferDamage::new_damage(&mut suffer_damage, entity, damage.damage);
+                            let source = name.map(|n| n.name.as_str()).unwrap_or("a trap");
+                            SufferDamage::new_damage(
+                                &mut suffer_damage,
+                                entity,
+                                damage.damage,
+                                source,
+                                false,
+                            );
                         }
 
                         let single_act = single_activations.get(*entity_id);
@@ -1,5 +1,5 @@
 use super::{Map, Player, Position, Viewshed};
-use crate::{GameLog, Hidden, Name};
+use crate::{GameLog, Hidden, LogCategory, Name};
 use rltk::{field_of_view, Point, RandomNumberGenerator};
 use specs::prelude::*;
 
@@ -33,6 +33,7 @@ impl<'a> System<'a> for VisibilitySystem {
                 viewshed
                     .visible_tiles
                     .retain(|p| p.x >= 0 && p.x < map.width && p.y >= 0 && p.y < map.height);
+                viewshed.visible_tiles.sort_by_key(|p| (p.y, p.x));
 
                 let p: Option<&Player> = player.get(ent);
                 if p.is_some() {
@@ -49,7 +50,7 @@ impl<'a> System<'a> for VisibilitySystem {
                             if hidden_item.is_some() && rng.roll_dice(1, 24) == 1 {
                                 let name = names.get(*t);
                                 if let Some(name) = name {
-                                    log.entries.push(format!("You spotted a {}", name.name))
+                                    log.entry(LogCategory::System).text("You spotted a ").item(&name.name).log()
                                 }
                                 hidden.remove(*t);
                             }